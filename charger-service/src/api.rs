@@ -1,16 +1,96 @@
 use anyhow::Result;
+use std::fmt;
+
+pub use crate::runtime::offchain::{
+    ChargerError, ConnectorId, ContractAuthorization, MeterSample, PresentedToken,
+};
 
 #[derive(Debug, PartialEq)]
 pub enum ChargeStatus {
     NotFound,
-    Active,
-    Ended { kwh: u64 },
+    Active {
+        meter: Option<MeterSample>,
+    },
+    /// The session is running, but the vehicle does not take energy
+    SuspendedEV {
+        meter: Option<MeterSample>,
+    },
+    /// The session is running, but the charger does not offer energy
+    SuspendedEVSE {
+        meter: Option<MeterSample>,
+    },
+    /// The connector is faulted, `meter` being the last reading of its session if any
+    Faulted {
+        error_code: String,
+        meter: Option<MeterSample>,
+    },
+    /// The connector is out of service, or the charger cannot be reached
+    Unavailable,
+    /// `signed_data` are the datasets signed by the meter (eg. `OCMF|{...}|{...}`) for the
+    /// start and end readings of the session, if the meter signs its readings
+    Ended {
+        kwh: u64,
+        signed_data: Vec<String>,
+    },
 }
 
 pub trait ChargerApi {
-    /// Start a new charge session
-    fn start_new_charge(&mut self) -> Result<()>;
+    /// Start a new charge session on the given connector
+    fn start_new_charge(&mut self, connector: ConnectorId) -> Result<()>;
+
+    /// Stop the charge session running on the given connector. The session is not
+    /// ended right away: its status turns to `Ended` once the charger has stopped
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<()>;
+
+    /// Get charge session status of the given connector, with its last meter reading if active
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> Result<ChargeStatus>;
+
+    /// Cap the charging power of the given connector to `watts`, connector 0 capping the
+    /// whole charger. The limit replaces the previous one, and lapses at `valid_until`
+    /// (unix time in milliseconds) if set. `Ok` means the charger has applied the limit
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        watts: u32,
+        valid_until: Option<u64>,
+    ) -> Result<()>;
+
+    /// Tokens presented by the drivers (RFID cards, ISO 15118 contracts) since the last call,
+    /// in the order they were presented. The charger does not check them: sessions are
+    /// requested on-chain on behalf of their owners
+    fn take_presented_tokens(&mut self) -> Result<Vec<PresentedToken>>;
 
-    /// Get charge session status
-    fn get_current_charge_status(&mut self) -> Result<ChargeStatus>;
+    /// Vehicles which presented their ISO 15118 contract certificate (Plug & Charge) since the
+    /// last call. The certificates are verified on-chain, where the sessions are requested
+    fn take_contract_authorizations(&mut self) -> Result<Vec<ContractAuthorization>>;
 }
+
+impl ChargerError {
+    /// Error of this kind, described by `message`. The kind is reported to the runtime,
+    /// the message only logged
+    pub fn with_message<M>(self, message: M) -> anyhow::Error
+    where
+        M: fmt::Display + Send + Sync + 'static,
+    {
+        anyhow::Error::new(self).context(message)
+    }
+}
+
+impl fmt::Display for ChargerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChargerError::Busy => write!(f, "connector busy"),
+            ChargerError::NotPlugged => write!(f, "no vehicle plugged in"),
+            ChargerError::Faulted => write!(f, "connector faulted"),
+            ChargerError::Unreachable => write!(f, "charger unreachable"),
+            ChargerError::Rejected => write!(f, "rejected by the charger"),
+            ChargerError::NoSession => write!(f, "no session running"),
+            ChargerError::UnknownConnector => write!(f, "unknown connector"),
+            ChargerError::Other(description) => {
+                write!(f, "{}", String::from_utf8_lossy(description))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChargerError {}
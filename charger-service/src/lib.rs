@@ -3,7 +3,16 @@
 pub mod runtime;
 
 #[cfg(feature = "std")]
-mod api;
+pub mod api;
 
 #[cfg(feature = "std")]
 pub mod mock;
+
+#[cfg(feature = "std")]
+pub mod modbus;
+
+#[cfg(feature = "std")]
+pub mod ocpp;
+
+#[cfg(feature = "std")]
+pub mod store;
@@ -1,64 +1,474 @@
-use anyhow::{anyhow, Result};
-use log::{debug, info};
-use rand::prelude::*;
+use anyhow::Result;
+use log::{debug, info, warn};
+use rand::{prelude::*, rngs::StdRng};
+use serde::{Deserialize, Serialize};
 use std::{
-    ops::Add,
-    time::{Duration, Instant},
+    collections::{HashMap, VecDeque},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::api::*;
+use crate::store::SessionStore;
 
+/// Mains voltage of the simulated charger, in mV
+const VOLTAGE_MV: u64 = 230_000;
+
+/// Bounds of the random energy of the sessions which are not scripted, in kWh
+const MIN_RANDOM_KWH: u64 = 1;
+const MAX_RANDOM_KWH: u64 = 60;
+
+/// Fault injected in a scripted session
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockFault {
+    /// The charger refuses to start the session
+    RefuseStart,
+    /// No vehicle is plugged in when the session is started
+    NotPlugged,
+    /// The connector faults with `error_code` once `after` has elapsed in the session.
+    /// It stays faulted until `MockCharger::repair` is called
+    FaultAfter { after: Duration, error_code: String },
+    /// The session ends without a final meter reading, and is then not found anymore
+    NoFinalReading,
+}
+
+/// Behaviour of one session of the mock charger
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptedSession {
+    pub duration: Duration,
+    pub kwh: u64,
+    pub fault: Option<MockFault>,
+    /// Datasets signed by the meter, reported with the end of the session
+    pub signed_data: Vec<String>,
+}
+
+impl ScriptedSession {
+    pub fn new(duration: Duration, kwh: u64) -> ScriptedSession {
+        ScriptedSession {
+            duration,
+            kwh,
+            fault: None,
+            signed_data: Vec::new(),
+        }
+    }
+
+    pub fn with_fault(mut self, fault: MockFault) -> ScriptedSession {
+        self.fault = Some(fault);
+        self
+    }
+
+    pub fn with_signed_data(mut self, signed_data: Vec<String>) -> ScriptedSession {
+        self.signed_data = signed_data;
+        self
+    }
+}
+
+/// Time source of the mock charger, as a duration since the unix epoch
+enum Clock {
+    System,
+    /// Time only moves with `MockCharger::advance`, starting at the epoch
+    Manual(Duration),
+}
+
+impl Clock {
+    fn now(&self) -> Duration {
+        match self {
+            Clock::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            Clock::Manual(now) => *now,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MockSession {
+    started_at: Duration,
+    end_at: Duration,
+    /// Energy delivered when the session ends
+    kwh: u64,
+    /// Scripted faults are not persisted
+    #[serde(skip)]
+    fault: Option<MockFault>,
+    #[serde(default)]
+    signed_data: Vec<String>,
+}
+
+impl MockSession {
+    /// Time and error code of the injected mid-session fault, if it happens before the end
+    fn fault(&self) -> Option<(Duration, &String)> {
+        match &self.fault {
+            Some(MockFault::FaultAfter { after, error_code })
+                if *after < self.end_at.saturating_sub(self.started_at) =>
+            {
+                Some((self.started_at + *after, error_code))
+            }
+            _ => None,
+        }
+    }
+
+    /// Meter reading at `now`, the energy growing linearly over the session until its end or fault
+    fn sample(&self, now: Duration) -> MeterSample {
+        // The clock may be behind the start of a persisted session (eg. a manual clock, or a
+        // system clock stepped back): no energy is delivered yet
+        let duration = self.end_at.saturating_sub(self.started_at).as_millis() as u64;
+        let stopped_at = self.fault().map_or(self.end_at, |(at, _)| at);
+        let elapsed = now
+            .min(stopped_at)
+            .saturating_sub(self.started_at)
+            .as_millis() as u64;
+        let power_w = (self.kwh * 3_600_000 * 1000 / duration.max(1)).min(u32::MAX as u64);
+        MeterSample {
+            energy_wh: self.kwh * 1000 * elapsed / duration.max(1),
+            power_w: power_w as u32,
+            voltage_mv: VOLTAGE_MV as u32,
+            current_ma: (power_w * 1_000_000 / VOLTAGE_MV).min(u32::MAX as u64) as u32,
+            timestamp: now.as_millis() as u64,
+        }
+    }
+}
+
+/// Power limit set on a connector, or on the whole charger (connector 0)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PowerLimit {
+    watts: u32,
+    /// Unix time in milliseconds
+    valid_until: Option<u64>,
+}
+
+/// A simulated charger. Sessions last a random duration and deliver a random energy,
+/// unless they are scripted with `MockChargerBuilder`
 pub struct MockCharger {
-    current_session_end_timestamp: Option<Instant>,
+    /// Current session of each connector
+    current_sessions: HashMap<ConnectorId, MockSession>,
+    /// Error code of the faulted connectors
+    faults: HashMap<ConnectorId, String>,
+    power_limits: HashMap<ConnectorId, PowerLimit>,
+    /// Tokens presented by the drivers, until they are taken by the runtime
+    presented_tokens: Vec<PresentedToken>,
+    /// Contracts presented by the vehicles, until they are taken by the runtime
+    contract_authorizations: Vec<ContractAuthorization>,
+    /// Behaviour of the next sessions, random sessions being used once it is exhausted
+    script: VecDeque<ScriptedSession>,
+    duration: Option<Duration>,
+    kwh: Option<u64>,
     min_time: u64,
     max_time: u64,
+    rng: StdRng,
+    clock: Clock,
+    /// Persisted sessions, resumed when the charger is built again
+    store: SessionStore,
 }
 
 impl MockCharger {
     pub fn new() -> MockCharger {
-        MockCharger {
-            current_session_end_timestamp: None,
+        MockCharger::builder().build()
+    }
+
+    pub fn builder() -> MockChargerBuilder {
+        MockChargerBuilder {
+            seed: None,
+            duration: None,
+            kwh: None,
             min_time: 20,
             max_time: 60,
+            script: VecDeque::new(),
+            manual_clock: false,
+            store: SessionStore::in_memory(),
+        }
+    }
+
+    /// Move the clock forward, when built with a manual clock
+    pub fn advance(&mut self, by: Duration) {
+        match &mut self.clock {
+            Clock::Manual(now) => *now += by,
+            Clock::System => warn!("Cannot advance the system clock of the mock charger"),
+        }
+    }
+
+    /// Clear the fault of `connector`
+    pub fn repair(&mut self, connector: ConnectorId) {
+        self.faults.remove(&connector);
+    }
+
+    /// Power limit in force on `connector`: the lowest of its own limit and of the limit
+    /// of the whole charger, the lapsed limits being ignored
+    pub fn power_limit(&self, connector: ConnectorId) -> Option<u32> {
+        let now = self.clock.now().as_millis() as u64;
+        [connector, 0]
+            .iter()
+            .filter_map(|connector| self.power_limits.get(connector))
+            .filter(|limit| limit.valid_until.map_or(true, |until| now < until))
+            .map(|limit| limit.watts)
+            .min()
+    }
+
+    /// Simulate a driver presenting `token` (eg. the UID of an RFID card) on `connector`
+    pub fn present_token(&mut self, connector: ConnectorId, token: &str) {
+        info!("Token {} presented on connector {}", token, connector);
+        self.presented_tokens.push(PresentedToken {
+            connector,
+            token: token.as_bytes().to_vec(),
+        });
+    }
+
+    /// Simulate a vehicle presenting its contract `emaid` with its certificate chain (DER
+    /// encoded, the contract certificate first) on `connector`
+    pub fn present_contract(
+        &mut self,
+        connector: ConnectorId,
+        emaid: &str,
+        certificate_chain: Vec<Vec<u8>>,
+    ) {
+        info!("Contract {} presented on connector {}", emaid, connector);
+        self.contract_authorizations.push(ContractAuthorization {
+            connector,
+            emaid: emaid.as_bytes().to_vec(),
+            certificate_chain,
+        });
+    }
+
+    fn next_session(&mut self) -> ScriptedSession {
+        if let Some(session) = self.script.pop_front() {
+            return session;
+        }
+        let duration = match self.duration {
+            Some(duration) => duration,
+            None => Duration::from_secs(self.rng.gen_range(self.min_time..=self.max_time)),
+        };
+        let kwh = match self.kwh {
+            Some(kwh) => kwh,
+            None => self.rng.gen_range(MIN_RANDOM_KWH..=MAX_RANDOM_KWH),
+        };
+        ScriptedSession::new(duration, kwh)
+    }
+}
+
+pub struct MockChargerBuilder {
+    seed: Option<u64>,
+    duration: Option<Duration>,
+    kwh: Option<u64>,
+    min_time: u64,
+    max_time: u64,
+    script: VecDeque<ScriptedSession>,
+    manual_clock: bool,
+    store: SessionStore,
+}
+
+impl MockChargerBuilder {
+    /// Seed of the random durations and energies
+    pub fn seed(mut self, seed: u64) -> MockChargerBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Bounds of the random session durations, in seconds, `min_time` at most `max_time`
+    pub fn random_duration(mut self, min_time: u64, max_time: u64) -> MockChargerBuilder {
+        self.min_time = min_time;
+        self.max_time = max_time;
+        self
+    }
+
+    /// Fixed duration of the sessions which are not scripted
+    pub fn duration(mut self, duration: Duration) -> MockChargerBuilder {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Fixed energy of the sessions which are not scripted
+    pub fn kwh(mut self, kwh: u64) -> MockChargerBuilder {
+        self.kwh = Some(kwh);
+        self
+    }
+
+    /// Script the next session, whatever its connector
+    pub fn session(mut self, session: ScriptedSession) -> MockChargerBuilder {
+        self.script.push_back(session);
+        self
+    }
+
+    /// Only move the clock with `MockCharger::advance`
+    pub fn manual_clock(mut self) -> MockChargerBuilder {
+        self.manual_clock = true;
+        self
+    }
+
+    /// Persist the sessions in `store`, and resume the sessions saved there
+    pub fn store(mut self, store: SessionStore) -> MockChargerBuilder {
+        self.store = store;
+        self
+    }
+
+    pub fn build(self) -> MockCharger {
+        MockCharger {
+            current_sessions: self.store.load(),
+            faults: HashMap::new(),
+            power_limits: HashMap::new(),
+            presented_tokens: Vec::new(),
+            contract_authorizations: Vec::new(),
+            script: self.script,
+            duration: self.duration,
+            kwh: self.kwh,
+            min_time: self.min_time,
+            max_time: self.max_time,
+            rng: match self.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            clock: if self.manual_clock {
+                Clock::Manual(Duration::default())
+            } else {
+                Clock::System
+            },
+            store: self.store,
         }
     }
 }
 
 impl ChargerApi for MockCharger {
-    fn start_new_charge(&mut self) -> Result<()> {
-        if self.current_session_end_timestamp.is_some() {
-            return Err(anyhow!("This charger already has an active session"));
+    fn start_new_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        if self.current_sessions.contains_key(&connector) {
+            return Err(ChargerError::Busy.with_message(format!(
+                "Connector {} already has an active session",
+                connector
+            )));
         }
-        let mut rng = rand::thread_rng();
-        let end_at = std::time::Instant::now().add(Duration::from_secs(
-            rng.gen_range(self.min_time..self.max_time),
-        ));
-        debug!("New charge session started, end at {:?}", end_at);
-        self.current_session_end_timestamp = Some(end_at);
+        if let Some(error_code) = self.faults.get(&connector) {
+            return Err(ChargerError::Faulted.with_message(format!(
+                "Connector {} is faulted: {}",
+                connector, error_code
+            )));
+        }
+        let scripted = self.next_session();
+        match scripted.fault {
+            Some(MockFault::RefuseStart) => {
+                return Err(ChargerError::Rejected.with_message(format!(
+                    "Charger refused to start on connector {}",
+                    connector
+                )));
+            }
+            Some(MockFault::NotPlugged) => {
+                return Err(ChargerError::NotPlugged
+                    .with_message(format!("No vehicle plugged in connector {}", connector)));
+            }
+            _ => {}
+        }
+        let started_at = self.clock.now();
+        let end_at = started_at + scripted.duration;
+        debug!(
+            "New charge session started on connector {}, end at {:?}",
+            connector, end_at
+        );
+        self.current_sessions.insert(
+            connector,
+            MockSession {
+                started_at,
+                end_at,
+                kwh: scripted.kwh,
+                fault: scripted.fault,
+                signed_data: scripted.signed_data,
+            },
+        );
+        self.store.save_or_warn(&self.current_sessions);
         Ok(())
     }
 
-    fn get_current_charge_status(&mut self) -> Result<ChargeStatus> {
-        debug!("Get charge status");
-        let status = match self.current_session_end_timestamp {
-            None => ChargeStatus::NotFound,
-            Some(end_at) if Instant::now() > end_at => {
-                self.current_session_end_timestamp = None;
-                let mut rng = rand::thread_rng();
-                let kwh = rng.gen_range(100..5000);
-                info!("Charge is ended, kwh: {}", kwh);
-                ChargeStatus::Ended { kwh }
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        let now = self.clock.now();
+        match self.current_sessions.get_mut(&connector) {
+            None => Err(ChargerError::NoSession
+                .with_message(format!("No active session on connector {}", connector))),
+            Some(session) => {
+                // The session ends now, with the energy delivered so far
+                if now < session.end_at {
+                    session.kwh = session.sample(now).energy_wh / 1000;
+                    session.end_at = now;
+                }
+                debug!("Charge session stopped on connector {}", connector);
+                self.store.save_or_warn(&self.current_sessions);
+                Ok(())
+            }
+        }
+    }
+
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> Result<ChargeStatus> {
+        debug!("Get charge status of connector {}", connector);
+        let now = self.clock.now();
+        let sessions = self.current_sessions.len();
+        let status = match self.current_sessions.get(&connector) {
+            None => match self.faults.get(&connector) {
+                Some(error_code) => ChargeStatus::Faulted {
+                    error_code: error_code.clone(),
+                    meter: None,
+                },
+                None => ChargeStatus::NotFound,
+            },
+            Some(session) if session.fault().map_or(false, |(at, _)| now >= at) => {
+                // The fault ends the session, the connector stays faulted
+                let meter = session.sample(now);
+                let error_code = session
+                    .fault()
+                    .map(|(_, code)| code.clone())
+                    .unwrap_or_default();
+                info!("Connector {} is faulted: {}", connector, error_code);
+                self.current_sessions.remove(&connector);
+                self.faults.insert(connector, error_code.clone());
+                ChargeStatus::Faulted {
+                    error_code,
+                    meter: Some(meter),
+                }
             }
-            _ => ChargeStatus::Active,
+            Some(session) if now >= session.end_at => {
+                let kwh = session.kwh;
+                let final_reading = session.fault != Some(MockFault::NoFinalReading);
+                let signed_data = session.signed_data.clone();
+                self.current_sessions.remove(&connector);
+                if final_reading {
+                    info!("Charge is ended, kwh: {}", kwh);
+                    ChargeStatus::Ended { kwh, signed_data }
+                } else {
+                    info!("Charge is ended without final reading");
+                    ChargeStatus::NotFound
+                }
+            }
+            Some(session) => ChargeStatus::Active {
+                meter: Some(session.sample(now)),
+            },
         };
+        if self.current_sessions.len() != sessions {
+            self.store.save_or_warn(&self.current_sessions);
+        }
         Ok(status)
     }
+
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        watts: u32,
+        valid_until: Option<u64>,
+    ) -> Result<()> {
+        debug!("Power of connector {} limited to {} W", connector, watts);
+        self.power_limits
+            .insert(connector, PowerLimit { watts, valid_until });
+        Ok(())
+    }
+
+    fn take_presented_tokens(&mut self) -> Result<Vec<PresentedToken>> {
+        Ok(std::mem::take(&mut self.presented_tokens))
+    }
+
+    fn take_contract_authorizations(&mut self) -> Result<Vec<ContractAuthorization>> {
+        Ok(std::mem::take(&mut self.contract_authorizations))
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::api::{ChargeStatus, ChargerApi};
-    use crate::mock::MockCharger;
+    use crate::api::{
+        ChargeStatus, ChargerApi, ChargerError, ContractAuthorization, MeterSample, PresentedToken,
+    };
+    use crate::mock::{MockCharger, MockFault, ScriptedSession, MAX_RANDOM_KWH, MIN_RANDOM_KWH};
+    use crate::store::SessionStore;
+    use std::time::Duration;
 
     #[test]
     fn should_create_new_session() {
@@ -66,39 +476,438 @@ mod test {
 
         // Check status before
         let status_before = charger_api
-            .get_current_charge_status()
+            .get_current_charge_status(1)
             .expect("Cannot get charge status");
         assert_eq!(status_before, ChargeStatus::NotFound);
 
         // Start new charge
-        assert_eq!(charger_api.start_new_charge().is_ok(), true);
+        assert_eq!(charger_api.start_new_charge(1).is_ok(), true);
 
         // Check status after
         let status_after = charger_api
-            .get_current_charge_status()
+            .get_current_charge_status(1)
             .expect("Cannot get charge status");
-        assert_eq!(status_after, ChargeStatus::Active);
+        assert!(matches!(
+            status_after,
+            ChargeStatus::Active { meter: Some(_) }
+        ));
     }
 
     #[test]
-    fn should_end_session() {
-        let mut charger_api = MockCharger {
-            current_session_end_timestamp: None,
-            min_time: 1,
-            max_time: 2,
+    fn should_run_sessions_on_several_connectors() {
+        let mut charger_api = MockCharger::new();
+
+        assert!(charger_api.start_new_charge(1).is_ok());
+        assert!(charger_api.start_new_charge(1).is_err());
+        assert!(charger_api.start_new_charge(2).is_ok());
+
+        assert!(matches!(
+            charger_api.get_current_charge_status(2).unwrap(),
+            ChargeStatus::Active { .. }
+        ));
+        assert_eq!(
+            charger_api.get_current_charge_status(3).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_report_increasing_meter_readings() {
+        let mut charger_api = MockCharger::builder().random_duration(2, 3).build();
+        charger_api
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+
+        let meter = |charger_api: &mut MockCharger| match charger_api
+            .get_current_charge_status(1)
+            .expect("Cannot get charge status")
+        {
+            ChargeStatus::Active { meter: Some(meter) } => meter,
+            other => panic!("Unexpected status {:?}", other),
         };
+        let first = meter(&mut charger_api);
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        let second = meter(&mut charger_api);
+
+        assert!(second.energy_wh > first.energy_wh);
+        assert!(second.timestamp >= first.timestamp);
+        assert!(first.power_w > 0);
+        assert_eq!(first.voltage_mv, 230_000);
+    }
+
+    #[test]
+    fn should_stop_session() {
+        let mut charger_api = MockCharger::new();
+
+        assert!(charger_api.stop_charge(1).is_err());
+        charger_api
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        charger_api.stop_charge(1).expect("Cannot stop charge");
+
+        let status = charger_api
+            .get_current_charge_status(1)
+            .expect("Cannot get charge status");
+        assert!(matches!(status, ChargeStatus::Ended { .. }));
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_end_session() {
+        let mut charger_api = MockCharger::builder().random_duration(1, 2).build();
 
         // Start new charge
         charger_api
-            .start_new_charge()
+            .start_new_charge(1)
             .expect("Cannot start new charge");
 
         // Check status after 2s
         std::thread::sleep(std::time::Duration::from_secs(2));
 
         let status_after = charger_api
-            .get_current_charge_status()
+            .get_current_charge_status(1)
             .expect("Cannot get charge status");
         assert!(matches!(status_after, ChargeStatus::Ended { .. }));
     }
+
+    #[test]
+    fn should_run_scripted_sessions() {
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .session(
+                ScriptedSession::new(Duration::from_secs(100), 10)
+                    .with_signed_data(vec!["OCMF|{}|{}".to_string()]),
+            )
+            .build();
+        charger_api
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+
+        charger_api.advance(Duration::from_secs(50));
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active {
+                meter: Some(MeterSample {
+                    energy_wh: 5000,
+                    power_w: 360_000,
+                    voltage_mv: 230_000,
+                    current_ma: 1_565_217,
+                    timestamp: 50_000,
+                })
+            }
+        );
+
+        charger_api.advance(Duration::from_secs(50));
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 10,
+                signed_data: vec!["OCMF|{}|{}".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn should_use_fixed_duration_and_energy() {
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .duration(Duration::from_secs(30))
+            .kwh(7)
+            .build();
+        charger_api.start_new_charge(1).unwrap();
+        charger_api.start_new_charge(2).unwrap();
+
+        charger_api.advance(Duration::from_secs(29));
+        assert!(matches!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { .. }
+        ));
+        charger_api.advance(Duration::from_secs(1));
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 7,
+                signed_data: vec![]
+            }
+        );
+        assert_eq!(
+            charger_api.get_current_charge_status(2).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 7,
+                signed_data: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn should_draw_sessions_within_bounds() {
+        let mut charger_api = MockCharger::builder()
+            .random_duration(30, 30)
+            .manual_clock()
+            .build();
+        charger_api.start_new_charge(1).unwrap();
+        charger_api.advance(Duration::from_secs(29));
+        assert!(matches!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { .. }
+        ));
+        charger_api.advance(Duration::from_secs(1));
+        match charger_api.get_current_charge_status(1).unwrap() {
+            ChargeStatus::Ended { kwh, .. } => {
+                assert!((MIN_RANDOM_KWH..=MAX_RANDOM_KWH).contains(&kwh))
+            }
+            other => panic!("Unexpected status {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_sample_sessions_started_after_now() {
+        let session = super::MockSession {
+            started_at: Duration::from_secs(120),
+            end_at: Duration::from_secs(60),
+            kwh: 12,
+            fault: Some(MockFault::FaultAfter {
+                after: Duration::from_secs(30),
+                error_code: "GroundFailure".to_string(),
+            }),
+            signed_data: vec![],
+        };
+        assert!(session.fault().is_none());
+        assert_eq!(session.sample(Duration::from_secs(30)).energy_wh, 0);
+    }
+
+    #[test]
+    fn should_be_reproducible_with_a_seed() {
+        let kwh = |seed| {
+            let mut charger_api = MockCharger::builder().seed(seed).manual_clock().build();
+            charger_api.start_new_charge(1).unwrap();
+            charger_api.advance(Duration::from_secs(60));
+            match charger_api.get_current_charge_status(1).unwrap() {
+                ChargeStatus::Ended { kwh, .. } => kwh,
+                other => panic!("Unexpected status {:?}", other),
+            }
+        };
+
+        assert_eq!(kwh(42), kwh(42));
+    }
+
+    #[test]
+    fn should_stop_session_with_energy_delivered_so_far() {
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .session(ScriptedSession::new(Duration::from_secs(100), 10))
+            .build();
+        charger_api.start_new_charge(1).unwrap();
+
+        charger_api.advance(Duration::from_secs(25));
+        charger_api.stop_charge(1).expect("Cannot stop charge");
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 2,
+                signed_data: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn should_refuse_start() {
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .session(
+                ScriptedSession::new(Duration::from_secs(10), 1).with_fault(MockFault::RefuseStart),
+            )
+            .session(
+                ScriptedSession::new(Duration::from_secs(10), 1).with_fault(MockFault::NotPlugged),
+            )
+            .build();
+        let start_error = |charger_api: &mut MockCharger| {
+            charger_api
+                .start_new_charge(1)
+                .unwrap_err()
+                .downcast::<ChargerError>()
+                .expect("Not a ChargerError")
+        };
+
+        assert_eq!(start_error(&mut charger_api), ChargerError::Rejected);
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+        assert_eq!(start_error(&mut charger_api), ChargerError::NotPlugged);
+        // Only the scripted sessions are refused
+        assert!(charger_api.start_new_charge(1).is_ok());
+        assert_eq!(start_error(&mut charger_api), ChargerError::Busy);
+    }
+
+    #[test]
+    fn should_fault_during_session() {
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .session(
+                ScriptedSession::new(Duration::from_secs(100), 10).with_fault(
+                    MockFault::FaultAfter {
+                        after: Duration::from_secs(40),
+                        error_code: "GroundFailure".to_string(),
+                    },
+                ),
+            )
+            .build();
+        charger_api.start_new_charge(1).unwrap();
+
+        charger_api.advance(Duration::from_secs(60));
+        match charger_api.get_current_charge_status(1).unwrap() {
+            ChargeStatus::Faulted {
+                error_code,
+                meter: Some(meter),
+            } => {
+                assert_eq!(error_code, "GroundFailure");
+                assert_eq!(meter.energy_wh, 4000);
+            }
+            other => panic!("Unexpected status {:?}", other),
+        }
+
+        // The connector stays faulted until repaired
+        assert!(matches!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Faulted { meter: None, .. }
+        ));
+        assert!(charger_api.start_new_charge(1).is_err());
+        charger_api.repair(1);
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+        assert!(charger_api.start_new_charge(1).is_ok());
+    }
+
+    #[test]
+    fn should_apply_power_limits() {
+        let mut charger_api = MockCharger::builder().manual_clock().build();
+        assert_eq!(charger_api.power_limit(1), None);
+
+        charger_api.set_power_limit(1, 7400, None).unwrap();
+        charger_api
+            .set_power_limit(0, 11_000, Some(60_000))
+            .unwrap();
+        assert_eq!(charger_api.power_limit(1), Some(7400));
+        assert_eq!(charger_api.power_limit(2), Some(11_000));
+
+        // A new limit replaces the previous one, the lapsed limits are ignored
+        charger_api.set_power_limit(0, 3700, Some(60_000)).unwrap();
+        assert_eq!(charger_api.power_limit(1), Some(3700));
+        charger_api.advance(Duration::from_secs(60));
+        assert_eq!(charger_api.power_limit(1), Some(7400));
+        assert_eq!(charger_api.power_limit(2), None);
+    }
+
+    #[test]
+    fn should_report_presented_tokens() {
+        let mut charger_api = MockCharger::new();
+        assert_eq!(charger_api.take_presented_tokens().unwrap(), vec![]);
+
+        charger_api.present_token(2, "04A2B3C4D5E6F7");
+        charger_api.present_token(1, "FR-DEL-C12345678-9");
+        assert_eq!(
+            charger_api.take_presented_tokens().unwrap(),
+            vec![
+                PresentedToken {
+                    connector: 2,
+                    token: b"04A2B3C4D5E6F7".to_vec(),
+                },
+                PresentedToken {
+                    connector: 1,
+                    token: b"FR-DEL-C12345678-9".to_vec(),
+                },
+            ]
+        );
+        // The tokens are only reported once
+        assert_eq!(charger_api.take_presented_tokens().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn should_report_presented_contracts() {
+        let mut charger_api = MockCharger::new();
+        assert_eq!(charger_api.take_contract_authorizations().unwrap(), vec![]);
+
+        charger_api.present_contract(1, "FRDELC123456789", vec![vec![1, 2], vec![3]]);
+        assert_eq!(
+            charger_api.take_contract_authorizations().unwrap(),
+            vec![ContractAuthorization {
+                connector: 1,
+                emaid: b"FRDELC123456789".to_vec(),
+                certificate_chain: vec![vec![1, 2], vec![3]],
+            }]
+        );
+        assert_eq!(charger_api.take_contract_authorizations().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn should_end_session_without_final_reading() {
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .session(
+                ScriptedSession::new(Duration::from_secs(10), 1)
+                    .with_fault(MockFault::NoFinalReading),
+            )
+            .build();
+        charger_api.start_new_charge(1).unwrap();
+
+        charger_api.advance(Duration::from_secs(10));
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_resume_persisted_sessions() {
+        let path = std::env::temp_dir().join(format!("mock-sessions-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .store(SessionStore::open(&path))
+            .session(ScriptedSession::new(Duration::from_secs(100), 10))
+            .build();
+        charger_api.start_new_charge(1).unwrap();
+        drop(charger_api);
+
+        // The node restarts: the session is resumed
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .store(SessionStore::open(&path))
+            .build();
+        charger_api.advance(Duration::from_secs(50));
+        assert!(matches!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active {
+                meter: Some(MeterSample {
+                    energy_wh: 5000,
+                    ..
+                })
+            }
+        ));
+        charger_api.advance(Duration::from_secs(50));
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 10,
+                signed_data: vec![]
+            }
+        );
+
+        // The ended session is not resumed anymore
+        let mut charger_api = MockCharger::builder()
+            .manual_clock()
+            .store(SessionStore::open(&path))
+            .build();
+        assert_eq!(
+            charger_api.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+        let _ = std::fs::remove_file(&path);
+    }
 }
@@ -0,0 +1,160 @@
+//! A local Modbus TCP server, used to test the Modbus backend without hardware
+use anyhow::Result;
+use log::{debug, warn};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use super::frame::*;
+
+#[derive(Default)]
+struct FakeState {
+    /// Registers of each (unit, address)
+    holding_registers: HashMap<(u8, u16), u16>,
+    input_registers: HashMap<(u8, u16), u16>,
+    coils: HashMap<(u8, u16), bool>,
+}
+
+pub struct FakeModbusServer {
+    state: Arc<Mutex<FakeState>>,
+    local_addr: SocketAddr,
+}
+
+impl FakeModbusServer {
+    /// Listen on `address` (eg. `127.0.0.1:0`), serving each connection on its own thread
+    pub fn listen(address: &str) -> Result<FakeModbusServer> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(FakeState::default()));
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let state = server_state.clone();
+                        thread::spawn(move || serve(stream, state));
+                    }
+                    Err(e) => warn!("Cannot accept Modbus connection: {}", e),
+                }
+            }
+        });
+        Ok(FakeModbusServer { state, local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn set_holding_registers(&self, unit_id: u8, address: u16, words: &[u16]) {
+        let mut state = self.state.lock().unwrap();
+        for (offset, word) in words.iter().enumerate() {
+            state
+                .holding_registers
+                .insert((unit_id, address + offset as u16), *word);
+        }
+    }
+
+    pub fn set_input_registers(&self, unit_id: u8, address: u16, words: &[u16]) {
+        let mut state = self.state.lock().unwrap();
+        for (offset, word) in words.iter().enumerate() {
+            state
+                .input_registers
+                .insert((unit_id, address + offset as u16), *word);
+        }
+    }
+
+    /// Store `value` as a float in two input registers, as the Eastron meters do
+    pub fn set_input_f32(&self, unit_id: u8, address: u16, value: f32) {
+        let bits = value.to_bits();
+        self.set_input_registers(unit_id, address, &[(bits >> 16) as u16, bits as u16]);
+    }
+
+    /// Remove the registers of `unit_id`, whose reads then fail with an exception
+    pub fn clear_registers(&self, unit_id: u8) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .holding_registers
+            .retain(|(unit, _), _| *unit != unit_id);
+        state
+            .input_registers
+            .retain(|(unit, _), _| *unit != unit_id);
+    }
+
+    pub fn coil(&self, unit_id: u8, address: u16) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .coils
+            .get(&(unit_id, address))
+            .copied()
+            .unwrap_or(false)
+    }
+}
+
+fn serve(mut stream: TcpStream, state: Arc<Mutex<FakeState>>) {
+    loop {
+        let request = match Frame::read(&mut stream) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("Modbus connection closed: {}", e);
+                return;
+            }
+        };
+        let pdu = {
+            let mut state = state.lock().unwrap();
+            handle(&mut state, request.unit_id, &request.pdu)
+        };
+        let response = Frame {
+            transaction_id: request.transaction_id,
+            unit_id: request.unit_id,
+            pdu,
+        };
+        if let Err(e) = response.write(&mut stream) {
+            warn!("Cannot send Modbus response: {}", e);
+            return;
+        }
+    }
+}
+
+/// Response PDU to the request `pdu`
+fn handle(state: &mut FakeState, unit_id: u8, pdu: &[u8]) -> Vec<u8> {
+    let function = pdu[0];
+    let exception = |code| vec![function | EXCEPTION_FLAG, code];
+    if pdu.len() != 5 {
+        return exception(ILLEGAL_DATA_VALUE);
+    }
+    let address = u16::from_be_bytes([pdu[1], pdu[2]]);
+    let value = u16::from_be_bytes([pdu[3], pdu[4]]);
+    match function {
+        READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+            let registers = if function == READ_HOLDING_REGISTERS {
+                &state.holding_registers
+            } else {
+                &state.input_registers
+            };
+            let words: Option<Vec<u16>> = (0..value)
+                .map(|offset| registers.get(&(unit_id, address + offset)).copied())
+                .collect();
+            match words {
+                Some(words) => {
+                    let mut response = vec![function, 2 * words.len() as u8];
+                    for word in words {
+                        response.extend_from_slice(&word.to_be_bytes());
+                    }
+                    response
+                }
+                None => exception(ILLEGAL_DATA_ADDRESS),
+            }
+        }
+        WRITE_SINGLE_COIL => match value {
+            COIL_ON | COIL_OFF => {
+                state.coils.insert((unit_id, address), value == COIL_ON);
+                pdu.to_vec()
+            }
+            _ => exception(ILLEGAL_DATA_VALUE),
+        },
+        _ => exception(ILLEGAL_FUNCTION),
+    }
+}
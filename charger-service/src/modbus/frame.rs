@@ -0,0 +1,186 @@
+//! Modbus TCP framing (MBAP header + PDU), and a blocking client over one TCP connection.
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+pub const WRITE_SINGLE_COIL: u8 = 0x05;
+
+/// Exception responses have the high bit of the function code set
+pub const EXCEPTION_FLAG: u8 = 0x80;
+pub const ILLEGAL_FUNCTION: u8 = 0x01;
+pub const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+pub const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// Values of a coil in a WriteSingleCoil request
+pub const COIL_ON: u16 = 0xFF00;
+pub const COIL_OFF: u16 = 0x0000;
+
+/// Protocol identifier of the MBAP header, always 0 for Modbus
+const PROTOCOL_ID: u16 = 0;
+
+/// Largest PDU allowed by the specification
+const MAX_PDU_LEN: usize = 253;
+
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub transaction_id: u16,
+    pub unit_id: u8,
+    /// Function code followed by its data
+    pub pdu: Vec<u8>,
+}
+
+impl Frame {
+    pub fn read(stream: &mut impl Read) -> Result<Frame> {
+        let mut header = [0u8; 7];
+        stream.read_exact(&mut header)?;
+        let transaction_id = u16::from_be_bytes([header[0], header[1]]);
+        let protocol_id = u16::from_be_bytes([header[2], header[3]]);
+        // The length counts the unit id and the PDU
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if protocol_id != PROTOCOL_ID {
+            return Err(anyhow!("Unknown Modbus protocol id {}", protocol_id));
+        }
+        if length < 2 || length - 1 > MAX_PDU_LEN {
+            return Err(anyhow!("Invalid Modbus frame length {}", length));
+        }
+        let mut pdu = vec![0u8; length - 1];
+        stream.read_exact(&mut pdu)?;
+        Ok(Frame {
+            transaction_id,
+            unit_id: header[6],
+            pdu,
+        })
+    }
+
+    pub fn write(&self, stream: &mut impl Write) -> Result<()> {
+        let mut buffer = Vec::with_capacity(7 + self.pdu.len());
+        buffer.extend_from_slice(&self.transaction_id.to_be_bytes());
+        buffer.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+        buffer.extend_from_slice(&(self.pdu.len() as u16 + 1).to_be_bytes());
+        buffer.push(self.unit_id);
+        buffer.extend_from_slice(&self.pdu);
+        stream.write_all(&buffer)?;
+        Ok(())
+    }
+}
+
+/// Client of a Modbus TCP server (a meter, or a gateway in front of several units).
+/// The connection is opened on first use, and opened again after an I/O error.
+pub struct Client {
+    address: String,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+    next_transaction_id: u16,
+}
+
+impl Client {
+    pub fn new(address: &str, timeout: Duration) -> Client {
+        Client {
+            address: address.to_string(),
+            timeout,
+            stream: None,
+            next_transaction_id: 1,
+        }
+    }
+
+    /// Read `count` registers starting at `address`, with function `READ_HOLDING_REGISTERS`
+    /// or `READ_INPUT_REGISTERS`
+    pub fn read_registers(
+        &mut self,
+        unit_id: u8,
+        function: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>> {
+        let mut request = vec![function];
+        request.extend_from_slice(&address.to_be_bytes());
+        request.extend_from_slice(&count.to_be_bytes());
+        let response = self.call(unit_id, request)?;
+        match response.get(1) {
+            Some(&len)
+                if len as usize == 2 * count as usize && response.len() == 2 + len as usize =>
+            {
+                Ok(response[2..]
+                    .chunks(2)
+                    .map(|word| u16::from_be_bytes([word[0], word[1]]))
+                    .collect())
+            }
+            _ => Err(anyhow!(
+                "Invalid response reading {} registers at {}",
+                count,
+                address
+            )),
+        }
+    }
+
+    pub fn write_coil(&mut self, unit_id: u8, address: u16, on: bool) -> Result<()> {
+        let value = if on { COIL_ON } else { COIL_OFF };
+        let mut request = vec![WRITE_SINGLE_COIL];
+        request.extend_from_slice(&address.to_be_bytes());
+        request.extend_from_slice(&value.to_be_bytes());
+        // The server echoes the request
+        let response = self.call(unit_id, request.clone())?;
+        if response != request {
+            return Err(anyhow!("Invalid response writing coil {}", address));
+        }
+        Ok(())
+    }
+
+    /// Send a request PDU to `unit_id`, and return the response PDU
+    fn call(&mut self, unit_id: u8, pdu: Vec<u8>) -> Result<Vec<u8>> {
+        let function = pdu[0];
+        let transaction_id = self.next_transaction_id;
+        self.next_transaction_id = self.next_transaction_id.wrapping_add(1);
+        let request = Frame {
+            transaction_id,
+            unit_id,
+            pdu,
+        };
+        let response = match self.exchange(&request) {
+            Ok(response) => response,
+            Err(e) => {
+                // The connection may be out of sync: start again with a new one
+                warn!("Modbus connection to {} failed: {}", self.address, e);
+                self.stream = None;
+                return Err(e);
+            }
+        };
+        match response.pdu.as_slice() {
+            [code, exception] if *code == function | EXCEPTION_FLAG => Err(anyhow!(
+                "Modbus exception {} for function {} on unit {}",
+                exception,
+                function,
+                unit_id
+            )),
+            [code, ..] if *code == function => Ok(response.pdu),
+            _ => Err(anyhow!("Unexpected response to function {}", function)),
+        }
+    }
+
+    fn exchange(&mut self, request: &Frame) -> Result<Frame> {
+        if self.stream.is_none() {
+            debug!("Connecting to Modbus server {}", self.address);
+            let stream = TcpStream::connect(&self.address)?;
+            stream.set_read_timeout(Some(self.timeout))?;
+            stream.set_write_timeout(Some(self.timeout))?;
+            self.stream = Some(stream);
+        }
+        let stream = self.stream.as_mut().unwrap();
+        request.write(stream)?;
+        let response = Frame::read(stream)?;
+        if response.transaction_id != request.transaction_id {
+            return Err(anyhow!(
+                "Modbus response {} does not match request {}",
+                response.transaction_id,
+                request.transaction_id
+            ));
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,116 @@
+//! Register maps of the Modbus energy meters
+use serde::Deserialize;
+
+use super::frame::{READ_HOLDING_REGISTERS, READ_INPUT_REGISTERS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterKind {
+    Holding,
+    Input,
+}
+
+/// Encoding of a value, spanning one or several registers in big-endian word order
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterFormat {
+    U16,
+    U32,
+    U64,
+    /// IEEE 754 single precision float
+    F32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Register {
+    /// Protocol address (eg. 0x0048 for input register 30073)
+    pub address: u16,
+    pub kind: RegisterKind,
+    pub format: RegisterFormat,
+    /// Factor from the raw value to the base unit (Wh, W, V or A), eg. 1000 for a register in kWh
+    #[serde(default = "unit_scale")]
+    pub scale: f64,
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+impl Register {
+    pub fn new(address: u16, kind: RegisterKind, format: RegisterFormat, scale: f64) -> Register {
+        Register {
+            address,
+            kind,
+            format,
+            scale,
+        }
+    }
+
+    pub(super) fn function(&self) -> u8 {
+        match self.kind {
+            RegisterKind::Holding => READ_HOLDING_REGISTERS,
+            RegisterKind::Input => READ_INPUT_REGISTERS,
+        }
+    }
+
+    /// Number of registers holding the value
+    pub(super) fn count(&self) -> u16 {
+        match self.format {
+            RegisterFormat::U16 => 1,
+            RegisterFormat::U32 | RegisterFormat::F32 => 2,
+            RegisterFormat::U64 => 4,
+        }
+    }
+
+    /// Value in the base unit, from the `count()` registers read at `address`
+    pub(super) fn decode(&self, words: &[u16]) -> f64 {
+        let raw = words
+            .iter()
+            .fold(0u64, |value, word| (value << 16) | *word as u64);
+        let value = match self.format {
+            RegisterFormat::F32 => f32::from_bits(raw as u32) as f64,
+            _ => raw as f64,
+        };
+        value * self.scale
+    }
+}
+
+/// Registers of a meter model. Only the energy register is required
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RegisterMap {
+    /// Cumulative imported active energy
+    pub energy: Register,
+    pub power: Option<Register>,
+    pub voltage: Option<Register>,
+    pub current: Option<Register>,
+}
+
+impl RegisterMap {
+    /// Register map of a known meter model: `sdm120` or `sdm630` (Eastron)
+    pub fn model(name: &str) -> Option<RegisterMap> {
+        let input_f32 = |address, scale| {
+            Some(Register::new(
+                address,
+                RegisterKind::Input,
+                RegisterFormat::F32,
+                scale,
+            ))
+        };
+        match name {
+            "sdm120" => Some(RegisterMap {
+                energy: Register::new(0x0048, RegisterKind::Input, RegisterFormat::F32, 1000.0),
+                power: input_f32(0x000C, 1.0),
+                voltage: input_f32(0x0000, 1.0),
+                current: input_f32(0x0006, 1.0),
+            }),
+            // Voltage and current of phase 1, total power of the three phases
+            "sdm630" => Some(RegisterMap {
+                energy: Register::new(0x0048, RegisterKind::Input, RegisterFormat::F32, 1000.0),
+                power: input_f32(0x0034, 1.0),
+                voltage: input_f32(0x0000, 1.0),
+                current: input_f32(0x0006, 1.0),
+            }),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,569 @@
+//! Charger backend for the posts without a smart controller: each connector is a contactor
+//! switched by a relay, and metered by an energy meter, both reached over Modbus TCP.
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::api::*;
+use crate::store::SessionStore;
+
+pub mod fake;
+mod frame;
+mod meter;
+
+use frame::Client;
+pub use meter::{Register, RegisterFormat, RegisterKind, RegisterMap};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Modbus devices of a connector
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectorConfig {
+    /// Unit id of the meter and of the relay
+    pub unit_id: u8,
+    /// Coil switching the contactor of the connector
+    pub relay_coil: u16,
+    pub meter: RegisterMap,
+    /// Time without power after which the vehicle is deemed unplugged: the relay is opened
+    /// and the session ended. Requires the power register of the meter; without it, a
+    /// session only ends with `stop_charge`.
+    pub idle_timeout: Option<Duration>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Session {
+    /// Energy register when the session started, in Wh
+    meter_start: f64,
+    /// Energy delivered when the session was stopped, in Wh
+    delivered: Option<u64>,
+    /// Unix time (in ms) of the first reading without power, since the last one with power
+    #[serde(default)]
+    idle_since: Option<u64>,
+}
+
+pub struct ModbusCharger {
+    client: Client,
+    connectors: HashMap<ConnectorId, ConnectorConfig>,
+    sessions: HashMap<ConnectorId, Session>,
+    store: SessionStore,
+}
+
+impl ModbusCharger {
+    /// Charger behind the Modbus TCP server at `address` (eg. `192.168.1.10:502`),
+    /// which is connected on first use
+    pub fn new(address: &str) -> ModbusCharger {
+        ModbusCharger {
+            client: Client::new(address, DEFAULT_TIMEOUT),
+            connectors: HashMap::new(),
+            sessions: HashMap::new(),
+            store: SessionStore::in_memory(),
+        }
+    }
+
+    /// Persist the sessions in `store`, and resume the sessions saved there: the relays of
+    /// their connectors are still closed
+    pub fn with_store(mut self, store: SessionStore) -> ModbusCharger {
+        self.sessions = store.load();
+        self.store = store;
+        self
+    }
+
+    pub fn with_connector(
+        mut self,
+        connector: ConnectorId,
+        config: ConnectorConfig,
+    ) -> ModbusCharger {
+        self.connectors.insert(connector, config);
+        self
+    }
+
+    fn config(&self, connector: ConnectorId) -> Result<ConnectorConfig> {
+        self.connectors.get(&connector).cloned().ok_or_else(|| {
+            ChargerError::UnknownConnector
+                .with_message(format!("Connector {} is not configured", connector))
+        })
+    }
+
+    /// Value of `register` in its base unit
+    fn read(&mut self, unit_id: u8, register: &Register) -> Result<f64> {
+        let words = self.client.read_registers(
+            unit_id,
+            register.function(),
+            register.address,
+            register.count(),
+        )?;
+        Ok(register.decode(&words))
+    }
+
+    fn read_optional(&mut self, unit_id: u8, register: &Option<Register>) -> Result<Option<f64>> {
+        match register {
+            Some(register) => self.read(unit_id, register).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Track the readings without power of the session of `connector`, and return the time
+    /// of the first one since the last reading with power
+    fn update_idle_since(&mut self, connector: ConnectorId, idle: bool, now: u64) -> Option<u64> {
+        let session = self.sessions.get_mut(&connector)?;
+        let idle_since = match (idle, session.idle_since) {
+            (true, Some(since)) => return Some(since),
+            (false, None) => return None,
+            (true, None) => Some(now),
+            (false, Some(_)) => None,
+        };
+        session.idle_since = idle_since;
+        self.store.save_or_warn(&self.sessions);
+        idle_since
+    }
+
+    fn read_sample(&mut self, config: &ConnectorConfig, meter_start: f64) -> Result<MeterSample> {
+        let energy = self.read(config.unit_id, &config.meter.energy)?;
+        let power = self.read_optional(config.unit_id, &config.meter.power)?;
+        let voltage = self.read_optional(config.unit_id, &config.meter.voltage)?;
+        let current = self.read_optional(config.unit_id, &config.meter.current)?;
+        Ok(MeterSample {
+            energy_wh: (energy - meter_start).max(0.0).round() as u64,
+            power_w: power.unwrap_or_default().max(0.0).round() as u32,
+            voltage_mv: (voltage.unwrap_or_default().max(0.0) * 1000.0).round() as u32,
+            current_ma: (current.unwrap_or_default().max(0.0) * 1000.0).round() as u32,
+            // The meters have no clock: the reading is taken now
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_millis() as u64)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl ChargerApi for ModbusCharger {
+    fn start_new_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        let config = self.config(connector)?;
+        if self.sessions.contains_key(&connector) {
+            return Err(ChargerError::Busy.with_message(format!(
+                "Connector {} already has an active session",
+                connector
+            )));
+        }
+        let meter_start = self
+            .read(config.unit_id, &config.meter.energy)
+            .context(ChargerError::Unreachable)?;
+        self.client
+            .write_coil(config.unit_id, config.relay_coil, true)
+            .context(ChargerError::Unreachable)?;
+        info!(
+            "Relay of connector {} closed, meter start: {} Wh",
+            connector, meter_start
+        );
+        self.sessions.insert(
+            connector,
+            Session {
+                meter_start,
+                delivered: None,
+                idle_since: None,
+            },
+        );
+        self.store.save_or_warn(&self.sessions);
+        Ok(())
+    }
+
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        let config = self.config(connector)?;
+        let meter_start = match self.sessions.get(&connector) {
+            Some(session) => session.meter_start,
+            None => {
+                return Err(ChargerError::NoSession
+                    .with_message(format!("No active session on connector {}", connector)))
+            }
+        };
+        // The final reading is taken before the contactor is opened, so that a failed reading
+        // leaves the session running
+        let delivered = self
+            .read_sample(&config, meter_start)
+            .context(ChargerError::Unreachable)?
+            .energy_wh;
+        self.client
+            .write_coil(config.unit_id, config.relay_coil, false)
+            .context(ChargerError::Unreachable)?;
+        info!(
+            "Relay of connector {} opened, delivered: {} Wh",
+            connector, delivered
+        );
+        if let Some(session) = self.sessions.get_mut(&connector) {
+            session.delivered = Some(delivered);
+        }
+        self.store.save_or_warn(&self.sessions);
+        Ok(())
+    }
+
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> Result<ChargeStatus> {
+        debug!("Get charge status of connector {}", connector);
+        let (meter_start, delivered) = match self.sessions.get(&connector) {
+            Some(session) => (session.meter_start, session.delivered),
+            None => return Ok(ChargeStatus::NotFound),
+        };
+        if let Some(delivered) = delivered {
+            self.sessions.remove(&connector);
+            self.store.save_or_warn(&self.sessions);
+            // Meters read over Modbus do not sign their readings
+            return Ok(ChargeStatus::Ended {
+                kwh: delivered / 1000,
+                signed_data: Vec::new(),
+            });
+        }
+        let config = self.config(connector)?;
+        let meter = self.read_sample(&config, meter_start)?;
+        // A contactor cannot tell whether the vehicle is charging, but its meter can
+        let idle = config.meter.power.is_some() && meter.power_w == 0;
+        let idle_since = self.update_idle_since(connector, idle, meter.timestamp);
+        match (idle_since, config.idle_timeout) {
+            (Some(since), Some(timeout))
+                if meter.timestamp.saturating_sub(since) >= timeout.as_millis() as u64 =>
+            {
+                self.client
+                    .write_coil(config.unit_id, config.relay_coil, false)
+                    .context(ChargerError::Unreachable)?;
+                info!(
+                    "No power on connector {} for {:?}: relay opened, delivered: {} Wh",
+                    connector, timeout, meter.energy_wh
+                );
+                self.sessions.remove(&connector);
+                self.store.save_or_warn(&self.sessions);
+                Ok(ChargeStatus::Ended {
+                    kwh: meter.energy_wh / 1000,
+                    signed_data: Vec::new(),
+                })
+            }
+            _ if idle => Ok(ChargeStatus::SuspendedEV { meter: Some(meter) }),
+            _ => Ok(ChargeStatus::Active { meter: Some(meter) }),
+        }
+    }
+
+    /// A contactor can only switch the connector on or off: the power cannot be limited
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        _watts: u32,
+        _valid_until: Option<u64>,
+    ) -> Result<()> {
+        if connector != 0 {
+            self.config(connector)?;
+        }
+        Err(ChargerError::Rejected.with_message(format!(
+            "Connector {} is switched by a contactor, its power cannot be limited",
+            connector
+        )))
+    }
+
+    /// The contactor posts have no card reader
+    fn take_presented_tokens(&mut self) -> Result<Vec<PresentedToken>> {
+        Ok(Vec::new())
+    }
+
+    /// Nor do they speak ISO 15118
+    fn take_contract_authorizations(&mut self) -> Result<Vec<ContractAuthorization>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fake::FakeModbusServer;
+    use super::*;
+
+    const UNIT_ID: u8 = 1;
+    const RELAY_COIL: u16 = 0;
+
+    fn sdm630() -> ConnectorConfig {
+        ConnectorConfig {
+            unit_id: UNIT_ID,
+            relay_coil: RELAY_COIL,
+            meter: RegisterMap::model("sdm630").unwrap(),
+            idle_timeout: None,
+        }
+    }
+
+    /// Set the readings of the SDM630 meter, energy being in kWh
+    fn set_readings(server: &FakeModbusServer, energy: f32, power: f32) {
+        server.set_input_f32(UNIT_ID, 0x0048, energy);
+        server.set_input_f32(UNIT_ID, 0x0034, power);
+        server.set_input_f32(UNIT_ID, 0x0000, 230.0);
+        server.set_input_f32(UNIT_ID, 0x0006, 32.0);
+    }
+
+    fn connect() -> (ModbusCharger, FakeModbusServer) {
+        let server = FakeModbusServer::listen("127.0.0.1:0").expect("Cannot listen");
+        let charger =
+            ModbusCharger::new(&server.local_addr().to_string()).with_connector(1, sdm630());
+        (charger, server)
+    }
+
+    #[test]
+    fn should_start_and_stop_a_charge() {
+        let (mut charger, server) = connect();
+        set_readings(&server, 1000.0, 0.0);
+
+        charger
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        assert!(server.coil(UNIT_ID, RELAY_COIL));
+
+        set_readings(&server, 1002.5, 7400.0);
+        match charger.get_current_charge_status(1).unwrap() {
+            ChargeStatus::Active { meter: Some(meter) } => {
+                assert_eq!(meter.energy_wh, 2500);
+                assert_eq!(meter.power_w, 7400);
+                assert_eq!(meter.voltage_mv, 230_000);
+                assert_eq!(meter.current_ma, 32_000);
+                assert!(meter.timestamp > 0);
+            }
+            other => panic!("Unexpected status {:?}", other),
+        }
+
+        set_readings(&server, 1004.0, 0.0);
+        charger.stop_charge(1).expect("Cannot stop charge");
+        assert!(!server.coil(UNIT_ID, RELAY_COIL));
+        assert_eq!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 4,
+                signed_data: vec![]
+            }
+        );
+        assert_eq!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_keep_the_session_running_when_the_final_reading_fails() {
+        let (mut charger, server) = connect();
+        set_readings(&server, 1000.0, 7400.0);
+        charger
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+
+        server.clear_registers(UNIT_ID);
+        assert!(charger.stop_charge(1).is_err());
+        assert!(server.coil(UNIT_ID, RELAY_COIL));
+
+        set_readings(&server, 1002.0, 7400.0);
+        assert!(matches!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { .. }
+        ));
+        charger.stop_charge(1).expect("Cannot stop charge");
+        assert!(!server.coil(UNIT_ID, RELAY_COIL));
+        assert_eq!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 2,
+                signed_data: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn should_report_suspended_ev_without_power() {
+        let (mut charger, server) = connect();
+        set_readings(&server, 10.0, 0.0);
+        charger
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+
+        assert!(matches!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::SuspendedEV { meter: Some(_) }
+        ));
+    }
+
+    #[test]
+    fn should_end_the_session_without_power() {
+        let server = FakeModbusServer::listen("127.0.0.1:0").expect("Cannot listen");
+        let config = |idle_timeout| ConnectorConfig {
+            idle_timeout: Some(idle_timeout),
+            ..sdm630()
+        };
+        let mut charger = ModbusCharger::new(&server.local_addr().to_string())
+            .with_connector(1, config(Duration::from_secs(0)))
+            .with_connector(2, config(Duration::from_secs(600)));
+        set_readings(&server, 1000.0, 7400.0);
+        charger
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+
+        // The session goes on while the vehicle draws power
+        set_readings(&server, 1002.5, 7400.0);
+        assert!(matches!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { .. }
+        ));
+
+        // The vehicle is unplugged: the session ends with the energy delivered
+        set_readings(&server, 1003.0, 0.0);
+        assert_eq!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 3,
+                signed_data: vec![]
+            }
+        );
+        assert!(!server.coil(UNIT_ID, RELAY_COIL));
+        assert_eq!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+
+        // A vehicle pausing for less than the timeout is not deemed unplugged
+        charger
+            .start_new_charge(2)
+            .expect("Cannot start new charge");
+        assert!(matches!(
+            charger.get_current_charge_status(2).unwrap(),
+            ChargeStatus::SuspendedEV { .. }
+        ));
+        assert!(server.coil(UNIT_ID, RELAY_COIL));
+    }
+
+    #[test]
+    fn should_not_start_twice() {
+        let (mut charger, server) = connect();
+        set_readings(&server, 10.0, 0.0);
+
+        assert!(charger.start_new_charge(1).is_ok());
+        assert!(charger.start_new_charge(1).is_err());
+        assert!(charger.stop_charge(2).is_err());
+        // Connector 2 is not configured
+        assert!(charger.start_new_charge(2).is_err());
+    }
+
+    #[test]
+    fn should_refuse_power_limits() {
+        let (mut charger, _server) = connect();
+
+        let error = |result: Result<()>| result.unwrap_err().downcast::<ChargerError>().unwrap();
+        assert_eq!(
+            error(charger.set_power_limit(1, 7400, None)),
+            ChargerError::Rejected
+        );
+        assert_eq!(
+            error(charger.set_power_limit(2, 7400, None)),
+            ChargerError::UnknownConnector
+        );
+    }
+
+    #[test]
+    fn should_read_integer_registers() {
+        let server = FakeModbusServer::listen("127.0.0.1:0").expect("Cannot listen");
+        // Energy in hundredths of kWh over two holding registers, power in W
+        let config = ConnectorConfig {
+            unit_id: 3,
+            relay_coil: 8,
+            meter: RegisterMap {
+                energy: Register::new(100, RegisterKind::Holding, RegisterFormat::U32, 10.0),
+                power: Some(Register::new(
+                    102,
+                    RegisterKind::Holding,
+                    RegisterFormat::U16,
+                    1.0,
+                )),
+                voltage: None,
+                current: None,
+            },
+            idle_timeout: None,
+        };
+        let mut charger =
+            ModbusCharger::new(&server.local_addr().to_string()).with_connector(2, config);
+        server.set_holding_registers(3, 100, &[0x0001, 0x0000, 0]);
+        charger
+            .start_new_charge(2)
+            .expect("Cannot start new charge");
+        assert!(server.coil(3, 8));
+
+        server.set_holding_registers(3, 100, &[0x0001, 0x00C8, 11_000]);
+        match charger.get_current_charge_status(2).unwrap() {
+            ChargeStatus::Active { meter: Some(meter) } => {
+                assert_eq!(meter.energy_wh, 2000);
+                assert_eq!(meter.power_w, 11_000);
+                assert_eq!(meter.voltage_mv, 0);
+            }
+            other => panic!("Unexpected status {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_fail_on_modbus_exception() {
+        let (mut charger, _server) = connect();
+
+        // The meter registers are not set: the server answers with an exception
+        assert!(charger.start_new_charge(1).is_err());
+        assert_eq!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_fail_without_server() {
+        let address = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let mut charger = ModbusCharger::new(&address.to_string()).with_connector(1, sdm630());
+
+        assert!(charger.start_new_charge(1).is_err());
+    }
+
+    #[test]
+    fn should_decode_register_formats() {
+        let register = |format| Register::new(0, RegisterKind::Input, format, 1.0);
+
+        assert_eq!(register(RegisterFormat::U16).decode(&[0x1234]), 4660.0);
+        assert_eq!(
+            register(RegisterFormat::U32).decode(&[0x0001, 0x0002]),
+            65538.0
+        );
+        assert_eq!(
+            register(RegisterFormat::U64).decode(&[0, 0, 0x0001, 0x0000]),
+            65536.0
+        );
+        assert_eq!(
+            register(RegisterFormat::F32).decode(&[0x4348, 0x0000]),
+            200.0
+        );
+    }
+
+    #[test]
+    fn should_resume_persisted_sessions() {
+        let path =
+            std::env::temp_dir().join(format!("modbus-sessions-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (charger, server) = connect();
+        let mut charger = charger.with_store(SessionStore::open(&path));
+        set_readings(&server, 1000.0, 7400.0);
+        charger
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        drop(charger);
+
+        // The node restarts: the session goes on from the persisted meter start
+        let mut charger = ModbusCharger::new(&server.local_addr().to_string())
+            .with_connector(1, sdm630())
+            .with_store(SessionStore::open(&path));
+        set_readings(&server, 1003.0, 7400.0);
+        charger.stop_charge(1).expect("Cannot stop charge");
+        assert_eq!(
+            charger.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 3,
+                signed_data: vec![]
+            }
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
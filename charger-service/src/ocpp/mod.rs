@@ -0,0 +1,55 @@
+//! Charger backends speaking the Open Charge Point Protocol (OCPP-J, over WebSocket)
+use crate::api::MeterSample;
+
+mod rpc;
+
+pub use rpc::ChargePointCredentials;
+
+pub mod v16;
+pub mod v201;
+
+/// Default measurand of the sampled values which omit it
+const ENERGY_REGISTER: &str = "Energy.Active.Import.Register";
+
+/// Fold the sampled values of a meter value into `sample`, the last reading of a transaction.
+/// `values` yields `(measurand, value)` pairs, values being in Wh, W, V or A;
+/// the energy register is made relative to `meter_start` (in Wh).
+fn update_sample<'a>(
+    sample: &mut MeterSample,
+    meter_start: i64,
+    timestamp: &str,
+    values: impl Iterator<Item = (&'a str, f64)>,
+) {
+    for (measurand, value) in values {
+        match measurand {
+            ENERGY_REGISTER => {
+                sample.energy_wh = (value.round() as i64 - meter_start).max(0) as u64
+            }
+            "Power.Active.Import" => sample.power_w = value.max(0.0).round() as u32,
+            "Voltage" => sample.voltage_mv = (value.max(0.0) * 1000.0).round() as u32,
+            "Current.Import" => sample.current_ma = (value.max(0.0) * 1000.0).round() as u32,
+            _ => {}
+        }
+    }
+    sample.timestamp = chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| time.timestamp_millis().max(0) as u64)
+        .unwrap_or(sample.timestamp);
+}
+
+/// RFC 3339 date of `unix_ms`, a unix time in milliseconds (eg. the end of a power limit)
+fn to_rfc3339(unix_ms: u64) -> String {
+    use chrono::TimeZone;
+    chrono::Utc
+        .timestamp_millis_opt(unix_ms.min(i64::MAX as u64) as i64)
+        .single()
+        .unwrap_or_else(chrono::Utc::now)
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Scale a value expressed in `unit` (eg. `kWh`) to the base unit (eg. `Wh`)
+fn scale_to_base_unit(value: f64, unit: Option<&str>) -> f64 {
+    match unit {
+        Some(unit) if unit.starts_with('k') => value * 1000.0,
+        _ => value,
+    }
+}
@@ -0,0 +1,595 @@
+//! OCPP-J RPC framework: message framing and WebSocket transport shared by all OCPP versions.
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tungstenite::{
+    client::IntoClientRequest,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{HeaderValue, StatusCode},
+    Error as WsError, Message, WebSocket,
+};
+
+/// Read timeout of the socket, used to interleave reads and writes on the connection thread
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Time given to a peer to complete the WebSocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most handshakes run at once: the connections accepted beyond are closed right away
+const MAX_PENDING_HANDSHAKES: usize = 8;
+
+const PROTOCOL_HEADER: &str = "Sec-WebSocket-Protocol";
+const AUTHORIZATION_HEADER: &str = "Authorization";
+
+/// The only charge point allowed to connect: its identity (last segment of the URL path) and
+/// the password of its HTTP Basic authentication (OCPP security profile 1)
+#[derive(Debug, Clone)]
+pub struct ChargePointCredentials {
+    pub identity: String,
+    pub password: String,
+}
+
+impl ChargePointCredentials {
+    /// Value of the Authorization header sent by the charge point
+    fn basic_authorization(&self) -> String {
+        format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", self.identity, self.password))
+        )
+    }
+
+    fn is_authorized(&self, authorization: Option<&HeaderValue>) -> bool {
+        authorization
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| base64::decode(encoded.trim()).ok())
+            .map(|decoded| {
+                constant_time_eq(
+                    &decoded,
+                    format!("{}:{}", self.identity, self.password).as_bytes(),
+                )
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Compare `a` and `b` in a time which does not depend on their content, so that the password
+/// cannot be guessed from the response time
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Counts a pending handshake for as long as it lives
+struct PendingHandshake(Arc<AtomicUsize>);
+
+impl PendingHandshake {
+    fn start(pending: &Arc<AtomicUsize>) -> Option<PendingHandshake> {
+        if pending.fetch_add(1, Ordering::SeqCst) >= MAX_PENDING_HANDSHAKES {
+            pending.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(PendingHandshake(pending.clone()))
+    }
+}
+
+impl Drop for PendingHandshake {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+static NEXT_MESSAGE_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Call {
+        id: String,
+        action: String,
+        payload: Value,
+    },
+    CallResult {
+        id: String,
+        payload: Value,
+    },
+    CallError {
+        id: String,
+        code: String,
+        description: String,
+    },
+}
+
+impl Frame {
+    pub fn parse(text: &str) -> Result<Frame> {
+        let value: Value = serde_json::from_str(text)?;
+        let items = value
+            .as_array()
+            .ok_or_else(|| anyhow!("OCPP-J message is not an array"))?;
+        let id = items
+            .get(1)
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("OCPP-J message has no id"))?
+            .to_string();
+        let text_at = |index: usize| {
+            items
+                .get(index)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        match items.first().and_then(Value::as_u64) {
+            Some(2) => Ok(Frame::Call {
+                id,
+                action: text_at(2),
+                payload: items.get(3).cloned().unwrap_or(Value::Null),
+            }),
+            Some(3) => Ok(Frame::CallResult {
+                id,
+                payload: items.get(2).cloned().unwrap_or(Value::Null),
+            }),
+            Some(4) => Ok(Frame::CallError {
+                id,
+                code: text_at(2),
+                description: text_at(3),
+            }),
+            _ => Err(anyhow!("Unknown OCPP-J message type")),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Frame::Call {
+                id,
+                action,
+                payload,
+            } => json!([2, id, action, payload]),
+            Frame::CallResult { id, payload } => json!([3, id, payload]),
+            Frame::CallError {
+                id,
+                code,
+                description,
+            } => json!([4, id, code, description, {}]),
+        }
+        .to_string()
+    }
+}
+
+/// Error returned to the peer when an incoming call cannot be handled
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: &'static str,
+    pub description: String,
+}
+
+impl RpcError {
+    pub fn not_implemented(action: &str) -> RpcError {
+        RpcError {
+            code: "NotImplemented",
+            description: format!("Action {} is not supported", action),
+        }
+    }
+
+    pub fn formation_violation(error: impl std::fmt::Display) -> RpcError {
+        RpcError {
+            code: "FormationViolation",
+            description: error.to_string(),
+        }
+    }
+}
+
+/// Handles the calls initiated by the peer
+pub trait Handler: Send + 'static {
+    fn handle_call(&mut self, action: &str, payload: Value) -> Result<Value, RpcError>;
+}
+
+type PendingCalls = Mutex<HashMap<String, mpsc::Sender<Result<Value>>>>;
+
+/// State shared between the endpoint handles and the connection thread
+#[derive(Default)]
+struct Shared {
+    pending: PendingCalls,
+    closed: AtomicBool,
+}
+
+/// Handle used to send calls to a connected peer
+#[derive(Clone)]
+pub struct Endpoint {
+    outbox: mpsc::Sender<String>,
+    shared: Arc<Shared>,
+    timeout: Duration,
+}
+
+impl Endpoint {
+    /// Spawn the connection thread for this socket, incoming calls are dispatched to `handler`
+    pub fn spawn<H: Handler>(
+        socket: WebSocket<TcpStream>,
+        handler: H,
+        timeout: Duration,
+    ) -> Result<Endpoint> {
+        socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+        let (outbox, inbox) = mpsc::channel();
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+        thread::spawn(move || {
+            run_connection(socket, handler, inbox, &thread_shared);
+            thread_shared.closed.store(true, Ordering::SeqCst);
+            // Dropping the senders fails all the calls still waiting for a response
+            thread_shared.pending.lock().unwrap().clear();
+        });
+        Ok(Endpoint {
+            outbox,
+            shared,
+            timeout,
+        })
+    }
+
+    /// Send a call to the peer and wait for its response
+    pub fn call(&self, action: &str, payload: Value) -> Result<Value> {
+        let id = NEXT_MESSAGE_ID.fetch_add(1, Ordering::SeqCst).to_string();
+        let (sender, receiver) = mpsc::channel();
+        self.shared
+            .pending
+            .lock()
+            .unwrap()
+            .insert(id.clone(), sender);
+
+        let frame = Frame::Call {
+            id: id.clone(),
+            action: action.to_string(),
+            payload,
+        };
+        debug!("Send OCPP call {}", action);
+        let result = match self.outbox.send(frame.to_text()) {
+            Err(_) => Err(anyhow!("Connection with the peer is closed")),
+            Ok(_) => receiver
+                .recv_timeout(self.timeout)
+                .map_err(|_| anyhow!("No response to {} within {:?}", action, self.timeout))
+                .and_then(|response| response),
+        };
+        self.shared.pending.lock().unwrap().remove(&id);
+        result
+    }
+
+    pub fn is_connected(&self) -> bool {
+        !self.shared.closed.load(Ordering::SeqCst)
+    }
+}
+
+fn run_connection<H: Handler>(
+    mut socket: WebSocket<TcpStream>,
+    mut handler: H,
+    inbox: mpsc::Receiver<String>,
+    shared: &Shared,
+) {
+    loop {
+        // Flush the outgoing calls
+        loop {
+            match inbox.try_recv() {
+                Ok(text) => {
+                    if let Err(e) = socket.write_message(Message::Text(text)) {
+                        warn!("Cannot send OCPP message: {}", e);
+                        return;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // All the endpoint handles are dropped
+                    let _ = socket.close(None);
+                    return;
+                }
+            }
+        }
+
+        match socket.read_message() {
+            Ok(Message::Text(text)) => {
+                if let Some(reply) = dispatch(&text, &mut handler, shared) {
+                    if let Err(e) = socket.write_message(Message::Text(reply.to_text())) {
+                        warn!("Cannot send OCPP message: {}", e);
+                        return;
+                    }
+                }
+            }
+            Ok(Message::Close(_)) => {
+                debug!("OCPP connection closed by peer");
+                return;
+            }
+            Ok(_) => {}
+            Err(WsError::Io(e))
+                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => {
+                debug!("OCPP connection lost: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+fn dispatch<H: Handler>(text: &str, handler: &mut H, shared: &Shared) -> Option<Frame> {
+    match Frame::parse(text) {
+        Ok(Frame::Call {
+            id,
+            action,
+            payload,
+        }) => {
+            debug!("Received OCPP call {}", action);
+            Some(match handler.handle_call(&action, payload) {
+                Ok(payload) => Frame::CallResult { id, payload },
+                Err(error) => Frame::CallError {
+                    id,
+                    code: error.code.to_string(),
+                    description: error.description,
+                },
+            })
+        }
+        Ok(Frame::CallResult { id, payload }) => {
+            if let Some(sender) = shared.pending.lock().unwrap().remove(&id) {
+                let _ = sender.send(Ok(payload));
+            }
+            None
+        }
+        Ok(Frame::CallError {
+            id,
+            code,
+            description,
+        }) => {
+            if let Some(sender) = shared.pending.lock().unwrap().remove(&id) {
+                let _ = sender.send(Err(anyhow!("{}: {}", code, description)));
+            }
+            None
+        }
+        Err(e) => {
+            warn!("Invalid OCPP-J message received: {}", e);
+            None
+        }
+    }
+}
+
+/// Accept the WebSocket connections of the charge point of `credentials` negotiating
+/// `protocol`, each handshake on its own thread so that a stalled peer does not hold the others.
+/// `on_connect` receives the charge point identity and the socket.
+pub fn listen<F>(
+    address: &str,
+    protocol: &'static str,
+    credentials: ChargePointCredentials,
+    on_connect: F,
+) -> Result<SocketAddr>
+where
+    F: Fn(String, WebSocket<TcpStream>) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(address)?;
+    let local_addr = listener.local_addr()?;
+    let credentials = Arc::new(credentials);
+    let on_connect = Arc::new(on_connect);
+    let pending = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Cannot accept OCPP connection: {}", e);
+                    continue;
+                }
+            };
+            let handshake = match PendingHandshake::start(&pending) {
+                Some(handshake) => handshake,
+                None => {
+                    warn!("Too many pending OCPP handshakes: connection closed");
+                    continue;
+                }
+            };
+            let credentials = credentials.clone();
+            let on_connect = on_connect.clone();
+            thread::spawn(move || {
+                let accepted = accept(stream, protocol, &credentials);
+                drop(handshake);
+                match accepted {
+                    Ok(socket) => on_connect(credentials.identity.clone(), socket),
+                    Err(e) => warn!("OCPP WebSocket handshake failed: {}", e),
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+/// WebSocket handshake of a peer, which must authenticate as the charge point of `credentials`
+fn accept(
+    stream: TcpStream,
+    protocol: &'static str,
+    credentials: &ChargePointCredentials,
+) -> Result<WebSocket<TcpStream>> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let callback = |request: &Request, mut response: Response| {
+        let identity = request.uri().path().rsplit('/').next().unwrap_or_default();
+        if identity != credentials.identity {
+            warn!("Connection of unknown charge point {} refused", identity);
+            return Err(error_response(StatusCode::NOT_FOUND));
+        }
+        if !credentials.is_authorized(request.headers().get(AUTHORIZATION_HEADER)) {
+            warn!("Charge point {} failed to authenticate", identity);
+            let mut error = error_response(StatusCode::UNAUTHORIZED);
+            error.headers_mut().insert(
+                "WWW-Authenticate",
+                HeaderValue::from_static("Basic realm=\"OCPP\""),
+            );
+            return Err(error);
+        }
+        let requested = request
+            .headers()
+            .get(PROTOCOL_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if requested.split(',').any(|p| p.trim() == protocol) {
+            response
+                .headers_mut()
+                .insert(PROTOCOL_HEADER, HeaderValue::from_static(protocol));
+        }
+        Ok(response)
+    };
+    let socket = tungstenite::accept_hdr(stream, callback).map_err(|e| anyhow!("{}", e))?;
+    socket.get_ref().set_write_timeout(None)?;
+    Ok(socket)
+}
+
+fn error_response(status: StatusCode) -> ErrorResponse {
+    let mut response = ErrorResponse::new(None);
+    *response.status_mut() = status;
+    response
+}
+
+/// Open a WebSocket connection to `url` (`ws://host:port/path`, the path ending with the
+/// identity of the charge point) negotiating `protocol`, authenticated with `password`
+pub fn connect(url: &str, protocol: &'static str, password: &str) -> Result<WebSocket<TcpStream>> {
+    let mut request = url.into_client_request()?;
+    let credentials = ChargePointCredentials {
+        identity: request
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        password: password.to_string(),
+    };
+    request
+        .headers_mut()
+        .insert(PROTOCOL_HEADER, HeaderValue::from_static(protocol));
+    request.headers_mut().insert(
+        AUTHORIZATION_HEADER,
+        HeaderValue::from_str(&credentials.basic_authorization())?,
+    );
+    let host = request
+        .uri()
+        .host()
+        .ok_or_else(|| anyhow!("No host in URL {}", url))?;
+    let port = request.uri().port_u16().unwrap_or(80);
+    let stream = TcpStream::connect((host, port))?;
+    let (socket, _) =
+        tungstenite::client(request, stream).map_err(|e| anyhow!("Handshake failed: {}", e))?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{connect, listen, ChargePointCredentials, Frame};
+    use serde_json::json;
+    use std::{net::TcpStream, sync::mpsc, time::Duration};
+
+    const PROTOCOL: &str = "ocpp1.6";
+
+    fn credentials() -> ChargePointCredentials {
+        ChargePointCredentials {
+            identity: "CP001".into(),
+            password: "0123456789abcdef".into(),
+        }
+    }
+
+    #[test]
+    fn should_only_accept_the_authenticated_charge_point() {
+        let (connected, connections) = mpsc::channel();
+        let connected = std::sync::Mutex::new(connected);
+        let address = listen(
+            "127.0.0.1:0",
+            PROTOCOL,
+            credentials(),
+            move |identity, _| {
+                connected.lock().unwrap().send(identity).unwrap();
+            },
+        )
+        .expect("Cannot listen");
+
+        // A peer which never completes its handshake does not hold the others
+        let _stalled = TcpStream::connect(address).expect("Cannot connect");
+
+        let url = |identity: &str| format!("ws://{}/ocpp/{}", address, identity);
+        assert!(connect(&url("CP002"), PROTOCOL, "0123456789abcdef").is_err());
+        assert!(connect(&url("CP001"), PROTOCOL, "wrong password").is_err());
+        let _socket = connect(&url("CP001"), PROTOCOL, "0123456789abcdef").expect("Cannot connect");
+        assert_eq!(
+            connections.recv_timeout(Duration::from_secs(5)),
+            Ok("CP001".to_string())
+        );
+        assert!(connections.try_recv().is_err());
+    }
+
+    #[test]
+    fn should_bound_the_pending_handshakes() {
+        let (connected, connections) = mpsc::channel();
+        let connected = std::sync::Mutex::new(connected);
+        let address = listen(
+            "127.0.0.1:0",
+            PROTOCOL,
+            credentials(),
+            move |identity, _| {
+                connected.lock().unwrap().send(identity).unwrap();
+            },
+        )
+        .expect("Cannot listen");
+        let url = format!("ws://{}/ocpp/CP001", address);
+
+        // The connections beyond the pending handshakes are closed
+        let stalled: Vec<_> = (0..MAX_PENDING_HANDSHAKES)
+            .map(|_| TcpStream::connect(address).expect("Cannot connect"))
+            .collect();
+        assert!(connect(&url, PROTOCOL, "0123456789abcdef").is_err());
+
+        // They are accepted again once the pending handshakes are over
+        drop(stalled);
+        let accepted = (0..50).any(|_| {
+            thread::sleep(Duration::from_millis(20));
+            connect(&url, PROTOCOL, "0123456789abcdef").is_ok()
+        });
+        assert!(accepted);
+        assert_eq!(
+            connections.recv_timeout(Duration::from_secs(5)),
+            Ok("CP001".to_string())
+        );
+    }
+
+    #[test]
+    fn should_compare_passwords() {
+        assert!(constant_time_eq(b"CP001:secret", b"CP001:secret"));
+        assert!(!constant_time_eq(b"CP001:secret", b"CP001:secreT"));
+        assert!(!constant_time_eq(b"CP001:secret", b"CP001:secret2"));
+        assert!(!constant_time_eq(b"", b"CP001:secret"));
+    }
+
+    #[test]
+    fn should_parse_and_serialize_frames() {
+        let call =
+            Frame::parse(r#"[2,"19223201","BootNotification",{"chargePointVendor":"VendorX"}]"#)
+                .expect("Cannot parse call");
+        assert_eq!(
+            call,
+            Frame::Call {
+                id: "19223201".into(),
+                action: "BootNotification".into(),
+                payload: json!({"chargePointVendor": "VendorX"}),
+            }
+        );
+        assert_eq!(Frame::parse(&call.to_text()).unwrap(), call);
+
+        let error = Frame::parse(r#"[4,"42","NotImplemented","Unknown action",{}]"#)
+            .expect("Cannot parse call error");
+        assert_eq!(
+            error,
+            Frame::CallError {
+                id: "42".into(),
+                code: "NotImplemented".into(),
+                description: "Unknown action".into(),
+            }
+        );
+        assert!(Frame::parse(r#"{"not":"an array"}"#).is_err());
+    }
+}
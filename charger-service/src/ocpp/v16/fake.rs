@@ -0,0 +1,352 @@
+//! A local OCPP 1.6 charge point, used to test the central system without hardware
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use super::messages::*;
+use super::PROTOCOL;
+use crate::ocpp::rpc::{self, Endpoint, Handler, RpcError};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct FakeState {
+    /// Energy register of each connector, in Wh
+    meters: HashMap<u32, i64>,
+    /// Running transaction of each connector
+    transactions: HashMap<u32, i32>,
+    reject_remote_start: bool,
+    reject_charging_profiles: bool,
+    /// Purpose and limit (in W) of the charging profile of each connector, 0 for the charge point
+    charging_profiles: HashMap<u32, (ChargingProfilePurpose, f64)>,
+    /// Dataset signed by the meter, sent with the next StopTransaction of each connector
+    signed_data: HashMap<u32, String>,
+}
+
+/// Actions triggered by the central system, executed outside of the connection thread
+enum Command {
+    StartTransaction { connector_id: u32, id_tag: String },
+    StopTransaction { connector_id: u32, reason: String },
+}
+
+pub struct FakeChargePoint {
+    endpoint: Endpoint,
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeChargePoint {
+    /// Connect to the central system at `url` with `password`, and send the BootNotification
+    pub fn connect(url: &str, password: &str) -> Result<FakeChargePoint> {
+        let state = Arc::new(Mutex::new(FakeState::default()));
+        let (commands, receiver) = mpsc::channel();
+        let handler = CentralSystemHandler {
+            state: state.clone(),
+            commands,
+        };
+        let endpoint = Endpoint::spawn(rpc::connect(url, PROTOCOL, password)?, handler, TIMEOUT)?;
+
+        let command_endpoint = endpoint.clone();
+        let command_state = state.clone();
+        thread::spawn(move || {
+            for command in receiver {
+                if let Err(e) = execute(&command_endpoint, &command_state, command) {
+                    warn!("Fake charge point command failed: {}", e);
+                }
+            }
+        });
+
+        let boot = BootNotificationRequest {
+            charge_point_vendor: "Delmonicos".into(),
+            charge_point_model: "Fake".into(),
+        };
+        endpoint.call("BootNotification", serde_json::to_value(boot)?)?;
+        Ok(FakeChargePoint { endpoint, state })
+    }
+
+    pub fn is_charging(&self, connector_id: u32) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .transactions
+            .contains_key(&connector_id)
+    }
+
+    /// Make the next RemoteStartTransaction requests rejected
+    pub fn reject_remote_start(&self, reject: bool) {
+        self.state.lock().unwrap().reject_remote_start = reject;
+    }
+
+    /// Make the next SetChargingProfile requests rejected
+    pub fn reject_charging_profiles(&self, reject: bool) {
+        self.state.lock().unwrap().reject_charging_profiles = reject;
+    }
+
+    /// Purpose and limit of the charging profile set on `connector_id`, if any
+    pub fn charging_profile(&self, connector_id: u32) -> Option<(ChargingProfilePurpose, f64)> {
+        self.state
+            .lock()
+            .unwrap()
+            .charging_profiles
+            .get(&connector_id)
+            .copied()
+    }
+
+    /// Send an Authorize request, as if the RFID card `id_tag` was presented
+    pub fn authorize(&self, id_tag: &str) -> Result<AuthorizationStatus> {
+        let request = AuthorizeRequest {
+            id_tag: id_tag.to_string(),
+        };
+        let response: AuthorizeResponse = serde_json::from_value(
+            self.endpoint
+                .call("Authorize", serde_json::to_value(request)?)?,
+        )?;
+        Ok(response.id_tag_info.status)
+    }
+
+    /// Send `signed_data` as the signed meter value of the next StopTransaction of `connector_id`
+    pub fn sign_stop(&self, connector_id: u32, signed_data: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .signed_data
+            .insert(connector_id, signed_data.to_string());
+    }
+
+    /// Deliver `wh` to the vehicle plugged on `connector_id` and send the new meter values
+    pub fn deliver(&self, connector_id: u32, wh: i64) -> Result<()> {
+        let request = {
+            let mut state = self.state.lock().unwrap();
+            let meter = state.meters.entry(connector_id).or_default();
+            *meter += wh;
+            let value = meter.to_string();
+            MeterValuesRequest {
+                connector_id,
+                transaction_id: state.transactions.get(&connector_id).copied(),
+                meter_value: vec![MeterValue {
+                    timestamp: now(),
+                    sampled_value: vec![
+                        SampledValue {
+                            value,
+                            format: None,
+                            measurand: Some("Energy.Active.Import.Register".into()),
+                            unit: Some("Wh".into()),
+                        },
+                        SampledValue {
+                            value: "7.4".into(),
+                            format: None,
+                            measurand: Some("Power.Active.Import".into()),
+                            unit: Some("kW".into()),
+                        },
+                        SampledValue {
+                            value: "230".into(),
+                            format: None,
+                            measurand: Some("Voltage".into()),
+                            unit: Some("V".into()),
+                        },
+                        SampledValue {
+                            value: "32".into(),
+                            format: None,
+                            measurand: Some("Current.Import".into()),
+                            unit: Some("A".into()),
+                        },
+                    ],
+                }],
+            }
+        };
+        self.endpoint
+            .call("MeterValues", serde_json::to_value(request)?)
+            .map(|_| ())
+    }
+
+    /// Send a StatusNotification, eg. `Faulted` with error code `GroundFailure`.
+    /// `connector_id` 0 is the whole charge point
+    pub fn notify_status(&self, connector_id: u32, status: &str, error_code: &str) -> Result<()> {
+        self.endpoint
+            .call(
+                "StatusNotification",
+                json!({
+                    "connectorId": connector_id,
+                    "errorCode": error_code,
+                    "status": status,
+                    "timestamp": now(),
+                }),
+            )
+            .map(|_| ())
+    }
+
+    /// Stop the transaction of `connector_id` locally, as if the vehicle was unplugged
+    pub fn stop(&self, connector_id: u32) -> Result<()> {
+        execute(
+            &self.endpoint,
+            &self.state,
+            Command::StopTransaction {
+                connector_id,
+                reason: "EVDisconnected".into(),
+            },
+        )
+    }
+}
+
+fn execute(endpoint: &Endpoint, state: &Arc<Mutex<FakeState>>, command: Command) -> Result<()> {
+    match command {
+        Command::StartTransaction {
+            connector_id,
+            id_tag,
+        } => {
+            let meter_start = state
+                .lock()
+                .unwrap()
+                .meters
+                .get(&connector_id)
+                .copied()
+                .unwrap_or_default();
+            let request = StartTransactionRequest {
+                connector_id,
+                id_tag,
+                meter_start,
+                timestamp: now(),
+            };
+            let response: StartTransactionResponse = serde_json::from_value(
+                endpoint.call("StartTransaction", serde_json::to_value(request)?)?,
+            )?;
+            state
+                .lock()
+                .unwrap()
+                .transactions
+                .insert(connector_id, response.transaction_id);
+            Ok(())
+        }
+        Command::StopTransaction {
+            connector_id,
+            reason,
+        } => {
+            let (transaction_id, meter_stop, signed_data) = {
+                let mut state = state.lock().unwrap();
+                match state.transactions.get(&connector_id).copied() {
+                    None => return Err(anyhow!("No transaction running")),
+                    Some(transaction_id) => (
+                        transaction_id,
+                        state.meters.get(&connector_id).copied().unwrap_or_default(),
+                        state.signed_data.remove(&connector_id),
+                    ),
+                }
+            };
+            let request = StopTransactionRequest {
+                transaction_id,
+                meter_stop,
+                timestamp: now(),
+                reason: Some(reason),
+                transaction_data: signed_data
+                    .map(|value| MeterValue {
+                        timestamp: now(),
+                        sampled_value: vec![SampledValue {
+                            value,
+                            format: Some("SignedData".into()),
+                            measurand: None,
+                            unit: None,
+                        }],
+                    })
+                    .into_iter()
+                    .collect(),
+            };
+            endpoint.call("StopTransaction", serde_json::to_value(request)?)?;
+            state.lock().unwrap().transactions.remove(&connector_id);
+            Ok(())
+        }
+    }
+}
+
+/// Handles the calls initiated by the central system
+struct CentralSystemHandler {
+    state: Arc<Mutex<FakeState>>,
+    commands: mpsc::Sender<Command>,
+}
+
+impl CentralSystemHandler {
+    fn respond(&self, accepted: bool, command: Command) -> Value {
+        let status = if accepted && self.commands.send(command).is_ok() {
+            RemoteStartStopStatus::Accepted
+        } else {
+            RemoteStartStopStatus::Rejected
+        };
+        json!(RemoteStartStopResponse { status })
+    }
+
+    fn set_charging_profile(&mut self, request: SetChargingProfileRequest) -> Value {
+        let mut state = self.state.lock().unwrap();
+        let profile = request.cs_charging_profiles;
+        // A transaction profile only applies to the transaction running on the connector
+        let accepted = !state.reject_charging_profiles
+            && match profile.charging_profile_purpose {
+                ChargingProfilePurpose::TxProfile => {
+                    profile.transaction_id.is_some()
+                        && state.transactions.get(&request.connector_id)
+                            == profile.transaction_id.as_ref()
+                }
+                _ => true,
+            };
+        let status = match profile.charging_schedule.charging_schedule_period.first() {
+            Some(period) if accepted => {
+                state.charging_profiles.insert(
+                    request.connector_id,
+                    (profile.charging_profile_purpose, period.limit),
+                );
+                ChargingProfileStatus::Accepted
+            }
+            _ => ChargingProfileStatus::Rejected,
+        };
+        json!(SetChargingProfileResponse { status })
+    }
+}
+
+impl Handler for CentralSystemHandler {
+    fn handle_call(&mut self, action: &str, payload: Value) -> Result<Value, RpcError> {
+        match action {
+            "RemoteStartTransaction" => {
+                let request: RemoteStartTransactionRequest =
+                    serde_json::from_value(payload).map_err(RpcError::formation_violation)?;
+                let connector_id = request.connector_id.unwrap_or(1);
+                let accepted = {
+                    let state = self.state.lock().unwrap();
+                    !state.reject_remote_start && !state.transactions.contains_key(&connector_id)
+                };
+                Ok(self.respond(
+                    accepted,
+                    Command::StartTransaction {
+                        connector_id,
+                        id_tag: request.id_tag,
+                    },
+                ))
+            }
+            "RemoteStopTransaction" => {
+                let request: RemoteStopTransactionRequest =
+                    serde_json::from_value(payload).map_err(RpcError::formation_violation)?;
+                let connector_id = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .transactions
+                    .iter()
+                    .find(|(_, transaction_id)| **transaction_id == request.transaction_id)
+                    .map(|(connector_id, _)| *connector_id);
+                Ok(self.respond(
+                    connector_id.is_some(),
+                    Command::StopTransaction {
+                        connector_id: connector_id.unwrap_or_default(),
+                        reason: "Remote".into(),
+                    },
+                ))
+            }
+            "SetChargingProfile" => serde_json::from_value(payload)
+                .map(|request| self.set_charging_profile(request))
+                .map_err(RpcError::formation_violation),
+            _ => Err(RpcError::not_implemented(action)),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! OCPP 1.6 payloads used by the central system and the fake charge point
+use serde::{Deserialize, Serialize};
+
+use crate::ocpp::{scale_to_base_unit, ENERGY_REGISTER};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum AuthorizationStatus {
+    Accepted,
+    Blocked,
+    Expired,
+    Invalid,
+    ConcurrentTx,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum RemoteStartStopStatus {
+    Accepted,
+    Rejected,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ChargingProfileStatus {
+    Accepted,
+    Rejected,
+    NotSupported,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ChargingProfilePurpose {
+    /// Limit of the whole charge point, set on connector 0
+    ChargePointMaxProfile,
+    /// Default limit of the next transactions of a connector
+    TxDefaultProfile,
+    /// Limit of a running transaction
+    TxProfile,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ChargePointStatus {
+    Available,
+    Preparing,
+    Charging,
+    SuspendedEVSE,
+    SuspendedEV,
+    Finishing,
+    Reserved,
+    Unavailable,
+    Faulted,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdTagInfo {
+    pub status: AuthorizationStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizeRequest {
+    pub id_tag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizeResponse {
+    pub id_tag_info: IdTagInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootNotificationRequest {
+    pub charge_point_vendor: String,
+    pub charge_point_model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusNotificationRequest {
+    /// 0 for the status of the whole charge point
+    pub connector_id: u32,
+    pub error_code: String,
+    pub status: ChargePointStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteStartTransactionRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connector_id: Option<u32>,
+    pub id_tag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteStopTransactionRequest {
+    pub transaction_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteStartStopResponse {
+    pub status: RemoteStartStopStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartTransactionRequest {
+    pub connector_id: u32,
+    pub id_tag: String,
+    pub meter_start: i64,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartTransactionResponse {
+    pub transaction_id: i32,
+    pub id_tag_info: IdTagInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopTransactionRequest {
+    pub transaction_id: i32,
+    pub meter_stop: i64,
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transaction_data: Vec<MeterValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampledValue {
+    pub value: String,
+    /// Raw (default) or SignedData, the value being then a dataset signed by the meter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub measurand: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterValue {
+    pub timestamp: String,
+    pub sampled_value: Vec<SampledValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterValuesRequest {
+    pub connector_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<i32>,
+    pub meter_value: Vec<MeterValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingSchedulePeriod {
+    /// Start of the period, in seconds from the start of the schedule
+    pub start_period: i32,
+    pub limit: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingSchedule {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_schedule: Option<String>,
+    /// W or A
+    pub charging_rate_unit: String,
+    pub charging_schedule_period: Vec<ChargingSchedulePeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingProfile {
+    pub charging_profile_id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<i32>,
+    pub stack_level: u32,
+    pub charging_profile_purpose: ChargingProfilePurpose,
+    /// Absolute, Recurring or Relative
+    pub charging_profile_kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_to: Option<String>,
+    pub charging_schedule: ChargingSchedule,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetChargingProfileRequest {
+    /// 0 for the whole charge point
+    pub connector_id: u32,
+    pub cs_charging_profiles: ChargingProfile,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetChargingProfileResponse {
+    pub status: ChargingProfileStatus,
+}
+
+impl SampledValue {
+    /// Measurand of this sample, Energy.Active.Import.Register when omitted
+    pub fn measurand(&self) -> &str {
+        self.measurand.as_deref().unwrap_or(ENERGY_REGISTER)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.format.as_deref() == Some("SignedData")
+    }
+
+    /// Value of this sample in the base unit of its measurand (Wh, W, V, A)
+    pub fn base_value(&self) -> Option<f64> {
+        if self.is_signed() {
+            return None;
+        }
+        let value: f64 = self.value.parse().ok()?;
+        Some(scale_to_base_unit(value, self.unit.as_deref()))
+    }
+}
+
+impl MeterValue {
+    /// `(measurand, value)` pairs of this meter value, values being in their base unit
+    pub fn readings(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.sampled_value
+            .iter()
+            .filter_map(|sample| Some((sample.measurand(), sample.base_value()?)))
+    }
+
+    /// Datasets signed by the meter carried by this meter value
+    pub fn signed_data(&self) -> impl Iterator<Item = &String> {
+        self.sampled_value
+            .iter()
+            .filter(|sample| sample.is_signed())
+            .map(|sample| &sample.value)
+    }
+}
+
+pub fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
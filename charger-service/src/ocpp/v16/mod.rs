@@ -0,0 +1,912 @@
+//! OCPP 1.6-J central system: drives a single charge point through its WebSocket connection
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::rpc::{self, ChargePointCredentials, Endpoint, Handler, RpcError};
+use super::{to_rfc3339, update_sample};
+use crate::api::*;
+use crate::store::SessionStore;
+
+pub mod fake;
+mod messages;
+
+use messages::*;
+
+pub const PROTOCOL: &str = "ocpp1.6";
+
+const HEARTBEAT_INTERVAL: u32 = 300;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_ID_TAG: &str = "DELMONICOS";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Transaction {
+    /// RemoteStartTransaction accepted, StartTransaction not yet received
+    Requested,
+    Charging {
+        transaction_id: i32,
+        meter_start: i64,
+        /// Last reading reported by MeterValues, not persisted
+        #[serde(skip)]
+        meter: Option<MeterSample>,
+        /// Datasets signed by the meter reported so far (eg. the start reading)
+        #[serde(default)]
+        signed_data: Vec<String>,
+    },
+    Finished {
+        meter_start: i64,
+        meter_stop: i64,
+        #[serde(default)]
+        signed_data: Vec<String>,
+    },
+}
+
+/// The transactions are persisted in `store`
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    #[serde(skip)]
+    endpoint: Option<Endpoint>,
+    transactions: HashMap<ConnectorId, Transaction>,
+    /// Last StatusNotification of each connector
+    #[serde(skip)]
+    statuses: HashMap<ConnectorId, StatusNotificationRequest>,
+    next_transaction_id: i32,
+    /// Id tags presented to the charge point, until they are taken by the runtime
+    #[serde(skip)]
+    presented_tokens: Vec<PresentedToken>,
+    #[serde(skip)]
+    store: SessionStore,
+}
+
+impl State {
+    fn save(&self) {
+        self.store.save_or_warn(self);
+    }
+
+    /// Status of `connector`: a fault or unavailability of the whole charge point (connector 0)
+    /// applies to every connector
+    fn status(&self, connector: ConnectorId) -> Option<&StatusNotificationRequest> {
+        match self.statuses.get(&0) {
+            Some(status)
+                if matches!(
+                    status.status,
+                    ChargePointStatus::Faulted | ChargePointStatus::Unavailable
+                ) =>
+            {
+                Some(status)
+            }
+            _ => self.statuses.get(&connector),
+        }
+    }
+
+    /// Connector where an id tag is presented, which Authorize does not tell: the connector
+    /// whose vehicle waits for an authorization, else the first one
+    fn authorizing_connector(&self) -> ConnectorId {
+        self.statuses
+            .values()
+            .filter(|status| {
+                status.status == ChargePointStatus::Preparing
+                    && !self.transactions.contains_key(&status.connector_id)
+            })
+            .map(|status| status.connector_id)
+            .min()
+            .unwrap_or(1)
+    }
+}
+
+/// Charge status of a connector which has no session
+fn idle_status(status: Option<&StatusNotificationRequest>) -> ChargeStatus {
+    match status.map(|status| (status.status, &status.error_code)) {
+        Some((ChargePointStatus::Faulted, error_code)) => ChargeStatus::Faulted {
+            error_code: error_code.clone(),
+            meter: None,
+        },
+        Some((ChargePointStatus::Unavailable, _)) => ChargeStatus::Unavailable,
+        _ => ChargeStatus::NotFound,
+    }
+}
+
+/// Charge status of a connector running a session, `meter` being the last reading of the session
+fn session_status(
+    status: Option<&StatusNotificationRequest>,
+    meter: Option<MeterSample>,
+) -> ChargeStatus {
+    match status.map(|status| (status.status, &status.error_code)) {
+        Some((ChargePointStatus::Faulted, error_code)) => ChargeStatus::Faulted {
+            error_code: error_code.clone(),
+            meter,
+        },
+        Some((ChargePointStatus::SuspendedEV, _)) => ChargeStatus::SuspendedEV { meter },
+        Some((ChargePointStatus::SuspendedEVSE, _)) => ChargeStatus::SuspendedEVSE { meter },
+        _ => ChargeStatus::Active { meter },
+    }
+}
+
+/// Likely reason why the charge point rejected a RemoteStartTransaction, from the last
+/// status of the connector: an idle connector has no vehicle plugged in
+fn rejection_reason(status: Option<&StatusNotificationRequest>) -> ChargerError {
+    match status.map(|status| status.status) {
+        Some(ChargePointStatus::Faulted) | Some(ChargePointStatus::Unavailable) => {
+            ChargerError::Faulted
+        }
+        Some(ChargePointStatus::Available) => ChargerError::NotPlugged,
+        _ => ChargerError::Rejected,
+    }
+}
+
+pub struct CentralSystem {
+    state: Arc<Mutex<State>>,
+    local_addr: SocketAddr,
+    id_tag: String,
+}
+
+impl CentralSystem {
+    /// Listen on `address` (eg. `0.0.0.0:9000`) for the connection of the charge point of
+    /// `credentials`
+    pub fn listen(address: &str, credentials: ChargePointCredentials) -> Result<CentralSystem> {
+        let state = Arc::new(Mutex::new(State::default()));
+        let server_state = state.clone();
+        let local_addr = rpc::listen(address, PROTOCOL, credentials, move |identity, socket| {
+            info!("Charge point {} connected", identity);
+            let handler = ChargePointHandler {
+                state: server_state.clone(),
+            };
+            match Endpoint::spawn(socket, handler, DEFAULT_TIMEOUT) {
+                // A new connection of the charge point replaces the previous one
+                Ok(endpoint) => server_state.lock().unwrap().endpoint = Some(endpoint),
+                Err(e) => warn!("Cannot handle connection of {}: {}", identity, e),
+            }
+        })?;
+        info!("OCPP 1.6 central system listening on {}", local_addr);
+        Ok(CentralSystem {
+            state,
+            local_addr,
+            id_tag: DEFAULT_ID_TAG.to_string(),
+        })
+    }
+
+    /// Persist the transactions in `store`, and resume the transactions saved there
+    pub fn with_store(self, store: SessionStore) -> CentralSystem {
+        {
+            let mut state = self.state.lock().unwrap();
+            let saved: State = store.load();
+            state.transactions = saved.transactions;
+            state.next_transaction_id = saved.next_transaction_id;
+            state.store = store;
+        }
+        self
+    }
+
+    /// Id tag sent in RemoteStartTransaction requests
+    pub fn with_id_tag(mut self, id_tag: &str) -> CentralSystem {
+        self.id_tag = id_tag.to_string();
+        self
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .endpoint
+            .as_ref()
+            .map(Endpoint::is_connected)
+            .unwrap_or(false)
+    }
+
+    fn endpoint(&self) -> Result<Endpoint> {
+        match &self.state.lock().unwrap().endpoint {
+            Some(endpoint) if endpoint.is_connected() => Ok(endpoint.clone()),
+            _ => Err(ChargerError::Unreachable.with_message("No charge point connected")),
+        }
+    }
+}
+
+impl ChargerApi for CentralSystem {
+    fn start_new_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        let endpoint = self.endpoint()?;
+        {
+            let mut state = self.state.lock().unwrap();
+            match state.transactions.get(&connector) {
+                Some(Transaction::Requested) | Some(Transaction::Charging { .. }) => {
+                    return Err(ChargerError::Busy.with_message(format!(
+                        "Connector {} already has an active session",
+                        connector
+                    )));
+                }
+                _ => {}
+            }
+            // Registered before the call: StartTransaction may be received before the response
+            state.transactions.insert(connector, Transaction::Requested);
+            state.save();
+        }
+
+        let request = RemoteStartTransactionRequest {
+            connector_id: Some(connector),
+            id_tag: self.id_tag.clone(),
+        };
+        let response = endpoint
+            .call("RemoteStartTransaction", serde_json::to_value(request)?)
+            .and_then(|value| Ok(serde_json::from_value::<RemoteStartStopResponse>(value)?));
+
+        match response {
+            Ok(RemoteStartStopResponse {
+                status: RemoteStartStopStatus::Accepted,
+            }) => {
+                debug!("RemoteStartTransaction accepted");
+                Ok(())
+            }
+            other => {
+                let mut state = self.state.lock().unwrap();
+                if state.transactions.get(&connector) == Some(&Transaction::Requested) {
+                    state.transactions.remove(&connector);
+                    state.save();
+                }
+                match other {
+                    Err(e) => Err(e.context(ChargerError::Unreachable)),
+                    _ => Err(rejection_reason(state.status(connector))
+                        .with_message("Charge point rejected RemoteStartTransaction")),
+                }
+            }
+        }
+    }
+
+    /// Sent to the charge point as RemoteStopTransaction
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        let endpoint = self.endpoint()?;
+        let transaction_id = match self.state.lock().unwrap().transactions.get(&connector) {
+            Some(Transaction::Charging { transaction_id, .. }) => *transaction_id,
+            _ => {
+                return Err(ChargerError::NoSession
+                    .with_message(format!("No transaction running on connector {}", connector)))
+            }
+        };
+        let request = RemoteStopTransactionRequest { transaction_id };
+        let response: RemoteStartStopResponse = serde_json::from_value(
+            endpoint
+                .call("RemoteStopTransaction", serde_json::to_value(request)?)
+                .context(ChargerError::Unreachable)?,
+        )?;
+        match response.status {
+            RemoteStartStopStatus::Accepted => Ok(()),
+            RemoteStartStopStatus::Rejected => {
+                Err(ChargerError::Rejected
+                    .with_message("Charge point rejected RemoteStopTransaction"))
+            }
+        }
+    }
+
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> Result<ChargeStatus> {
+        let mut state = self.state.lock().unwrap();
+        let connector_status = state.status(connector);
+        let status = match state.transactions.get(&connector) {
+            None => idle_status(connector_status),
+            Some(Transaction::Requested) => session_status(connector_status, None),
+            Some(Transaction::Charging { meter, .. }) => {
+                session_status(connector_status, meter.clone())
+            }
+            Some(Transaction::Finished {
+                meter_start,
+                meter_stop,
+                signed_data,
+            }) => {
+                // The meter values are in Wh
+                let kwh = (meter_stop - meter_start).max(0) as u64 / 1000;
+                let signed_data = signed_data.clone();
+                state.transactions.remove(&connector);
+                state.save();
+                info!("Charge is ended on connector {}, kwh: {}", connector, kwh);
+                ChargeStatus::Ended { kwh, signed_data }
+            }
+        };
+        Ok(status)
+    }
+
+    /// Sent to the charge point as SetChargingProfile: the limit of the whole charge point
+    /// (connector 0), of the transaction running on the connector, or else the default limit
+    /// of its next transactions
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        watts: u32,
+        valid_until: Option<u64>,
+    ) -> Result<()> {
+        let endpoint = self.endpoint()?;
+        let transaction_id = match self.state.lock().unwrap().transactions.get(&connector) {
+            Some(Transaction::Charging { transaction_id, .. }) => Some(*transaction_id),
+            _ => None,
+        };
+        let purpose = match (connector, transaction_id) {
+            (0, _) => ChargingProfilePurpose::ChargePointMaxProfile,
+            (_, Some(_)) => ChargingProfilePurpose::TxProfile,
+            (_, None) => ChargingProfilePurpose::TxDefaultProfile,
+        };
+        let request = SetChargingProfileRequest {
+            connector_id: connector,
+            cs_charging_profiles: ChargingProfile {
+                // One profile per connector, replaced by the next limit
+                charging_profile_id: connector as i32 + 1,
+                transaction_id,
+                stack_level: 0,
+                charging_profile_purpose: purpose,
+                charging_profile_kind: "Absolute".into(),
+                valid_to: valid_until.map(to_rfc3339),
+                charging_schedule: ChargingSchedule {
+                    start_schedule: Some(now()),
+                    charging_rate_unit: "W".into(),
+                    charging_schedule_period: vec![ChargingSchedulePeriod {
+                        start_period: 0,
+                        limit: watts as f64,
+                    }],
+                },
+            },
+        };
+        let response: SetChargingProfileResponse = serde_json::from_value(
+            endpoint
+                .call("SetChargingProfile", serde_json::to_value(request)?)
+                .context(ChargerError::Unreachable)?,
+        )?;
+        match response.status {
+            ChargingProfileStatus::Accepted => {
+                info!("Power of connector {} limited to {} W", connector, watts);
+                Ok(())
+            }
+            status => Err(ChargerError::Rejected.with_message(format!(
+                "Charge point rejected SetChargingProfile: {:?}",
+                status
+            ))),
+        }
+    }
+
+    fn take_presented_tokens(&mut self) -> Result<Vec<PresentedToken>> {
+        Ok(std::mem::take(
+            &mut self.state.lock().unwrap().presented_tokens,
+        ))
+    }
+
+    /// Plug & Charge is not supported with OCPP 1.6
+    fn take_contract_authorizations(&mut self) -> Result<Vec<ContractAuthorization>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Handles the calls initiated by the charge point
+struct ChargePointHandler {
+    state: Arc<Mutex<State>>,
+}
+
+impl ChargePointHandler {
+    /// The id tag is accepted: it is checked on-chain, where the session is requested on
+    /// behalf of its owner
+    fn authorize(&mut self, request: AuthorizeRequest) -> Value {
+        let mut state = self.state.lock().unwrap();
+        let connector = state.authorizing_connector();
+        info!(
+            "Id tag {} presented on connector {}",
+            request.id_tag, connector
+        );
+        state.presented_tokens.push(PresentedToken {
+            connector,
+            token: request.id_tag.into_bytes(),
+        });
+        json!(AuthorizeResponse {
+            id_tag_info: IdTagInfo {
+                status: AuthorizationStatus::Accepted,
+            },
+        })
+    }
+
+    fn start_transaction(&mut self, request: StartTransactionRequest) -> Value {
+        let mut state = self.state.lock().unwrap();
+        state.next_transaction_id += 1;
+        let transaction_id = state.next_transaction_id;
+        info!(
+            "Transaction {} started on connector {}, meter start: {}",
+            transaction_id, request.connector_id, request.meter_start
+        );
+        state.transactions.insert(
+            request.connector_id,
+            Transaction::Charging {
+                transaction_id,
+                meter_start: request.meter_start,
+                meter: None,
+                signed_data: Vec::new(),
+            },
+        );
+        state.save();
+        json!(StartTransactionResponse {
+            transaction_id,
+            id_tag_info: IdTagInfo {
+                status: AuthorizationStatus::Accepted,
+            },
+        })
+    }
+
+    fn status_notification(&mut self, request: StatusNotificationRequest) -> Value {
+        if request.status == ChargePointStatus::Faulted {
+            warn!(
+                "Connector {} is faulted: {}",
+                request.connector_id, request.error_code
+            );
+        }
+        let mut state = self.state.lock().unwrap();
+        state.statuses.insert(request.connector_id, request);
+        json!({})
+    }
+
+    fn meter_values(&mut self, request: MeterValuesRequest) -> Value {
+        let mut state = self.state.lock().unwrap();
+        if let Some(Transaction::Charging {
+            meter_start,
+            meter,
+            signed_data,
+            ..
+        }) = state.transactions.get_mut(&request.connector_id)
+        {
+            let sample = meter.get_or_insert_with(MeterSample::default);
+            for value in &request.meter_value {
+                update_sample(sample, *meter_start, &value.timestamp, value.readings());
+            }
+            debug!(
+                "Meter values on connector {}: {:?}",
+                request.connector_id, sample
+            );
+            let signed = signed_data.len();
+            signed_data.extend(
+                request
+                    .meter_value
+                    .iter()
+                    .flat_map(MeterValue::signed_data)
+                    .cloned(),
+            );
+            if signed_data.len() != signed {
+                state.save();
+            }
+        }
+        json!({})
+    }
+
+    fn stop_transaction(&mut self, request: StopTransactionRequest) -> Value {
+        let mut state = self.state.lock().unwrap();
+        let connector = state
+            .transactions
+            .iter()
+            .find_map(|(connector, transaction)| match transaction {
+                Transaction::Charging { transaction_id, .. }
+                    if *transaction_id == request.transaction_id =>
+                {
+                    Some(*connector)
+                }
+                _ => None,
+            });
+        match connector {
+            None => warn!(
+                "StopTransaction for unknown transaction {}",
+                request.transaction_id
+            ),
+            Some(connector) => {
+                if let Some(Transaction::Charging {
+                    meter_start,
+                    mut signed_data,
+                    ..
+                }) = state.transactions.remove(&connector)
+                {
+                    info!(
+                        "Transaction {} stopped, meter stop: {}",
+                        request.transaction_id, request.meter_stop
+                    );
+                    signed_data.extend(
+                        request
+                            .transaction_data
+                            .iter()
+                            .flat_map(MeterValue::signed_data)
+                            .cloned(),
+                    );
+                    state.transactions.insert(
+                        connector,
+                        Transaction::Finished {
+                            meter_start,
+                            meter_stop: request.meter_stop,
+                            signed_data,
+                        },
+                    );
+                    state.save();
+                }
+            }
+        }
+        json!({ "idTagInfo": IdTagInfo { status: AuthorizationStatus::Accepted } })
+    }
+}
+
+impl Handler for ChargePointHandler {
+    fn handle_call(&mut self, action: &str, payload: Value) -> Result<Value, RpcError> {
+        match action {
+            "BootNotification" => Ok(json!({
+                "status": "Accepted",
+                "currentTime": now(),
+                "interval": HEARTBEAT_INTERVAL,
+            })),
+            "Heartbeat" => Ok(json!({ "currentTime": now() })),
+            "StatusNotification" => serde_json::from_value(payload)
+                .map(|request| self.status_notification(request))
+                .map_err(RpcError::formation_violation),
+            "Authorize" => serde_json::from_value(payload)
+                .map(|request| self.authorize(request))
+                .map_err(RpcError::formation_violation),
+            "StartTransaction" => serde_json::from_value(payload)
+                .map(|request| self.start_transaction(request))
+                .map_err(RpcError::formation_violation),
+            "MeterValues" => serde_json::from_value(payload)
+                .map(|request| self.meter_values(request))
+                .map_err(RpcError::formation_violation),
+            "StopTransaction" => serde_json::from_value(payload)
+                .map(|request| self.stop_transaction(request))
+                .map_err(RpcError::formation_violation),
+            _ => Err(RpcError::not_implemented(action)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        fake::FakeChargePoint,
+        messages::{AuthorizationStatus, ChargingProfilePurpose},
+        CentralSystem, ChargePointCredentials,
+    };
+    use crate::api::{ChargeStatus, ChargerApi, ChargerError, PresentedToken};
+    use crate::store::SessionStore;
+    use std::time::{Duration, Instant};
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(
+                Instant::now() < deadline,
+                "Timeout while waiting for condition"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn credentials() -> ChargePointCredentials {
+        ChargePointCredentials {
+            identity: "CP001".into(),
+            password: "0123456789abcdef".into(),
+        }
+    }
+
+    fn connect() -> (CentralSystem, FakeChargePoint) {
+        let central_system =
+            CentralSystem::listen("127.0.0.1:0", credentials()).expect("Cannot listen");
+        let url = format!("ws://{}/ocpp/CP001", central_system.local_addr());
+        let charge_point = FakeChargePoint::connect(&url, "0123456789abcdef")
+            .expect("Cannot connect charge point");
+        wait_until(|| central_system.is_connected());
+        (central_system, charge_point)
+    }
+
+    #[test]
+    fn should_fail_without_charge_point() {
+        let mut central_system =
+            CentralSystem::listen("127.0.0.1:0", credentials()).expect("Cannot listen");
+        assert!(central_system.start_new_charge(1).is_err());
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_resume_persisted_transactions() {
+        let path =
+            std::env::temp_dir().join(format!("ocpp16-sessions-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (central_system, charge_point) = connect();
+        let mut central_system = central_system.with_store(SessionStore::open(&path));
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        wait_until(|| charge_point.is_charging(1));
+
+        // The node restarts while the charge point goes on charging
+        let mut central_system = CentralSystem::listen("127.0.0.1:0", credentials())
+            .expect("Cannot listen")
+            .with_store(SessionStore::open(&path));
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { meter: None }
+        );
+        assert!(central_system.start_new_charge(1).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn should_start_and_end_a_charge() {
+        let (mut central_system, charge_point) = connect();
+
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        wait_until(|| charge_point.is_charging(1));
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { meter: None }
+        );
+
+        charge_point
+            .deliver(1, 1200)
+            .expect("Cannot send meter values");
+        match central_system.get_current_charge_status(1).unwrap() {
+            ChargeStatus::Active { meter: Some(meter) } => {
+                assert_eq!(meter.energy_wh, 1200);
+                assert_eq!(meter.power_w, 7400);
+                assert_eq!(meter.voltage_mv, 230_000);
+                assert_eq!(meter.current_ma, 32_000);
+                assert!(meter.timestamp > 0);
+            }
+            other => panic!("Unexpected status {:?}", other),
+        }
+        charge_point
+            .deliver(1, 800)
+            .expect("Cannot send meter values");
+        charge_point.stop(1).expect("Cannot stop transaction");
+
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 2,
+                signed_data: vec![]
+            }
+        );
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_report_signed_meter_data() {
+        let (mut central_system, charge_point) = connect();
+        let signed_data = r#"OCMF|{"RD":[{"TX":"E","RV":1.5,"RU":"kWh"}]}|{"SD":"3045"}"#;
+
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        wait_until(|| charge_point.is_charging(1));
+        charge_point
+            .deliver(1, 1500)
+            .expect("Cannot send meter values");
+        charge_point.sign_stop(1, signed_data);
+        charge_point.stop(1).expect("Cannot stop transaction");
+
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 1,
+                signed_data: vec![signed_data.to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn should_stop_a_charge_remotely() {
+        let (mut central_system, charge_point) = connect();
+
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        wait_until(|| charge_point.is_charging(1));
+        charge_point
+            .deliver(1, 2500)
+            .expect("Cannot send meter values");
+
+        central_system
+            .stop_charge(1)
+            .expect("Cannot stop transaction");
+        wait_until(|| !charge_point.is_charging(1));
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 2,
+                signed_data: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn should_not_start_twice() {
+        let (mut central_system, _charge_point) = connect();
+
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        assert!(central_system.start_new_charge(1).is_err());
+    }
+
+    #[test]
+    fn should_charge_on_several_connectors() {
+        let (mut central_system, charge_point) = connect();
+
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        central_system
+            .start_new_charge(2)
+            .expect("Cannot start new charge");
+        wait_until(|| charge_point.is_charging(1) && charge_point.is_charging(2));
+
+        charge_point
+            .deliver(1, 1000)
+            .expect("Cannot send meter values");
+        charge_point
+            .deliver(2, 4000)
+            .expect("Cannot send meter values");
+        charge_point.stop(2).expect("Cannot stop transaction");
+
+        assert!(matches!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { meter: Some(meter) } if meter.energy_wh == 1000
+        ));
+        assert_eq!(
+            central_system.get_current_charge_status(2).unwrap(),
+            ChargeStatus::Ended {
+                kwh: 4,
+                signed_data: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn should_report_rejected_remote_start() {
+        let (mut central_system, charge_point) = connect();
+        charge_point.reject_remote_start(true);
+
+        assert!(central_system.start_new_charge(1).is_err());
+        assert_eq!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn should_report_suspensions_and_faults() {
+        let (mut central_system, charge_point) = connect();
+
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        wait_until(|| charge_point.is_charging(1));
+        charge_point
+            .deliver(1, 300)
+            .expect("Cannot send meter values");
+
+        charge_point
+            .notify_status(1, "SuspendedEV", "NoError")
+            .expect("Cannot send status");
+        assert!(matches!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::SuspendedEV { meter: Some(meter) } if meter.energy_wh == 300
+        ));
+
+        charge_point
+            .notify_status(1, "Charging", "NoError")
+            .expect("Cannot send status");
+        assert!(matches!(
+            central_system.get_current_charge_status(1).unwrap(),
+            ChargeStatus::Active { .. }
+        ));
+
+        charge_point
+            .notify_status(1, "Faulted", "GroundFailure")
+            .expect("Cannot send status");
+        match central_system.get_current_charge_status(1).unwrap() {
+            ChargeStatus::Faulted { error_code, meter } => {
+                assert_eq!(error_code, "GroundFailure");
+                assert_eq!(meter.map(|meter| meter.energy_wh), Some(300));
+            }
+            other => panic!("Unexpected status {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_set_charging_profiles() {
+        let (mut central_system, charge_point) = connect();
+
+        central_system
+            .set_power_limit(0, 22_000, Some(1_700_000_000_000))
+            .expect("Cannot limit power");
+        assert_eq!(
+            charge_point.charging_profile(0),
+            Some((ChargingProfilePurpose::ChargePointMaxProfile, 22_000.0))
+        );
+
+        // The limit of a connector applies to its running transaction
+        central_system
+            .set_power_limit(1, 7400, None)
+            .expect("Cannot limit power");
+        assert_eq!(
+            charge_point.charging_profile(1),
+            Some((ChargingProfilePurpose::TxDefaultProfile, 7400.0))
+        );
+        central_system
+            .start_new_charge(1)
+            .expect("Cannot start new charge");
+        wait_until(|| charge_point.is_charging(1));
+        central_system
+            .set_power_limit(1, 3700, None)
+            .expect("Cannot limit power");
+        assert_eq!(
+            charge_point.charging_profile(1),
+            Some((ChargingProfilePurpose::TxProfile, 3700.0))
+        );
+
+        charge_point.reject_charging_profiles(true);
+        let error = central_system
+            .set_power_limit(1, 1000, None)
+            .unwrap_err()
+            .downcast::<ChargerError>()
+            .expect("Not a ChargerError");
+        assert_eq!(error, ChargerError::Rejected);
+    }
+
+    #[test]
+    fn should_report_presented_id_tags() {
+        let (mut central_system, charge_point) = connect();
+
+        // Without a vehicle waiting, the id tag is reported on the first connector
+        assert_eq!(
+            charge_point.authorize("04A2B3C4").unwrap(),
+            AuthorizationStatus::Accepted
+        );
+        charge_point
+            .notify_status(2, "Preparing", "NoError")
+            .unwrap();
+        charge_point.authorize("04D5E6F7").unwrap();
+        assert_eq!(
+            central_system.take_presented_tokens().unwrap(),
+            vec![
+                PresentedToken {
+                    connector: 1,
+                    token: b"04A2B3C4".to_vec(),
+                },
+                PresentedToken {
+                    connector: 2,
+                    token: b"04D5E6F7".to_vec(),
+                },
+            ]
+        );
+        assert_eq!(central_system.take_presented_tokens().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn should_report_unavailable_charge_point() {
+        let (mut central_system, charge_point) = connect();
+
+        charge_point
+            .notify_status(0, "Unavailable", "NoError")
+            .expect("Cannot send status");
+        assert_eq!(
+            central_system.get_current_charge_status(2).unwrap(),
+            ChargeStatus::Unavailable
+        );
+
+        charge_point
+            .notify_status(0, "Available", "NoError")
+            .expect("Cannot send status");
+        assert_eq!(
+            central_system.get_current_charge_status(2).unwrap(),
+            ChargeStatus::NotFound
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! OCPP 2.0.1 payloads used by the CSMS and the scripted charging station
+use serde::{Deserialize, Serialize};
+
+use crate::ocpp::{scale_to_base_unit, ENERGY_REGISTER};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum RequestStartStopStatus {
+    Accepted,
+    Rejected,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum AuthorizationStatus {
+    Accepted,
+    Blocked,
+    Expired,
+    Invalid,
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ChargingProfileStatus {
+    Accepted,
+    Rejected,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ChargingProfilePurpose {
+    /// Limit of the whole charging station, set on EVSE 0
+    ChargingStationMaxProfile,
+    /// Default limit of the next transactions of an EVSE
+    TxDefaultProfile,
+    /// Limit of a running transaction
+    TxProfile,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum TransactionEventType {
+    Started,
+    Updated,
+    Ended,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ConnectorStatus {
+    Available,
+    Occupied,
+    Reserved,
+    Unavailable,
+    Faulted,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusNotificationRequest {
+    pub timestamp: String,
+    pub connector_status: ConnectorStatus,
+    pub evse_id: u32,
+    pub connector_id: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IdToken {
+    pub id_token: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdTokenInfo {
+    pub status: AuthorizationStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizeRequest {
+    pub id_token: IdToken,
+    /// PEM encoded chain of the contract certificate presented by the vehicle (Plug & Charge)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizeResponse {
+    pub id_token_info: IdTokenInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Evse {
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connector_id: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestStartTransactionRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evse_id: Option<u32>,
+    pub remote_start_id: i32,
+    pub id_token: IdToken,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestStartTransactionResponse {
+    pub status: RequestStartStopStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestStopTransactionRequest {
+    pub transaction_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestStopTransactionResponse {
+    pub status: RequestStartStopStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitOfMeasure {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiplier: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SampledValue {
+    pub value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub measurand: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_of_measure: Option<UnitOfMeasure>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signed_meter_value: Option<SignedMeterValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedMeterValue {
+    /// Dataset signed by the meter, base64 encoded
+    pub signed_meter_data: String,
+    pub signing_method: String,
+    /// Format of the dataset, eg. OCMF
+    pub encoding_method: String,
+    #[serde(default)]
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterValue {
+    pub timestamp: String,
+    pub sampled_value: Vec<SampledValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionInfo {
+    pub transaction_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charging_state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_start_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionEventRequest {
+    pub event_type: TransactionEventType,
+    pub timestamp: String,
+    pub trigger_reason: String,
+    pub seq_no: u32,
+    pub transaction_info: TransactionInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evse: Option<Evse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<IdToken>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub meter_value: Vec<MeterValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingSchedulePeriod {
+    /// Start of the period, in seconds from the start of the schedule
+    pub start_period: i32,
+    pub limit: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingSchedule {
+    pub id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_schedule: Option<String>,
+    /// W or A
+    pub charging_rate_unit: String,
+    pub charging_schedule_period: Vec<ChargingSchedulePeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargingProfile {
+    pub id: i32,
+    pub stack_level: u32,
+    pub charging_profile_purpose: ChargingProfilePurpose,
+    /// Absolute, Recurring or Relative
+    pub charging_profile_kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    pub charging_schedule: Vec<ChargingSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetChargingProfileRequest {
+    /// 0 for the whole charging station
+    pub evse_id: u32,
+    pub charging_profile: ChargingProfile,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetChargingProfileResponse {
+    pub status: ChargingProfileStatus,
+}
+
+impl SampledValue {
+    /// Measurand of this sample, Energy.Active.Import.Register when omitted
+    pub fn measurand(&self) -> &str {
+        self.measurand.as_deref().unwrap_or(ENERGY_REGISTER)
+    }
+
+    /// Value of this sample in the base unit of its measurand (Wh, W, V, A)
+    pub fn base_value(&self) -> f64 {
+        let unit = self.unit_of_measure.clone().unwrap_or_default();
+        let multiplier = 10f64.powi(unit.multiplier.unwrap_or(0));
+        scale_to_base_unit(self.value * multiplier, unit.unit.as_deref())
+    }
+
+    /// Energy register value in Wh, if this sample is the active energy import register
+    pub fn energy_wh(&self) -> Option<i64> {
+        if self.measurand() != ENERGY_REGISTER {
+            return None;
+        }
+        Some(self.base_value().round() as i64)
+    }
+}
+
+impl MeterValue {
+    /// `(measurand, value)` pairs of this meter value, values being in their base unit
+    pub fn readings(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.sampled_value
+            .iter()
+            .map(|sample| (sample.measurand(), sample.base_value()))
+    }
+
+    /// Datasets signed by the meter carried by this meter value
+    pub fn signed_data(&self) -> impl Iterator<Item = String> + '_ {
+        self.sampled_value
+            .iter()
+            .filter_map(|sample| sample.signed_meter_value.as_ref())
+            .map(SignedMeterValue::data)
+    }
+}
+
+impl SignedMeterValue {
+    /// Decoded dataset, or the dataset as sent when it is not base64 encoded
+    pub fn data(&self) -> String {
+        base64::decode(&self.signed_meter_data)
+            .ok()
+            .and_then(|data| String::from_utf8(data).ok())
+            .unwrap_or_else(|| self.signed_meter_data.clone())
+    }
+}
+
+impl AuthorizeRequest {
+    /// DER encoded certificates of the PEM chain, `None` if a certificate cannot be decoded
+    pub fn certificate_chain(&self) -> Option<Vec<Vec<u8>>> {
+        let pem = self.certificate.as_deref().unwrap_or_default();
+        pem.split("-----BEGIN CERTIFICATE-----")
+            .skip(1)
+            .map(|block| {
+                let end = block.find("-----END CERTIFICATE-----")?;
+                let base64: String = block[..end].split_whitespace().collect();
+                base64::decode(base64).ok()
+            })
+            .collect()
+    }
+}
+
+impl TransactionEventRequest {
+    /// Last energy register value carried by this event, in Wh
+    pub fn energy_wh(&self) -> Option<i64> {
+        self.meter_value
+            .iter()
+            .flat_map(|value| value.sampled_value.iter())
+            .filter_map(SampledValue::energy_wh)
+            .next_back()
+    }
+}
+
+pub fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
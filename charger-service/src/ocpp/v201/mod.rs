@@ -0,0 +1,662 @@
+//! OCPP 2.0.1 CSMS: drives a single charging station through its WebSocket connection
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::rpc::{self, ChargePointCredentials, Endpoint, Handler, RpcError};
+use super::{to_rfc3339, update_sample};
+use crate::api::*;
+use crate::store::SessionStore;
+
+mod messages;
+pub mod station;
+
+#[cfg(test)]
+mod tests;
+
+use messages::*;
+
+pub const PROTOCOL: &str = "ocpp2.0.1";
+
+/// EVSE of the transaction events which do not specify one
+const DEFAULT_EVSE_ID: u32 = 1;
+const HEARTBEAT_INTERVAL: u32 = 300;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_ID_TOKEN: &str = "DELMONICOS";
+/// StatusNotification carries no error code in OCPP 2.0.1 (faults are detailed by NotifyEvent)
+const FAULT_ERROR_CODE: &str = "OtherError";
+/// Error code of a transaction which ended without any energy reading: it cannot be billed
+const NO_METER_VALUES_ERROR_CODE: &str = "NoMeterValues";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Transaction {
+    /// RequestStartTransaction sent, the transaction is not yet reported by the station
+    Requested { remote_start_id: i32 },
+    /// Transaction reported by the station. It is not ours until `requested` is set:
+    /// a station may start a transaction when the cable is plugged, before any authorization
+    Running {
+        transaction_id: String,
+        requested: bool,
+        meter_start: Option<i64>,
+        meter_value: Option<i64>,
+        /// Last reading carried by the transaction events, not persisted
+        #[serde(skip)]
+        meter: Option<MeterSample>,
+        /// Last charging state of the transaction events (Charging, SuspendedEV...)
+        charging_state: Option<String>,
+        /// Datasets signed by the meter reported so far (eg. the start reading)
+        #[serde(default)]
+        signed_data: Vec<String>,
+    },
+    /// `meter_start` is unknown if the transaction reported no energy reading before it ended
+    Ended {
+        meter_start: Option<i64>,
+        meter_stop: Option<i64>,
+        #[serde(default)]
+        signed_data: Vec<String>,
+    },
+}
+
+/// The transactions are persisted in `store`
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    #[serde(skip)]
+    endpoint: Option<Endpoint>,
+    transactions: HashMap<ConnectorId, Transaction>,
+    /// Last connector status of each EVSE
+    #[serde(skip)]
+    statuses: HashMap<ConnectorId, ConnectorStatus>,
+    next_remote_start_id: i32,
+    /// Id tokens presented to the station, until they are taken by the runtime
+    #[serde(skip)]
+    presented_tokens: Vec<PresentedToken>,
+    /// Contracts presented by the vehicles, until they are taken by the runtime
+    #[serde(skip)]
+    contract_authorizations: Vec<ContractAuthorization>,
+    #[serde(skip)]
+    store: SessionStore,
+}
+
+impl State {
+    fn save(&self) {
+        self.store.save_or_warn(self);
+    }
+
+    /// EVSE where an id token is presented, which Authorize does not tell: the EVSE whose
+    /// vehicle is plugged in without a transaction of ours, else the first one
+    fn authorizing_evse(&self) -> ConnectorId {
+        let plugged_in = self
+            .transactions
+            .iter()
+            .filter_map(|(evse_id, transaction)| match transaction {
+                Transaction::Running {
+                    requested: false, ..
+                } => Some(*evse_id),
+                _ => None,
+            });
+        let occupied = self
+            .statuses
+            .iter()
+            .filter(|(evse_id, status)| {
+                **status == ConnectorStatus::Occupied && !self.transactions.contains_key(evse_id)
+            })
+            .map(|(evse_id, _)| *evse_id);
+        plugged_in.chain(occupied).min().unwrap_or(DEFAULT_EVSE_ID)
+    }
+}
+
+/// Likely reason why the charging station rejected a RequestStartTransaction, from the last
+/// status of the EVSE: an available connector has no vehicle plugged in
+fn rejection_reason(status: Option<ConnectorStatus>) -> ChargerError {
+    match status {
+        Some(ConnectorStatus::Faulted) | Some(ConnectorStatus::Unavailable) => {
+            ChargerError::Faulted
+        }
+        Some(ConnectorStatus::Available) => ChargerError::NotPlugged,
+        _ => ChargerError::Rejected,
+    }
+}
+
+pub struct Csms {
+    state: Arc<Mutex<State>>,
+    local_addr: SocketAddr,
+    id_token: String,
+}
+
+impl Csms {
+    /// Listen on `address` (eg. `0.0.0.0:9000`) for the connection of the charging station of
+    /// `credentials`
+    pub fn listen(address: &str, credentials: ChargePointCredentials) -> Result<Csms> {
+        let state = Arc::new(Mutex::new(State::default()));
+        let server_state = state.clone();
+        let local_addr = rpc::listen(address, PROTOCOL, credentials, move |identity, socket| {
+            info!("Charging station {} connected", identity);
+            let handler = StationHandler {
+                state: server_state.clone(),
+            };
+            match Endpoint::spawn(socket, handler, DEFAULT_TIMEOUT) {
+                // A new connection of the station replaces the previous one
+                Ok(endpoint) => server_state.lock().unwrap().endpoint = Some(endpoint),
+                Err(e) => warn!("Cannot handle connection of {}: {}", identity, e),
+            }
+        })?;
+        info!("OCPP 2.0.1 CSMS listening on {}", local_addr);
+        Ok(Csms {
+            state,
+            local_addr,
+            id_token: DEFAULT_ID_TOKEN.to_string(),
+        })
+    }
+
+    /// Persist the transactions in `store`, and resume the transactions saved there
+    pub fn with_store(self, store: SessionStore) -> Csms {
+        {
+            let mut state = self.state.lock().unwrap();
+            let saved: State = store.load();
+            state.transactions = saved.transactions;
+            state.next_remote_start_id = saved.next_remote_start_id;
+            state.store = store;
+        }
+        self
+    }
+
+    /// Central id token sent in RequestStartTransaction requests
+    pub fn with_id_token(mut self, id_token: &str) -> Csms {
+        self.id_token = id_token.to_string();
+        self
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .endpoint
+            .as_ref()
+            .map(Endpoint::is_connected)
+            .unwrap_or(false)
+    }
+
+    fn endpoint(&self) -> Result<Endpoint> {
+        match &self.state.lock().unwrap().endpoint {
+            Some(endpoint) if endpoint.is_connected() => Ok(endpoint.clone()),
+            _ => Err(ChargerError::Unreachable.with_message("No charging station connected")),
+        }
+    }
+}
+
+/// Connector ids of the ChargerApi are the EVSE ids of the charging station
+impl ChargerApi for Csms {
+    fn start_new_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        let endpoint = self.endpoint()?;
+        let remote_start_id = {
+            let mut state = self.state.lock().unwrap();
+            match state.transactions.get(&connector) {
+                Some(Transaction::Requested { .. })
+                | Some(Transaction::Running {
+                    requested: true, ..
+                }) => {
+                    return Err(ChargerError::Busy.with_message(format!(
+                        "EVSE {} already has an active session",
+                        connector
+                    )));
+                }
+                Some(Transaction::Running { .. }) => {
+                    // Transaction started on cable plug-in: it is claimed with the response
+                }
+                _ => {
+                    // Registered before the call: the transaction may be reported before the response
+                    let remote_start_id = state.next_remote_start_id + 1;
+                    state
+                        .transactions
+                        .insert(connector, Transaction::Requested { remote_start_id });
+                }
+            }
+            state.next_remote_start_id += 1;
+            state.save();
+            state.next_remote_start_id
+        };
+
+        let request = RequestStartTransactionRequest {
+            evse_id: Some(connector),
+            remote_start_id,
+            id_token: IdToken {
+                id_token: self.id_token.clone(),
+                kind: "Central".into(),
+            },
+        };
+        let response = endpoint
+            .call("RequestStartTransaction", serde_json::to_value(request)?)
+            .and_then(|value| {
+                Ok(serde_json::from_value::<RequestStartTransactionResponse>(
+                    value,
+                )?)
+            });
+
+        let mut state = self.state.lock().unwrap();
+        match response {
+            Ok(RequestStartTransactionResponse {
+                status: RequestStartStopStatus::Accepted,
+                transaction_id,
+            }) => {
+                debug!("RequestStartTransaction accepted");
+                if let Some(Transaction::Running {
+                    transaction_id: running_id,
+                    requested,
+                    ..
+                }) = state.transactions.get_mut(&connector)
+                {
+                    if transaction_id.as_ref() == Some(running_id) {
+                        *requested = true;
+                    }
+                }
+                state.save();
+                Ok(())
+            }
+            other => {
+                if state.transactions.get(&connector)
+                    == Some(&Transaction::Requested { remote_start_id })
+                {
+                    state.transactions.remove(&connector);
+                    state.save();
+                }
+                match other {
+                    Err(e) => Err(e.context(ChargerError::Unreachable)),
+                    _ => Err(rejection_reason(state.statuses.get(&connector).copied())
+                        .with_message("Charging station rejected RequestStartTransaction")),
+                }
+            }
+        }
+    }
+
+    /// Sent to the charging station as RequestStopTransaction
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<()> {
+        let endpoint = self.endpoint()?;
+        let transaction_id = match self.state.lock().unwrap().transactions.get(&connector) {
+            Some(Transaction::Running {
+                transaction_id,
+                requested: true,
+                ..
+            }) => transaction_id.clone(),
+            _ => {
+                return Err(ChargerError::NoSession
+                    .with_message(format!("No transaction running on EVSE {}", connector)))
+            }
+        };
+        let request = RequestStopTransactionRequest { transaction_id };
+        let response: RequestStopTransactionResponse = serde_json::from_value(
+            endpoint
+                .call("RequestStopTransaction", serde_json::to_value(request)?)
+                .context(ChargerError::Unreachable)?,
+        )?;
+        match response.status {
+            RequestStartStopStatus::Accepted => Ok(()),
+            RequestStartStopStatus::Rejected => Err(ChargerError::Rejected
+                .with_message("Charging station rejected RequestStopTransaction")),
+        }
+    }
+
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> Result<ChargeStatus> {
+        let mut state = self.state.lock().unwrap();
+        let connector_status = state.statuses.get(&connector).copied();
+        let status = match state.transactions.get(&connector) {
+            None
+            | Some(Transaction::Running {
+                requested: false, ..
+            }) => match connector_status {
+                Some(ConnectorStatus::Faulted) => ChargeStatus::Faulted {
+                    error_code: FAULT_ERROR_CODE.into(),
+                    meter: None,
+                },
+                Some(ConnectorStatus::Unavailable) => ChargeStatus::Unavailable,
+                _ => ChargeStatus::NotFound,
+            },
+            Some(Transaction::Requested { .. }) => match connector_status {
+                Some(ConnectorStatus::Faulted) => ChargeStatus::Faulted {
+                    error_code: FAULT_ERROR_CODE.into(),
+                    meter: None,
+                },
+                _ => ChargeStatus::Active { meter: None },
+            },
+            Some(Transaction::Running {
+                meter,
+                charging_state,
+                ..
+            }) => {
+                let meter = meter.clone();
+                match (connector_status, charging_state.as_deref()) {
+                    (Some(ConnectorStatus::Faulted), _) => ChargeStatus::Faulted {
+                        error_code: FAULT_ERROR_CODE.into(),
+                        meter,
+                    },
+                    (_, Some("SuspendedEV")) => ChargeStatus::SuspendedEV { meter },
+                    (_, Some("SuspendedEVSE")) => ChargeStatus::SuspendedEVSE { meter },
+                    _ => ChargeStatus::Active { meter },
+                }
+            }
+            Some(Transaction::Ended {
+                meter_start,
+                meter_stop,
+                signed_data,
+            }) => {
+                let status = match (*meter_start, *meter_stop) {
+                    (Some(start), Some(stop)) => {
+                        // The meter values are in Wh
+                        let kwh = (stop - start).max(0) as u64 / 1000;
+                        info!("Charge is ended on EVSE {}, kwh: {}", connector, kwh);
+                        ChargeStatus::Ended {
+                            kwh,
+                            signed_data: signed_data.clone(),
+                        }
+                    }
+                    _ => {
+                        warn!(
+                            "Transaction on EVSE {} ended without energy readings",
+                            connector
+                        );
+                        ChargeStatus::Faulted {
+                            error_code: NO_METER_VALUES_ERROR_CODE.into(),
+                            meter: None,
+                        }
+                    }
+                };
+                state.transactions.remove(&connector);
+                state.save();
+                status
+            }
+        };
+        Ok(status)
+    }
+
+    /// Sent to the charging station as SetChargingProfile: the limit of the whole station
+    /// (EVSE 0), of our transaction running on the EVSE, or else the default limit of its
+    /// next transactions
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        watts: u32,
+        valid_until: Option<u64>,
+    ) -> Result<()> {
+        let endpoint = self.endpoint()?;
+        let transaction_id = match self.state.lock().unwrap().transactions.get(&connector) {
+            Some(Transaction::Running {
+                transaction_id,
+                requested: true,
+                ..
+            }) => Some(transaction_id.clone()),
+            _ => None,
+        };
+        let purpose = match (connector, &transaction_id) {
+            (0, _) => ChargingProfilePurpose::ChargingStationMaxProfile,
+            (_, Some(_)) => ChargingProfilePurpose::TxProfile,
+            (_, None) => ChargingProfilePurpose::TxDefaultProfile,
+        };
+        // One profile per EVSE, replaced by the next limit
+        let profile_id = connector as i32 + 1;
+        let request = SetChargingProfileRequest {
+            evse_id: connector,
+            charging_profile: ChargingProfile {
+                id: profile_id,
+                stack_level: 0,
+                charging_profile_purpose: purpose,
+                charging_profile_kind: "Absolute".into(),
+                valid_to: valid_until.map(to_rfc3339),
+                transaction_id,
+                charging_schedule: vec![ChargingSchedule {
+                    id: profile_id,
+                    start_schedule: Some(now()),
+                    charging_rate_unit: "W".into(),
+                    charging_schedule_period: vec![ChargingSchedulePeriod {
+                        start_period: 0,
+                        limit: watts as f64,
+                    }],
+                }],
+            },
+        };
+        let response: SetChargingProfileResponse = serde_json::from_value(
+            endpoint
+                .call("SetChargingProfile", serde_json::to_value(request)?)
+                .context(ChargerError::Unreachable)?,
+        )?;
+        match response.status {
+            ChargingProfileStatus::Accepted => {
+                info!("Power of EVSE {} limited to {} W", connector, watts);
+                Ok(())
+            }
+            ChargingProfileStatus::Rejected => {
+                Err(ChargerError::Rejected
+                    .with_message("Charging station rejected SetChargingProfile"))
+            }
+        }
+    }
+
+    fn take_presented_tokens(&mut self) -> Result<Vec<PresentedToken>> {
+        Ok(std::mem::take(
+            &mut self.state.lock().unwrap().presented_tokens,
+        ))
+    }
+
+    fn take_contract_authorizations(&mut self) -> Result<Vec<ContractAuthorization>> {
+        Ok(std::mem::take(
+            &mut self.state.lock().unwrap().contract_authorizations,
+        ))
+    }
+}
+
+/// Handles the calls initiated by the charging station
+struct StationHandler {
+    state: Arc<Mutex<State>>,
+}
+
+impl StationHandler {
+    /// The id token (RFID card, or contract of the vehicle with ISO 15118) is accepted: it is
+    /// checked on-chain, where the session is requested on behalf of its owner. With Plug &
+    /// Charge, the chain of the contract certificate is verified on-chain as well
+    fn authorize(&mut self, request: AuthorizeRequest) -> Value {
+        let mut state = self.state.lock().unwrap();
+        let evse_id = state.authorizing_evse();
+        let status = match (&request.certificate, request.certificate_chain()) {
+            (None, _) => {
+                info!(
+                    "Id token {} ({}) presented on EVSE {}",
+                    request.id_token.id_token, request.id_token.kind, evse_id
+                );
+                state.presented_tokens.push(PresentedToken {
+                    connector: evse_id,
+                    token: request.id_token.id_token.into_bytes(),
+                });
+                AuthorizationStatus::Accepted
+            }
+            (Some(_), Some(certificate_chain)) if !certificate_chain.is_empty() => {
+                info!(
+                    "Contract {} presented on EVSE {}",
+                    request.id_token.id_token, evse_id
+                );
+                state.contract_authorizations.push(ContractAuthorization {
+                    connector: evse_id,
+                    emaid: request.id_token.id_token.into_bytes(),
+                    certificate_chain,
+                });
+                AuthorizationStatus::Accepted
+            }
+            (Some(_), _) => {
+                warn!(
+                    "Unreadable certificate of contract {}",
+                    request.id_token.id_token
+                );
+                AuthorizationStatus::Invalid
+            }
+        };
+        json!(AuthorizeResponse {
+            id_token_info: IdTokenInfo { status },
+        })
+    }
+
+    fn transaction_event(&mut self, event: TransactionEventRequest) -> Value {
+        let evse_id = event
+            .evse
+            .as_ref()
+            .map(|evse| evse.id)
+            .unwrap_or(DEFAULT_EVSE_ID);
+        let energy = event.energy_wh();
+        let event_signed_data: Vec<String> = event
+            .meter_value
+            .iter()
+            .flat_map(MeterValue::signed_data)
+            .collect();
+        let info = &event.transaction_info;
+        let mut state = self.state.lock().unwrap();
+        let current = state.transactions.remove(&evse_id);
+
+        let next = match (event.event_type, current) {
+            (TransactionEventType::Started, current) => {
+                let requested = match current {
+                    Some(Transaction::Requested { remote_start_id }) => {
+                        info.remote_start_id.is_none()
+                            || info.remote_start_id == Some(remote_start_id)
+                    }
+                    _ => false,
+                };
+                info!(
+                    "Transaction {} started on EVSE {} ({})",
+                    info.transaction_id, evse_id, event.trigger_reason
+                );
+                Some(Transaction::Running {
+                    transaction_id: info.transaction_id.clone(),
+                    requested,
+                    meter_start: energy,
+                    meter_value: energy,
+                    meter: sample(None, energy, &event),
+                    charging_state: info.charging_state.clone(),
+                    signed_data: event_signed_data,
+                })
+            }
+            (
+                event_type,
+                Some(Transaction::Running {
+                    transaction_id,
+                    requested,
+                    meter_start,
+                    meter_value,
+                    meter,
+                    charging_state,
+                    mut signed_data,
+                }),
+            ) if transaction_id == info.transaction_id => {
+                // A remote start of a transaction started on cable plug-in is reported by an update
+                let requested = requested || info.remote_start_id.is_some();
+                let meter_value = energy.or(meter_value);
+                // The first reading stands for the start reading when the Started event had none
+                let first_reading = meter_start.or(meter_value);
+                let meter = sample(meter, first_reading, &event);
+                let charging_state = info.charging_state.clone().or(charging_state);
+                signed_data.extend(event_signed_data);
+                match event_type {
+                    TransactionEventType::Ended if requested => {
+                        info!(
+                            "Transaction {} ended ({})",
+                            transaction_id,
+                            info.stopped_reason.as_deref().unwrap_or("Local")
+                        );
+                        // No reading before the end: the energy of the transaction is unknown
+                        Some(Transaction::Ended {
+                            meter_start,
+                            meter_stop: meter_value,
+                            signed_data,
+                        })
+                    }
+                    TransactionEventType::Ended => None,
+                    _ => Some(Transaction::Running {
+                        transaction_id,
+                        requested,
+                        meter_start: first_reading,
+                        meter_value,
+                        meter,
+                        charging_state,
+                        signed_data,
+                    }),
+                }
+            }
+            (_, current) => {
+                warn!(
+                    "TransactionEvent for unknown transaction {}",
+                    info.transaction_id
+                );
+                current
+            }
+        };
+        if let Some(next) = next {
+            state.transactions.insert(evse_id, next);
+        }
+        state.save();
+        json!({})
+    }
+
+    fn status_notification(&mut self, request: StatusNotificationRequest) -> Value {
+        if request.connector_status == ConnectorStatus::Faulted {
+            warn!(
+                "Connector {} of EVSE {} is faulted",
+                request.connector_id, request.evse_id
+            );
+        }
+        let mut state = self.state.lock().unwrap();
+        state
+            .statuses
+            .insert(request.evse_id, request.connector_status);
+        json!({})
+    }
+}
+
+/// Last reading of a transaction, updated with the meter values carried by `event`
+fn sample(
+    meter: Option<MeterSample>,
+    meter_start: Option<i64>,
+    event: &TransactionEventRequest,
+) -> Option<MeterSample> {
+    if event.meter_value.is_empty() {
+        return meter;
+    }
+    let mut sample = meter.unwrap_or_default();
+    for value in &event.meter_value {
+        update_sample(
+            &mut sample,
+            meter_start.unwrap_or_default(),
+            &value.timestamp,
+            value.readings(),
+        );
+    }
+    Some(sample)
+}
+
+impl Handler for StationHandler {
+    fn handle_call(&mut self, action: &str, payload: Value) -> Result<Value, RpcError> {
+        match action {
+            "BootNotification" => Ok(json!({
+                "status": "Accepted",
+                "currentTime": now(),
+                "interval": HEARTBEAT_INTERVAL,
+            })),
+            "Heartbeat" => Ok(json!({ "currentTime": now() })),
+            "StatusNotification" => serde_json::from_value(payload)
+                .map(|request| self.status_notification(request))
+                .map_err(RpcError::formation_violation),
+            "Authorize" => serde_json::from_value(payload)
+                .map(|request| self.authorize(request))
+                .map_err(RpcError::formation_violation),
+            "TransactionEvent" => serde_json::from_value(payload)
+                .map(|event| self.transaction_event(event))
+                .map_err(RpcError::formation_violation),
+            _ => Err(RpcError::not_implemented(action)),
+        }
+    }
+}
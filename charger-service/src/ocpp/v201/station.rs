@@ -0,0 +1,530 @@
+//! A scripted OCPP 2.0.1 charging station, used to test the CSMS without hardware
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use super::messages::*;
+use super::PROTOCOL;
+use crate::ocpp::rpc::{self, Endpoint, Handler, RpcError};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the station answers a RequestStartTransaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reaction {
+    /// Accept the request and start (or authorize) the transaction
+    Accept,
+    /// Accept the request, but never report the transaction
+    AcceptSilently,
+    Reject,
+}
+
+#[derive(Default)]
+struct StationState {
+    /// Energy register of each EVSE, in Wh
+    meters: HashMap<u32, i64>,
+    /// Running transaction of each EVSE
+    transactions: HashMap<u32, String>,
+    /// Charging state of the running transactions, `Charging` when not set
+    charging_states: HashMap<u32, String>,
+    next_transaction_id: u32,
+    seq_no: u32,
+    /// Reactions to the next RequestStartTransaction, `Accept` when empty
+    reactions: VecDeque<Reaction>,
+    /// Actions received from the CSMS, in order
+    received: Vec<String>,
+    /// Dataset signed by the meter, sent with the next Ended event of each EVSE
+    signed_data: HashMap<u32, String>,
+    reject_charging_profiles: bool,
+    /// Send the transaction events without meter values
+    omit_meter_values: bool,
+    /// Purpose and limit (in W) of the charging profile of each EVSE, 0 for the station
+    charging_profiles: HashMap<u32, (ChargingProfilePurpose, f64)>,
+}
+
+/// Transaction events triggered by the CSMS, sent outside of the connection thread
+enum Command {
+    Started { evse_id: u32, remote_start_id: i32 },
+    Authorized { evse_id: u32, remote_start_id: i32 },
+    Ended { evse_id: u32, reason: String },
+}
+
+pub struct ScriptedStation {
+    endpoint: Endpoint,
+    state: Arc<Mutex<StationState>>,
+}
+
+impl ScriptedStation {
+    /// Connect to the CSMS at `url` with `password`, and send the BootNotification
+    pub fn connect(url: &str, password: &str) -> Result<ScriptedStation> {
+        let state = Arc::new(Mutex::new(StationState::default()));
+        let (commands, receiver) = mpsc::channel();
+        let handler = CsmsHandler {
+            state: state.clone(),
+            commands,
+        };
+        let endpoint = Endpoint::spawn(rpc::connect(url, PROTOCOL, password)?, handler, TIMEOUT)?;
+        let station = ScriptedStation { endpoint, state };
+
+        let command_station = ScriptedStation {
+            endpoint: station.endpoint.clone(),
+            state: station.state.clone(),
+        };
+        thread::spawn(move || {
+            for command in receiver {
+                let result = match command {
+                    Command::Started {
+                        evse_id,
+                        remote_start_id,
+                    } => command_station.start(evse_id, "RemoteStart", Some(remote_start_id)),
+                    Command::Authorized {
+                        evse_id,
+                        remote_start_id,
+                    } => command_station.update(evse_id, "RemoteStart", Some(remote_start_id)),
+                    Command::Ended { evse_id, reason } => command_station.end(evse_id, &reason),
+                };
+                if let Err(e) = result {
+                    warn!("Scripted station command failed: {}", e);
+                }
+            }
+        });
+
+        station.endpoint.call(
+            "BootNotification",
+            json!({
+                "reason": "PowerUp",
+                "chargingStation": { "model": "Scripted", "vendorName": "Delmonicos" },
+            }),
+        )?;
+        Ok(station)
+    }
+
+    /// Queue the reaction to the next RequestStartTransaction
+    pub fn react_to_next_start(&self, reaction: Reaction) {
+        self.state.lock().unwrap().reactions.push_back(reaction);
+    }
+
+    pub fn transaction_id(&self, evse_id: u32) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .transactions
+            .get(&evse_id)
+            .cloned()
+    }
+
+    /// Actions received from the CSMS, in order
+    pub fn received(&self) -> Vec<String> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Send `signed_data` as the signed meter value of the next Ended event of `evse_id`
+    pub fn sign_end(&self, evse_id: u32, signed_data: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .signed_data
+            .insert(evse_id, signed_data.to_string());
+    }
+
+    /// Make the next SetChargingProfile requests rejected
+    pub fn reject_charging_profiles(&self, reject: bool) {
+        self.state.lock().unwrap().reject_charging_profiles = reject;
+    }
+
+    /// Make the next transaction events carry no meter values
+    pub fn omit_meter_values(&self, omit: bool) {
+        self.state.lock().unwrap().omit_meter_values = omit;
+    }
+
+    /// Purpose and limit of the charging profile set on `evse_id`, if any
+    pub fn charging_profile(&self, evse_id: u32) -> Option<(ChargingProfilePurpose, f64)> {
+        self.state
+            .lock()
+            .unwrap()
+            .charging_profiles
+            .get(&evse_id)
+            .copied()
+    }
+
+    /// Send an Authorize request for `id_token` of type `kind`: `ISO14443` for an RFID card,
+    /// `eMAID` for the contract of a vehicle (ISO 15118 Plug & Charge)
+    pub fn authorize(&self, id_token: &str, kind: &str) -> Result<AuthorizationStatus> {
+        self.send_authorize(AuthorizeRequest {
+            id_token: IdToken {
+                id_token: id_token.into(),
+                kind: kind.into(),
+            },
+            certificate: None,
+        })
+    }
+
+    /// Send an Authorize request for the contract `emaid` of the vehicle, with the chain of
+    /// its contract certificate (DER encoded, sent as PEM)
+    pub fn authorize_contract(
+        &self,
+        emaid: &str,
+        certificate_chain: &[Vec<u8>],
+    ) -> Result<AuthorizationStatus> {
+        let pem = certificate_chain
+            .iter()
+            .map(|der| {
+                format!(
+                    "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n",
+                    base64::encode(der)
+                )
+            })
+            .collect();
+        self.send_authorize(AuthorizeRequest {
+            id_token: IdToken {
+                id_token: emaid.into(),
+                kind: "eMAID".into(),
+            },
+            certificate: Some(pem),
+        })
+    }
+
+    fn send_authorize(&self, request: AuthorizeRequest) -> Result<AuthorizationStatus> {
+        let response: AuthorizeResponse = serde_json::from_value(
+            self.endpoint
+                .call("Authorize", serde_json::to_value(request)?)?,
+        )?;
+        Ok(response.id_token_info.status)
+    }
+
+    /// Cable plugged in: the station starts a transaction before any authorization
+    pub fn plug_in(&self, evse_id: u32) -> Result<()> {
+        self.start(evse_id, "CablePluggedIn", None)
+    }
+
+    /// Deliver `wh` to the vehicle plugged on `evse_id` and report the new meter value
+    pub fn deliver(&self, evse_id: u32, wh: i64) -> Result<()> {
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .meters
+            .entry(evse_id)
+            .or_default() += wh;
+        self.update(evse_id, "MeterValuePeriodic", None)
+    }
+
+    /// Change the charging state of the transaction of `evse_id` (eg. `SuspendedEV`)
+    pub fn set_charging_state(&self, evse_id: u32, charging_state: &str) -> Result<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .charging_states
+            .insert(evse_id, charging_state.into());
+        self.update(evse_id, "ChargingStateChanged", None)
+    }
+
+    /// Send a StatusNotification for the connector of `evse_id` (eg. `Faulted`)
+    pub fn notify_status(&self, evse_id: u32, connector_status: &str) -> Result<()> {
+        self.endpoint
+            .call(
+                "StatusNotification",
+                json!({
+                    "timestamp": now(),
+                    "connectorStatus": connector_status,
+                    "evseId": evse_id,
+                    "connectorId": 1,
+                }),
+            )
+            .map(|_| ())
+    }
+
+    /// Cable unplugged: the transaction ends locally
+    pub fn unplug(&self, evse_id: u32) -> Result<()> {
+        self.end(evse_id, "EVDisconnected")
+    }
+
+    fn start(
+        &self,
+        evse_id: u32,
+        trigger_reason: &str,
+        remote_start_id: Option<i32>,
+    ) -> Result<()> {
+        let transaction_id = {
+            let mut state = self.state.lock().unwrap();
+            if state.transactions.contains_key(&evse_id) {
+                return Err(anyhow!("A transaction is already running"));
+            }
+            state.next_transaction_id += 1;
+            let transaction_id = format!("TX-{}", state.next_transaction_id);
+            state.transactions.insert(evse_id, transaction_id.clone());
+            transaction_id
+        };
+        self.send_event(
+            evse_id,
+            TransactionEventType::Started,
+            trigger_reason,
+            TransactionInfo {
+                transaction_id,
+                charging_state: Some("Charging".into()),
+                stopped_reason: None,
+                remote_start_id,
+            },
+        )
+    }
+
+    fn update(
+        &self,
+        evse_id: u32,
+        trigger_reason: &str,
+        remote_start_id: Option<i32>,
+    ) -> Result<()> {
+        let transaction_id = self
+            .transaction_id(evse_id)
+            .ok_or_else(|| anyhow!("No transaction running"))?;
+        let charging_state = self
+            .state
+            .lock()
+            .unwrap()
+            .charging_states
+            .get(&evse_id)
+            .cloned()
+            .unwrap_or_else(|| "Charging".into());
+        self.send_event(
+            evse_id,
+            TransactionEventType::Updated,
+            trigger_reason,
+            TransactionInfo {
+                transaction_id,
+                charging_state: Some(charging_state),
+                stopped_reason: None,
+                remote_start_id,
+            },
+        )
+    }
+
+    fn end(&self, evse_id: u32, stopped_reason: &str) -> Result<()> {
+        let transaction_id = self
+            .transaction_id(evse_id)
+            .ok_or_else(|| anyhow!("No transaction running"))?;
+        let trigger_reason = if stopped_reason == "Remote" {
+            "RemoteStop"
+        } else {
+            "EVDeparted"
+        };
+        self.send_event(
+            evse_id,
+            TransactionEventType::Ended,
+            trigger_reason,
+            TransactionInfo {
+                transaction_id,
+                charging_state: Some("Idle".into()),
+                stopped_reason: Some(stopped_reason.into()),
+                remote_start_id: None,
+            },
+        )?;
+        let mut state = self.state.lock().unwrap();
+        state.transactions.remove(&evse_id);
+        state.charging_states.remove(&evse_id);
+        Ok(())
+    }
+
+    fn send_event(
+        &self,
+        evse_id: u32,
+        event_type: TransactionEventType,
+        trigger_reason: &str,
+        transaction_info: TransactionInfo,
+    ) -> Result<()> {
+        let mut event = {
+            let mut state = self.state.lock().unwrap();
+            state.seq_no += 1;
+            let meter = state.meters.get(&evse_id).copied().unwrap_or_default();
+            let signed_meter_value = match event_type {
+                TransactionEventType::Ended => {
+                    state
+                        .signed_data
+                        .remove(&evse_id)
+                        .map(|data| SignedMeterValue {
+                            signed_meter_data: base64::encode(data),
+                            signing_method: "ECDSA-secp256r1-SHA256".into(),
+                            encoding_method: "OCMF".into(),
+                            public_key: String::new(),
+                        })
+                }
+                _ => None,
+            };
+            TransactionEventRequest {
+                event_type,
+                timestamp: now(),
+                trigger_reason: trigger_reason.into(),
+                seq_no: state.seq_no,
+                transaction_info,
+                evse: Some(Evse {
+                    id: evse_id,
+                    connector_id: Some(1),
+                }),
+                id_token: None,
+                meter_value: vec![MeterValue {
+                    timestamp: now(),
+                    sampled_value: vec![
+                        SampledValue {
+                            value: meter as f64,
+                            measurand: Some("Energy.Active.Import.Register".into()),
+                            unit_of_measure: Some(UnitOfMeasure {
+                                unit: Some("Wh".into()),
+                                multiplier: None,
+                            }),
+                            signed_meter_value,
+                        },
+                        SampledValue {
+                            value: 11.0,
+                            measurand: Some("Power.Active.Import".into()),
+                            unit_of_measure: Some(UnitOfMeasure {
+                                unit: Some("kW".into()),
+                                multiplier: None,
+                            }),
+                            signed_meter_value: None,
+                        },
+                        SampledValue {
+                            value: 4.0,
+                            measurand: Some("Voltage".into()),
+                            unit_of_measure: Some(UnitOfMeasure {
+                                unit: Some("V".into()),
+                                multiplier: Some(2),
+                            }),
+                            signed_meter_value: None,
+                        },
+                        SampledValue {
+                            value: 16.0,
+                            measurand: Some("Current.Import".into()),
+                            unit_of_measure: Some(UnitOfMeasure {
+                                unit: Some("A".into()),
+                                multiplier: None,
+                            }),
+                            signed_meter_value: None,
+                        },
+                    ],
+                }],
+            }
+        };
+        if self.state.lock().unwrap().omit_meter_values {
+            event.meter_value.clear();
+        }
+        self.endpoint
+            .call("TransactionEvent", serde_json::to_value(event)?)
+            .map(|_| ())
+    }
+}
+
+/// Handles the calls initiated by the CSMS
+struct CsmsHandler {
+    state: Arc<Mutex<StationState>>,
+    commands: mpsc::Sender<Command>,
+}
+
+impl CsmsHandler {
+    fn request_start(&mut self, request: RequestStartTransactionRequest) -> Value {
+        let evse_id = request.evse_id.unwrap_or(1);
+        let (reaction, transaction_id) = {
+            let mut state = self.state.lock().unwrap();
+            let reaction = state.reactions.pop_front().unwrap_or(Reaction::Accept);
+            (reaction, state.transactions.get(&evse_id).cloned())
+        };
+        let command = match transaction_id {
+            Some(_) => Command::Authorized {
+                evse_id,
+                remote_start_id: request.remote_start_id,
+            },
+            None => Command::Started {
+                evse_id,
+                remote_start_id: request.remote_start_id,
+            },
+        };
+        let status = match reaction {
+            Reaction::Reject => RequestStartStopStatus::Rejected,
+            Reaction::AcceptSilently => RequestStartStopStatus::Accepted,
+            Reaction::Accept => match self.commands.send(command) {
+                Ok(_) => RequestStartStopStatus::Accepted,
+                Err(_) => RequestStartStopStatus::Rejected,
+            },
+        };
+        json!(RequestStartTransactionResponse {
+            status,
+            transaction_id,
+        })
+    }
+
+    fn request_stop(&mut self, request: RequestStopTransactionRequest) -> Value {
+        let evse_id = self
+            .state
+            .lock()
+            .unwrap()
+            .transactions
+            .iter()
+            .find(|(_, transaction_id)| **transaction_id == request.transaction_id)
+            .map(|(evse_id, _)| *evse_id);
+        let status = if let Some(evse_id) = evse_id {
+            let command = Command::Ended {
+                evse_id,
+                reason: "Remote".into(),
+            };
+            match self.commands.send(command) {
+                Ok(_) => RequestStartStopStatus::Accepted,
+                Err(_) => RequestStartStopStatus::Rejected,
+            }
+        } else {
+            RequestStartStopStatus::Rejected
+        };
+        json!(RequestStopTransactionResponse { status })
+    }
+
+    fn set_charging_profile(&mut self, request: SetChargingProfileRequest) -> Value {
+        let mut state = self.state.lock().unwrap();
+        let profile = request.charging_profile;
+        // A transaction profile only applies to the transaction running on the EVSE
+        let accepted = !state.reject_charging_profiles
+            && match profile.charging_profile_purpose {
+                ChargingProfilePurpose::TxProfile => {
+                    profile.transaction_id.is_some()
+                        && state.transactions.get(&request.evse_id)
+                            == profile.transaction_id.as_ref()
+                }
+                _ => true,
+            };
+        let limit = profile
+            .charging_schedule
+            .first()
+            .and_then(|schedule| schedule.charging_schedule_period.first())
+            .map(|period| period.limit);
+        let status = match limit {
+            Some(limit) if accepted => {
+                state
+                    .charging_profiles
+                    .insert(request.evse_id, (profile.charging_profile_purpose, limit));
+                ChargingProfileStatus::Accepted
+            }
+            _ => ChargingProfileStatus::Rejected,
+        };
+        json!(SetChargingProfileResponse { status })
+    }
+}
+
+impl Handler for CsmsHandler {
+    fn handle_call(&mut self, action: &str, payload: Value) -> Result<Value, RpcError> {
+        self.state.lock().unwrap().received.push(action.to_string());
+        match action {
+            "RequestStartTransaction" => serde_json::from_value(payload)
+                .map(|request| self.request_start(request))
+                .map_err(RpcError::formation_violation),
+            "RequestStopTransaction" => serde_json::from_value(payload)
+                .map(|request| self.request_stop(request))
+                .map_err(RpcError::formation_violation),
+            "SetChargingProfile" => serde_json::from_value(payload)
+                .map(|request| self.set_charging_profile(request))
+                .map_err(RpcError::formation_violation),
+            _ => Err(RpcError::not_implemented(action)),
+        }
+    }
+}
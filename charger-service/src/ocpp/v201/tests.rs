@@ -0,0 +1,425 @@
+//! Conformance-style tests of the CSMS against the scripted charging station.
+//! Test names refer to the use cases of the OCPP 2.0.1 specification (part 2, blocks C, E, F, G, J and K).
+use super::{
+    messages::{AuthorizationStatus, ChargingProfilePurpose},
+    station::{Reaction, ScriptedStation},
+    ChargePointCredentials, Csms,
+};
+use crate::api::{ChargeStatus, ChargerApi, ChargerError, ContractAuthorization, PresentedToken};
+use std::time::{Duration, Instant};
+
+fn wait_until(condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(
+            Instant::now() < deadline,
+            "Timeout while waiting for condition"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn credentials() -> ChargePointCredentials {
+    ChargePointCredentials {
+        identity: "CS001".into(),
+        password: "0123456789abcdef".into(),
+    }
+}
+
+fn connect() -> (Csms, ScriptedStation) {
+    let csms = Csms::listen("127.0.0.1:0", credentials()).expect("Cannot listen");
+    let url = format!("ws://{}/ocpp/CS001", csms.local_addr());
+    let station = ScriptedStation::connect(&url, "0123456789abcdef")
+        .expect("Cannot connect charging station");
+    wait_until(|| csms.is_connected());
+    (csms, station)
+}
+
+fn status(csms: &mut Csms) -> ChargeStatus {
+    csms.get_current_charge_status(1)
+        .expect("Cannot get charge status")
+}
+
+#[test]
+fn c01_authorization_using_rfid() {
+    let (mut csms, station) = connect();
+
+    // Without a vehicle plugged in, the token is reported on the first EVSE
+    assert_eq!(
+        station.authorize("04A2B3C4", "ISO14443").unwrap(),
+        AuthorizationStatus::Accepted
+    );
+    station.plug_in(2).expect("Cannot plug in");
+    station.authorize("04D5E6F7", "ISO14443").unwrap();
+    assert_eq!(
+        csms.take_presented_tokens().unwrap(),
+        vec![
+            PresentedToken {
+                connector: 1,
+                token: b"04A2B3C4".to_vec(),
+            },
+            PresentedToken {
+                connector: 2,
+                token: b"04D5E6F7".to_vec(),
+            },
+        ]
+    );
+    assert_eq!(csms.take_presented_tokens().unwrap(), vec![]);
+}
+
+#[test]
+fn c07_authorization_using_contract_certificates() {
+    let (mut csms, station) = connect();
+
+    // The vehicle presents its contract once plugged in
+    station.plug_in(1).expect("Cannot plug in");
+    station.authorize("FRDELC123456789", "eMAID").unwrap();
+    assert_eq!(
+        csms.take_presented_tokens().unwrap(),
+        vec![PresentedToken {
+            connector: 1,
+            token: b"FRDELC123456789".to_vec(),
+        }]
+    );
+}
+
+#[test]
+fn c07_authorization_with_contract_certificate_chain() {
+    let (mut csms, station) = connect();
+
+    // The chain is reported as sent by the vehicle, it is verified on-chain
+    station.plug_in(1).expect("Cannot plug in");
+    let certificate_chain = vec![vec![0x30, 0x03, 0x02, 0x01, 0x01], vec![0x30, 0x00]];
+    assert_eq!(
+        station
+            .authorize_contract("FRDELC123456789", &certificate_chain)
+            .unwrap(),
+        AuthorizationStatus::Accepted
+    );
+    assert_eq!(
+        csms.take_contract_authorizations().unwrap(),
+        vec![ContractAuthorization {
+            connector: 1,
+            emaid: b"FRDELC123456789".to_vec(),
+            certificate_chain,
+        }]
+    );
+    assert_eq!(csms.take_presented_tokens().unwrap(), vec![]);
+}
+
+#[test]
+fn f01_remote_start_cable_plugged_in_first() {
+    let (mut csms, station) = connect();
+
+    station.plug_in(1).expect("Cannot plug in");
+    // A transaction started on cable plug-in does not belong to the node yet
+    assert_eq!(status(&mut csms), ChargeStatus::NotFound);
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    assert!(matches!(status(&mut csms), ChargeStatus::Active { .. }));
+
+    station.deliver(1, 3000).expect("Cannot deliver energy");
+    station.unplug(1).expect("Cannot unplug");
+    assert_eq!(
+        status(&mut csms),
+        ChargeStatus::Ended {
+            kwh: 3,
+            signed_data: vec![]
+        }
+    );
+}
+
+#[test]
+fn f02_remote_start_remote_start_first() {
+    let (mut csms, station) = connect();
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    assert_eq!(station.received(), vec!["RequestStartTransaction"]);
+    assert!(matches!(status(&mut csms), ChargeStatus::Active { .. }));
+
+    station.deliver(1, 1500).expect("Cannot deliver energy");
+    station.deliver(1, 500).expect("Cannot deliver energy");
+    match status(&mut csms) {
+        ChargeStatus::Active { meter: Some(meter) } => {
+            assert_eq!(meter.energy_wh, 2000);
+            assert_eq!(meter.power_w, 11_000);
+            assert_eq!(meter.voltage_mv, 400_000);
+            assert_eq!(meter.current_ma, 16_000);
+            assert!(meter.timestamp > 0);
+        }
+        other => panic!("Unexpected status {:?}", other),
+    }
+
+    station.unplug(1).expect("Cannot unplug");
+    assert_eq!(
+        status(&mut csms),
+        ChargeStatus::Ended {
+            kwh: 2,
+            signed_data: vec![]
+        }
+    );
+    assert_eq!(status(&mut csms), ChargeStatus::NotFound);
+}
+
+#[test]
+fn f02_remote_start_accepted_before_transaction_is_reported() {
+    let (mut csms, station) = connect();
+    station.react_to_next_start(Reaction::AcceptSilently);
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    // The session is active as soon as the request is accepted
+    assert!(matches!(status(&mut csms), ChargeStatus::Active { .. }));
+    assert!(csms.start_new_charge(1).is_err());
+}
+
+#[test]
+fn f02_first_meter_value_is_the_start_reading() {
+    let (mut csms, station) = connect();
+    station.omit_meter_values(true);
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    station.omit_meter_values(false);
+    station.deliver(1, 1000).expect("Cannot deliver energy");
+    station.deliver(1, 2000).expect("Cannot deliver energy");
+
+    station.unplug(1).expect("Cannot unplug");
+    assert_eq!(
+        status(&mut csms),
+        ChargeStatus::Ended {
+            kwh: 2,
+            signed_data: vec![]
+        }
+    );
+}
+
+#[test]
+fn f02_transaction_without_meter_values_is_not_billed() {
+    let (mut csms, station) = connect();
+    station.omit_meter_values(true);
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    station.deliver(1, 3000).expect("Cannot deliver energy");
+    // Only the Ended event carries a reading
+    station.omit_meter_values(false);
+
+    station.unplug(1).expect("Cannot unplug");
+    assert_eq!(
+        status(&mut csms),
+        ChargeStatus::Faulted {
+            error_code: "NoMeterValues".into(),
+            meter: None
+        }
+    );
+    assert_eq!(status(&mut csms), ChargeStatus::NotFound);
+}
+
+#[test]
+fn f03_remote_start_rejected() {
+    let (mut csms, station) = connect();
+    station.react_to_next_start(Reaction::Reject);
+
+    assert!(csms.start_new_charge(1).is_err());
+    assert_eq!(status(&mut csms), ChargeStatus::NotFound);
+
+    // The charger is not left busy after a rejection
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    assert!(matches!(status(&mut csms), ChargeStatus::Active { .. }));
+}
+
+#[test]
+fn f03_remote_stop_transaction() {
+    let (mut csms, station) = connect();
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    station.deliver(1, 1700).expect("Cannot deliver energy");
+
+    csms.stop_charge(1).expect("Cannot stop transaction");
+    wait_until(|| station.transaction_id(1).is_none());
+    assert_eq!(
+        station.received(),
+        vec!["RequestStartTransaction", "RequestStopTransaction"]
+    );
+    assert_eq!(
+        status(&mut csms),
+        ChargeStatus::Ended {
+            kwh: 1,
+            signed_data: vec![]
+        }
+    );
+}
+
+#[test]
+fn e_local_transaction_is_not_reported() {
+    let (mut csms, station) = connect();
+
+    station.plug_in(1).expect("Cannot plug in");
+    station.deliver(1, 1000).expect("Cannot deliver energy");
+    station.unplug(1).expect("Cannot unplug");
+
+    assert_eq!(status(&mut csms), ChargeStatus::NotFound);
+    assert!(csms.stop_charge(1).is_err());
+}
+
+#[test]
+fn e_start_fails_without_station() {
+    let mut csms = Csms::listen("127.0.0.1:0", credentials()).expect("Cannot listen");
+
+    assert!(csms.start_new_charge(1).is_err());
+    assert_eq!(status(&mut csms), ChargeStatus::NotFound);
+}
+
+#[test]
+fn e_transactions_on_several_evses() {
+    let (mut csms, station) = connect();
+
+    station.plug_in(2).expect("Cannot plug in");
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    csms.start_new_charge(2).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+
+    station.deliver(1, 1000).expect("Cannot deliver energy");
+    station.deliver(2, 2500).expect("Cannot deliver energy");
+    station.unplug(2).expect("Cannot unplug");
+
+    assert!(matches!(status(&mut csms), ChargeStatus::Active { .. }));
+    assert_eq!(
+        csms.get_current_charge_status(2).unwrap(),
+        ChargeStatus::Ended {
+            kwh: 2,
+            signed_data: vec![]
+        }
+    );
+}
+
+#[test]
+fn e_charging_state_changes_are_reported() {
+    let (mut csms, station) = connect();
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    station.deliver(1, 400).expect("Cannot deliver energy");
+
+    station
+        .set_charging_state(1, "SuspendedEV")
+        .expect("Cannot change charging state");
+    assert!(matches!(
+        status(&mut csms),
+        ChargeStatus::SuspendedEV { meter: Some(meter) } if meter.energy_wh == 400
+    ));
+
+    station
+        .set_charging_state(1, "SuspendedEVSE")
+        .expect("Cannot change charging state");
+    assert!(matches!(
+        status(&mut csms),
+        ChargeStatus::SuspendedEVSE { .. }
+    ));
+
+    station
+        .set_charging_state(1, "Charging")
+        .expect("Cannot change charging state");
+    assert!(matches!(status(&mut csms), ChargeStatus::Active { .. }));
+}
+
+#[test]
+fn g_evse_faulted_during_transaction() {
+    let (mut csms, station) = connect();
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    station.deliver(1, 900).expect("Cannot deliver energy");
+
+    station
+        .notify_status(1, "Faulted")
+        .expect("Cannot send status");
+    match status(&mut csms) {
+        ChargeStatus::Faulted { error_code, meter } => {
+            assert_eq!(error_code, "OtherError");
+            assert_eq!(meter.map(|meter| meter.energy_wh), Some(900));
+        }
+        other => panic!("Unexpected status {:?}", other),
+    }
+}
+
+#[test]
+fn g_unavailable_evse() {
+    let (mut csms, station) = connect();
+
+    station
+        .notify_status(1, "Unavailable")
+        .expect("Cannot send status");
+    assert_eq!(status(&mut csms), ChargeStatus::Unavailable);
+
+    station
+        .notify_status(1, "Available")
+        .expect("Cannot send status");
+    assert_eq!(status(&mut csms), ChargeStatus::NotFound);
+}
+
+#[test]
+fn j_signed_meter_values_are_reported() {
+    let (mut csms, station) = connect();
+    let signed_data = r#"OCMF|{"RD":[{"TX":"E","RV":1.2,"RU":"kWh"}]}|{"SD":"3045"}"#;
+
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    wait_until(|| station.transaction_id(1).is_some());
+    station.deliver(1, 1200).expect("Cannot deliver energy");
+    station.sign_end(1, signed_data);
+    station.unplug(1).expect("Cannot unplug");
+
+    // The dataset is base64 encoded by the station, and reported decoded
+    assert_eq!(
+        status(&mut csms),
+        ChargeStatus::Ended {
+            kwh: 1,
+            signed_data: vec![signed_data.to_string()]
+        }
+    );
+}
+
+#[test]
+fn k01_set_charging_profile() {
+    let (mut csms, station) = connect();
+
+    csms.set_power_limit(0, 22_000, Some(1_700_000_000_000))
+        .expect("Cannot limit power");
+    assert_eq!(
+        station.charging_profile(0),
+        Some((ChargingProfilePurpose::ChargingStationMaxProfile, 22_000.0))
+    );
+
+    // Without a transaction of ours, the limit applies to the next transactions of the EVSE
+    station.plug_in(1).expect("Cannot plug in");
+    csms.set_power_limit(1, 7400, None)
+        .expect("Cannot limit power");
+    assert_eq!(
+        station.charging_profile(1),
+        Some((ChargingProfilePurpose::TxDefaultProfile, 7400.0))
+    );
+    csms.start_new_charge(1).expect("Cannot start new charge");
+    csms.set_power_limit(1, 3700, None)
+        .expect("Cannot limit power");
+    assert_eq!(
+        station.charging_profile(1),
+        Some((ChargingProfilePurpose::TxProfile, 3700.0))
+    );
+}
+
+#[test]
+fn k01_set_charging_profile_rejected() {
+    let (mut csms, station) = connect();
+    station.reject_charging_profiles(true);
+
+    let error = csms
+        .set_power_limit(1, 7400, None)
+        .unwrap_err()
+        .downcast::<ChargerError>()
+        .expect("Not a ChargerError");
+    assert_eq!(error, ChargerError::Rejected);
+    assert_eq!(station.charging_profile(1), None);
+}
@@ -1,41 +1,138 @@
-use crate::runtime::offchain;
-use crate::{
-    api::{ChargeStatus, ChargerApi},
-    mock::MockCharger,
+use crate::api::{ChargeStatus, ChargerApi};
+use crate::runtime::offchain::{
+    self, ChargerError, ConnectorId, ContractAuthorization, PresentedToken,
 };
+use log::warn;
 use std::sync::{Arc, Mutex};
 
 pub trait Externalities: Send {
-    fn start_charge(&mut self) -> bool;
-    fn get_current_charge_status(&mut self) -> offchain::ChargeStatus;
+    fn start_charge(&mut self, connector: ConnectorId) -> Result<(), ChargerError>;
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<(), ChargerError>;
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> offchain::ChargeStatus;
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        watts: u32,
+        valid_until: Option<u64>,
+    ) -> Result<(), ChargerError>;
+    fn take_presented_tokens(&mut self) -> Vec<PresentedToken>;
+    fn take_contract_authorizations(&mut self) -> Vec<ContractAuthorization>;
 }
+/// Externalities of any charger backend, including a `dyn ChargerApi` selected at runtime
 pub struct ChargerExternalities<T>
 where
-    T: ChargerApi,
+    T: ChargerApi + ?Sized,
 {
     api: Arc<Mutex<T>>,
 }
 
-impl ChargerExternalities<MockCharger> {
-    pub fn new(api: Arc<Mutex<MockCharger>>) -> ChargerExternalities<MockCharger> {
+impl<T: ChargerApi + ?Sized> ChargerExternalities<T> {
+    pub fn new(api: Arc<Mutex<T>>) -> ChargerExternalities<T> {
         ChargerExternalities { api }
     }
 }
 
-impl<T: ChargerApi + Send> Externalities for ChargerExternalities<T> {
-    fn start_charge(&mut self) -> bool {
-        return self.api.lock().unwrap().start_new_charge().is_ok();
+impl<T: ChargerApi + Send + ?Sized> Externalities for ChargerExternalities<T> {
+    fn start_charge(&mut self, connector: ConnectorId) -> Result<(), ChargerError> {
+        self.api
+            .lock()
+            .unwrap()
+            .start_new_charge(connector)
+            .map_err(|e| {
+                warn!("Cannot start charge on connector {}: {:#}", connector, e);
+                charger_error(&e)
+            })
     }
 
-    fn get_current_charge_status(&mut self) -> offchain::ChargeStatus {
-        match self.api.lock().unwrap().get_current_charge_status() {
-            Ok(ChargeStatus::Ended { kwh }) => offchain::ChargeStatus::Ended { kwh },
-            Ok(ChargeStatus::Active) => offchain::ChargeStatus::Active,
-            _ => offchain::ChargeStatus::NoCharge,
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<(), ChargerError> {
+        self.api
+            .lock()
+            .unwrap()
+            .stop_charge(connector)
+            .map_err(|e| {
+                warn!("Cannot stop charge on connector {}: {:#}", connector, e);
+                charger_error(&e)
+            })
+    }
+
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> offchain::ChargeStatus {
+        match self
+            .api
+            .lock()
+            .unwrap()
+            .get_current_charge_status(connector)
+        {
+            Ok(ChargeStatus::NotFound) => offchain::ChargeStatus::NoCharge,
+            Ok(ChargeStatus::Active { meter }) => offchain::ChargeStatus::Active { meter },
+            Ok(ChargeStatus::SuspendedEV { meter }) => {
+                offchain::ChargeStatus::SuspendedEV { meter }
+            }
+            Ok(ChargeStatus::SuspendedEVSE { meter }) => {
+                offchain::ChargeStatus::SuspendedEVSE { meter }
+            }
+            Ok(ChargeStatus::Faulted { error_code, meter }) => offchain::ChargeStatus::Faulted {
+                error_code: error_code.into_bytes(),
+                meter,
+            },
+            Ok(ChargeStatus::Unavailable) => offchain::ChargeStatus::Unavailable,
+            Ok(ChargeStatus::Ended { kwh, signed_data }) => offchain::ChargeStatus::Ended {
+                kwh,
+                signed_data: signed_data.into_iter().map(String::into_bytes).collect(),
+            },
+            Err(e) => {
+                warn!("Cannot get charge status of connector {}: {}", connector, e);
+                offchain::ChargeStatus::Unavailable
+            }
+        }
+    }
+
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        watts: u32,
+        valid_until: Option<u64>,
+    ) -> Result<(), ChargerError> {
+        self.api
+            .lock()
+            .unwrap()
+            .set_power_limit(connector, watts, valid_until)
+            .map_err(|e| {
+                warn!(
+                    "Cannot limit power of connector {} to {} W: {:#}",
+                    connector, watts, e
+                );
+                charger_error(&e)
+            })
+    }
+
+    fn take_presented_tokens(&mut self) -> Vec<PresentedToken> {
+        match self.api.lock().unwrap().take_presented_tokens() {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                warn!("Cannot get the tokens presented to the charger: {:#}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn take_contract_authorizations(&mut self) -> Vec<ContractAuthorization> {
+        match self.api.lock().unwrap().take_contract_authorizations() {
+            Ok(authorizations) => authorizations,
+            Err(e) => {
+                warn!("Cannot get the contracts presented to the charger: {:#}", e);
+                Vec::new()
+            }
         }
     }
 }
 
+/// Reason of a backend failure: the `ChargerError` it was raised with, if any
+fn charger_error(e: &anyhow::Error) -> ChargerError {
+    e.downcast_ref::<ChargerError>()
+        .cloned()
+        .unwrap_or_else(|| ChargerError::Other(e.to_string().into_bytes()))
+}
+
 sp_externalities::decl_extension! {
     pub struct ChargerExt(Box<dyn Externalities>);
 }
@@ -1,30 +1,299 @@
 use codec::{Decode, Encode};
 use sp_externalities::ExternalitiesExt;
 use sp_runtime_interface::pass_by::PassByCodec;
+use sp_std::vec::Vec;
 
 #[cfg(feature = "std")]
 use super::externalities::ChargerExt;
 
+/// Identifies an outlet of a charger (OCPP connector id / EVSE id), starting at 1
+pub type ConnectorId = u32;
+
+/// Meter reading of a connector, taken while a charge session is running
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct MeterSample {
+    /// Energy delivered since the beginning of the session, in Wh
+    pub energy_wh: u64,
+    /// Instantaneous active power, in W
+    pub power_w: u32,
+    /// Voltage, in mV
+    pub voltage_mv: u32,
+    /// Current, in mA
+    pub current_ma: u32,
+    /// Unix time of the reading, in milliseconds
+    pub timestamp: u64,
+}
+
+/// Identification presented by a driver on a connector, so that a session is requested on
+/// behalf of its owner
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PresentedToken {
+    pub connector: ConnectorId,
+    /// UID of an RFID card, or ISO 15118 contract id (eMAID), as reported by the charger
+    pub token: Vec<u8>,
+}
+
+/// Vehicle presenting its ISO 15118 contract certificate on a connector (Plug & Charge), so
+/// that a session is requested on behalf of the owner of the contract
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ContractAuthorization {
+    pub connector: ConnectorId,
+    /// Contract id (eMAID) presented by the vehicle
+    pub emaid: Vec<u8>,
+    /// DER encoded certificates, the contract certificate first and then its sub-CAs
+    pub certificate_chain: Vec<Vec<u8>>,
+}
+
 #[derive(Encode, Decode, PassByCodec)]
 pub enum ChargeStatus {
+    NoCharge,
+    /// `meter` is the last reading of the charger, if it reported one yet
+    Active {
+        meter: Option<MeterSample>,
+    },
+    SuspendedEV {
+        meter: Option<MeterSample>,
+    },
+    SuspendedEVSE {
+        meter: Option<MeterSample>,
+    },
+    /// `error_code` is the code reported by the charger (eg. OCPP `GroundFailure`)
+    Faulted {
+        error_code: Vec<u8>,
+        meter: Option<MeterSample>,
+    },
+    Unavailable,
+    /// `signed_data` are the datasets signed by the meter (OCMF) for the start and end
+    /// readings of the session, empty if the meter does not sign its readings
+    Ended {
+        kwh: u64,
+        signed_data: Vec<Vec<u8>>,
+    },
+}
+
+/// Reason why a charger could not start or stop a session
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum ChargerError {
+    /// The connector already has a session running
+    Busy,
+    /// No vehicle is plugged in the connector
+    NotPlugged,
+    /// The connector is faulted or out of service
+    Faulted,
+    /// The charger cannot be reached by its backend
+    Unreachable,
+    /// The charger refused the request
+    Rejected,
+    /// No session is running on the connector
+    NoSession,
+    /// The connector is not known by the backend
+    UnknownConnector,
+    /// Any other failure, with its description
+    Other(Vec<u8>),
+}
+
+/// Charge status returned by the first versions of get_current_charge_status
+#[derive(Encode, Decode, PassByCodec)]
+pub enum ChargeStatusV1 {
     NoCharge,
     Active,
     Ended { kwh: u64 },
 }
 
+/// Charge status returned by version 3 of get_current_charge_status
+#[derive(Encode, Decode, PassByCodec)]
+pub enum ChargeStatusV3 {
+    NoCharge,
+    Active { meter: Option<MeterSample> },
+    Ended { kwh: u64 },
+}
+
+/// Charge status returned by version 4 of get_current_charge_status
+#[derive(Encode, Decode, PassByCodec)]
+pub enum ChargeStatusV4 {
+    NoCharge,
+    Active {
+        meter: Option<MeterSample>,
+    },
+    SuspendedEV {
+        meter: Option<MeterSample>,
+    },
+    SuspendedEVSE {
+        meter: Option<MeterSample>,
+    },
+    Faulted {
+        error_code: Vec<u8>,
+        meter: Option<MeterSample>,
+    },
+    Unavailable,
+    Ended {
+        kwh: u64,
+    },
+}
+
+/// The runtimes of the first versions know no other state than an active session
+impl From<ChargeStatus> for ChargeStatusV1 {
+    fn from(status: ChargeStatus) -> Self {
+        match status {
+            ChargeStatus::NoCharge => ChargeStatusV1::NoCharge,
+            ChargeStatus::Ended { kwh, .. } => ChargeStatusV1::Ended { kwh },
+            _ => ChargeStatusV1::Active,
+        }
+    }
+}
+
+impl From<ChargeStatus> for ChargeStatusV3 {
+    fn from(status: ChargeStatus) -> Self {
+        match status {
+            ChargeStatus::NoCharge => ChargeStatusV3::NoCharge,
+            ChargeStatus::Active { meter }
+            | ChargeStatus::SuspendedEV { meter }
+            | ChargeStatus::SuspendedEVSE { meter }
+            | ChargeStatus::Faulted { meter, .. } => ChargeStatusV3::Active { meter },
+            ChargeStatus::Unavailable => ChargeStatusV3::Active { meter: None },
+            ChargeStatus::Ended { kwh, .. } => ChargeStatusV3::Ended { kwh },
+        }
+    }
+}
+
+impl From<ChargeStatus> for ChargeStatusV4 {
+    fn from(status: ChargeStatus) -> Self {
+        match status {
+            ChargeStatus::NoCharge => ChargeStatusV4::NoCharge,
+            ChargeStatus::Active { meter } => ChargeStatusV4::Active { meter },
+            ChargeStatus::SuspendedEV { meter } => ChargeStatusV4::SuspendedEV { meter },
+            ChargeStatus::SuspendedEVSE { meter } => ChargeStatusV4::SuspendedEVSE { meter },
+            ChargeStatus::Faulted { error_code, meter } => {
+                ChargeStatusV4::Faulted { error_code, meter }
+            }
+            ChargeStatus::Unavailable => ChargeStatusV4::Unavailable,
+            ChargeStatus::Ended { kwh, .. } => ChargeStatusV4::Ended { kwh },
+        }
+    }
+}
+
+/// Connector of the sessions of the runtimes which know no connector
+#[cfg(feature = "std")]
+const DEFAULT_CONNECTOR: ConnectorId = 1;
+
+/// Host functions of the charger. A released host function is never changed: a new version
+/// is added, as the runtimes already on chain keep calling the previous ones.
 #[sp_runtime_interface::runtime_interface]
 pub trait Api {
+    #[version(1)]
     fn start_charge(&mut self) -> bool {
         return self
             .extension::<ChargerExt>()
             .expect("no extension")
-            .start_charge();
+            .start_charge(DEFAULT_CONNECTOR)
+            .is_ok();
+    }
+
+    #[version(2)]
+    fn start_charge(&mut self, connector: ConnectorId) -> bool {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .start_charge(connector)
+            .is_ok();
+    }
+
+    #[version(3)]
+    fn start_charge(&mut self, connector: ConnectorId) -> Result<(), ChargerError> {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .start_charge(connector);
+    }
+
+    #[version(1)]
+    fn stop_charge(&mut self, connector: ConnectorId) -> bool {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .stop_charge(connector)
+            .is_ok();
+    }
+
+    #[version(2)]
+    fn stop_charge(&mut self, connector: ConnectorId) -> Result<(), ChargerError> {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .stop_charge(connector);
+    }
+
+    #[version(1)]
+    fn get_current_charge_status(&mut self) -> ChargeStatusV1 {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .get_current_charge_status(DEFAULT_CONNECTOR)
+            .into();
+    }
+
+    #[version(2)]
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> ChargeStatusV1 {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .get_current_charge_status(connector)
+            .into();
+    }
+
+    #[version(3)]
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> ChargeStatusV3 {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .get_current_charge_status(connector)
+            .into();
+    }
+
+    #[version(4)]
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> ChargeStatusV4 {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .get_current_charge_status(connector)
+            .into();
+    }
+
+    #[version(5)]
+    fn get_current_charge_status(&mut self, connector: ConnectorId) -> ChargeStatus {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .get_current_charge_status(connector);
+    }
+
+    fn set_power_limit(
+        &mut self,
+        connector: ConnectorId,
+        watts: u32,
+        valid_until: Option<u64>,
+    ) -> Result<(), ChargerError> {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .set_power_limit(connector, watts, valid_until);
+    }
+
+    fn take_presented_tokens(&mut self) -> Vec<PresentedToken> {
+        return self
+            .extension::<ChargerExt>()
+            .expect("no extension")
+            .take_presented_tokens();
     }
 
-    fn get_current_charge_status(&mut self) -> ChargeStatus {
+    fn take_contract_authorizations(&mut self) -> Vec<ContractAuthorization> {
         return self
             .extension::<ChargerExt>()
             .expect("no extension")
-            .get_current_charge_status();
+            .take_contract_authorizations();
     }
 }
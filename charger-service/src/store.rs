@@ -0,0 +1,124 @@
+//! Crash-safe persistence of the in-flight sessions of a charger backend, so that a node
+//! restart resumes them instead of forgetting them.
+use anyhow::Result;
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+};
+
+/// JSON file holding the state of a backend, replaced atomically on each save
+#[derive(Debug, Clone, Default)]
+pub struct SessionStore {
+    /// None when nothing is persisted
+    path: Option<PathBuf>,
+}
+
+impl SessionStore {
+    pub fn open(path: impl Into<PathBuf>) -> SessionStore {
+        SessionStore {
+            path: Some(path.into()),
+        }
+    }
+
+    /// Store which persists nothing
+    pub fn in_memory() -> SessionStore {
+        SessionStore { path: None }
+    }
+
+    /// Last saved state, or the default state if none was saved (or it cannot be read)
+    pub fn load<T: DeserializeOwned + Default>(&self) -> T {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return T::default(),
+        };
+        match fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(serde_json::from_slice(&content)?))
+        {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Cannot load sessions from {}: {}", path.display(), e);
+                T::default()
+            }
+        }
+    }
+
+    pub fn save<T: Serialize>(&self, state: &T) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written aside then renamed: a crash leaves either the previous or the new state
+        let temp_path = path.with_extension("tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&serde_json::to_vec(state)?)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Save `state`, only logging a failure: the backend keeps running in memory
+    pub fn save_or_warn<T: Serialize>(&self, state: &T) {
+        if let Err(e) = self.save(state) {
+            warn!("Cannot save sessions: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SessionStore;
+    use std::collections::HashMap;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "charger-service-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn should_reload_saved_state() {
+        let path = temp_path("reload");
+        let store = SessionStore::open(&path);
+        let mut state: HashMap<u32, u64> = store.load();
+        assert!(state.is_empty());
+
+        state.insert(1, 1500);
+        store.save(&state).expect("Cannot save state");
+
+        let reloaded: HashMap<u32, u64> = SessionStore::open(&path).load();
+        assert_eq!(reloaded, state);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn should_ignore_corrupted_state() {
+        let path = temp_path("corrupted");
+        std::fs::write(&path, b"{ not json").unwrap();
+
+        let state: HashMap<u32, u64> = SessionStore::open(&path).load();
+        assert!(state.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn should_not_persist_in_memory() {
+        let store = SessionStore::in_memory();
+        let mut state = HashMap::new();
+        state.insert(1u32, 1u64);
+        store.save(&state).expect("Cannot save state");
+
+        let reloaded: HashMap<u32, u64> = store.load();
+        assert!(reloaded.is_empty());
+    }
+}
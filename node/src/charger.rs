@@ -0,0 +1,406 @@
+//! Selection and configuration of the backend driving the charger of the node
+
+use charger_service::api::{ChargerApi, ConnectorId};
+use charger_service::mock::MockCharger;
+use charger_service::modbus::{ConnectorConfig, ModbusCharger, RegisterMap};
+use charger_service::ocpp::{v16::CentralSystem, v201::Csms, ChargePointCredentials};
+use charger_service::store::SessionStore;
+use sc_service::error::Error as ServiceError;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use structopt::StructOpt;
+
+/// Charger shared by the offchain workers
+pub type SharedCharger = Arc<Mutex<dyn ChargerApi + Send>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChargerBackend {
+    /// Simulated charger
+    Mock,
+    /// Charger speaking OCPP to the node
+    Ocpp,
+    /// Relays and energy meters reached over Modbus TCP
+    Modbus,
+}
+
+impl Default for ChargerBackend {
+    fn default() -> Self {
+        ChargerBackend::Mock
+    }
+}
+
+impl FromStr for ChargerBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mock" => Ok(ChargerBackend::Mock),
+            "ocpp" => Ok(ChargerBackend::Ocpp),
+            "modbus" => Ok(ChargerBackend::Modbus),
+            _ => Err(format!("Unknown charger backend: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, StructOpt)]
+pub struct ChargerParams {
+    /// Backend driving the charger: mock, ocpp or modbus
+    #[structopt(long, default_value = "mock")]
+    pub charger_backend: ChargerBackend,
+
+    /// TOML file with the settings of the charger backends
+    #[structopt(long, parse(from_os_str))]
+    pub charger_config: Option<PathBuf>,
+}
+
+/// Content of the `--charger-config` file, with a table for each backend
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ChargerConfig {
+    /// File persisting the in-flight sessions, instead of the default one of the chain
+    sessions_file: Option<PathBuf>,
+    mock: MockConfig,
+    ocpp: OcppConfig,
+    modbus: ModbusConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct MockConfig {
+    /// Bounds of the session durations, in seconds
+    min_duration: u64,
+    max_duration: u64,
+    seed: Option<u64>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            min_duration: 20,
+            max_duration: 60,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct OcppConfig {
+    /// OCPP version of the charger: `1.6` or `2.0.1`
+    version: String,
+    /// Address the charger connects to
+    listen: String,
+    /// Identity of the charger (last segment of the URL it connects to), and password of its
+    /// HTTP Basic authentication: other chargers are refused
+    identity: Option<String>,
+    password: Option<String>,
+    /// Id tag (1.6) or central id token (2.0.1) authorizing the sessions
+    id_tag: Option<String>,
+}
+
+impl Default for OcppConfig {
+    fn default() -> Self {
+        OcppConfig {
+            version: "1.6".to_string(),
+            listen: "0.0.0.0:9000".to_string(),
+            identity: None,
+            password: None,
+            id_tag: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct ModbusConfig {
+    /// Modbus TCP server of the relays and meters (eg. a gateway)
+    address: String,
+    connectors: Vec<ModbusConnectorConfig>,
+}
+
+impl Default for ModbusConfig {
+    fn default() -> Self {
+        ModbusConfig {
+            address: "127.0.0.1:502".to_string(),
+            connectors: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModbusConnectorConfig {
+    connector: ConnectorId,
+    unit_id: u8,
+    relay_coil: u16,
+    /// Known meter model (eg. `sdm630`), or the registers of the meter
+    model: Option<String>,
+    meter: Option<RegisterMap>,
+    /// Seconds without power after which the vehicle is deemed unplugged, ending its session
+    idle_timeout: Option<u64>,
+}
+
+impl ModbusConnectorConfig {
+    fn register_map(&self) -> Result<RegisterMap, ServiceError> {
+        match (&self.model, &self.meter) {
+            (_, Some(meter)) => Ok(meter.clone()),
+            (Some(model), None) => RegisterMap::model(model)
+                .ok_or_else(|| ServiceError::Other(format!("Unknown meter model: {}", model))),
+            (None, None) => Err(ServiceError::Other(format!(
+                "No meter model for connector {}",
+                self.connector
+            ))),
+        }
+    }
+}
+
+impl ChargerParams {
+    /// Build the charger of the selected backend, resuming the sessions persisted in
+    /// `sessions_file` (if any) when the node was stopped
+    pub fn new_charger(
+        &self,
+        sessions_file: Option<PathBuf>,
+    ) -> Result<SharedCharger, ServiceError> {
+        let config = match &self.charger_config {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).map_err(|e| {
+                    ServiceError::Other(format!("Cannot read {}: {}", path.display(), e))
+                })?;
+                toml::from_str::<ChargerConfig>(&content).map_err(|e| {
+                    ServiceError::Other(format!("Invalid charger config {}: {}", path.display(), e))
+                })?
+            }
+            None => ChargerConfig::default(),
+        };
+        let store = match config.sessions_file.or(sessions_file) {
+            Some(path) => {
+                log::info!("Persisting charger sessions in {}", path.display());
+                SessionStore::open(path)
+            }
+            None => SessionStore::in_memory(),
+        };
+
+        let charger: SharedCharger = match self.charger_backend {
+            ChargerBackend::Mock => {
+                if config.mock.min_duration > config.mock.max_duration {
+                    return Err(ServiceError::Other(
+                        "The min_duration of the mock charger exceeds its max_duration".to_string(),
+                    ));
+                }
+                let mut builder = MockCharger::builder()
+                    .random_duration(config.mock.min_duration, config.mock.max_duration)
+                    .store(store);
+                if let Some(seed) = config.mock.seed {
+                    builder = builder.seed(seed);
+                }
+                Arc::new(Mutex::new(builder.build()))
+            }
+            ChargerBackend::Ocpp => {
+                let ocpp = config.ocpp;
+                let credentials = match (&ocpp.identity, &ocpp.password) {
+                    (Some(identity), Some(password)) => ChargePointCredentials {
+                        identity: identity.clone(),
+                        password: password.clone(),
+                    },
+                    _ => {
+                        return Err(ServiceError::Other(
+                            "The identity and password of the OCPP charger are not configured"
+                                .to_string(),
+                        ))
+                    }
+                };
+                let listen_error =
+                    |e| ServiceError::Other(format!("Cannot listen on {}: {}", ocpp.listen, e));
+                match ocpp.version.as_str() {
+                    "1.6" => {
+                        let mut central_system = CentralSystem::listen(&ocpp.listen, credentials)
+                            .map_err(listen_error)?
+                            .with_store(store);
+                        if let Some(id_tag) = &ocpp.id_tag {
+                            central_system = central_system.with_id_tag(id_tag);
+                        }
+                        Arc::new(Mutex::new(central_system))
+                    }
+                    "2.0.1" => {
+                        let mut csms = Csms::listen(&ocpp.listen, credentials)
+                            .map_err(listen_error)?
+                            .with_store(store);
+                        if let Some(id_token) = &ocpp.id_tag {
+                            csms = csms.with_id_token(id_token);
+                        }
+                        Arc::new(Mutex::new(csms))
+                    }
+                    version => {
+                        return Err(ServiceError::Other(format!(
+                            "Unsupported OCPP version: {}",
+                            version
+                        )))
+                    }
+                }
+            }
+            ChargerBackend::Modbus => {
+                let mut charger = ModbusCharger::new(&config.modbus.address).with_store(store);
+                for connector in &config.modbus.connectors {
+                    charger = charger.with_connector(
+                        connector.connector,
+                        ConnectorConfig {
+                            unit_id: connector.unit_id,
+                            relay_coil: connector.relay_coil,
+                            meter: connector.register_map()?,
+                            idle_timeout: connector.idle_timeout.map(Duration::from_secs),
+                        },
+                    );
+                }
+                Arc::new(Mutex::new(charger))
+            }
+        };
+        log::info!("Using {:?} charger backend", self.charger_backend);
+        Ok(charger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parameters of `backend`, configured by `config` (TOML) written in a file named `name`
+    fn params(backend: ChargerBackend, name: &str, config: &str) -> ChargerParams {
+        let path = std::env::temp_dir().join(format!(
+            "charger-config-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, config).expect("Cannot write charger config");
+        ChargerParams {
+            charger_backend: backend,
+            charger_config: Some(path),
+        }
+    }
+
+    fn error(result: Result<SharedCharger, ServiceError>) -> String {
+        match result {
+            Ok(_) => panic!("The charger config should be rejected"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn should_parse_charger_backend() {
+        let params = ChargerParams::from_iter_safe(&["node", "--charger-backend", "modbus"])
+            .expect("Cannot parse charger params");
+        assert_eq!(params.charger_backend, ChargerBackend::Modbus);
+        assert_eq!(
+            ChargerParams::from_iter_safe(&["node"])
+                .unwrap()
+                .charger_backend,
+            ChargerBackend::Mock
+        );
+        assert!(ChargerParams::from_iter_safe(&["node", "--charger-backend", "ocpi"]).is_err());
+        assert_eq!(
+            "ocpi".parse::<ChargerBackend>(),
+            Err("Unknown charger backend: ocpi".to_string())
+        );
+    }
+
+    #[test]
+    fn should_parse_charger_config() {
+        let config: ChargerConfig = toml::from_str(
+            r#"
+            sessions_file = "/var/lib/charger/sessions.json"
+
+            [mock]
+            min_duration = 5
+            max_duration = 5
+
+            [ocpp]
+            version = "2.0.1"
+            identity = "CP001"
+            password = "0123456789abcdef"
+
+            [modbus]
+            address = "192.168.1.10:502"
+
+            [[modbus.connectors]]
+            connector = 1
+            unit_id = 1
+            relay_coil = 0
+            model = "sdm630"
+            idle_timeout = 300
+
+            [[modbus.connectors]]
+            connector = 2
+            unit_id = 2
+            relay_coil = 1
+
+            [modbus.connectors.meter]
+            energy = { address = 0x0100, kind = "holding", format = "u32", scale = 10.0 }
+            "#,
+        )
+        .expect("Cannot parse charger config");
+
+        assert_eq!(
+            config.sessions_file,
+            Some(PathBuf::from("/var/lib/charger/sessions.json"))
+        );
+        assert_eq!(config.mock.min_duration, 5);
+        assert_eq!(config.mock.seed, None);
+        assert_eq!(config.ocpp.version, "2.0.1");
+        // Default values of the omitted keys
+        assert_eq!(config.ocpp.listen, "0.0.0.0:9000");
+        assert_eq!(config.ocpp.identity.as_deref(), Some("CP001"));
+        assert_eq!(config.ocpp.id_tag, None);
+        assert_eq!(config.modbus.address, "192.168.1.10:502");
+        assert_eq!(config.modbus.connectors.len(), 2);
+        assert_eq!(
+            config.modbus.connectors[0].register_map().ok(),
+            RegisterMap::model("sdm630")
+        );
+        assert!(config.modbus.connectors[1].register_map().is_ok());
+        assert_eq!(config.modbus.connectors[0].idle_timeout, Some(300));
+        assert_eq!(config.modbus.connectors[1].idle_timeout, None);
+
+        assert!(toml::from_str::<ChargerConfig>("[mock]\nmin_duration = \"20\"").is_err());
+    }
+
+    #[test]
+    fn should_build_configured_charger() {
+        let params = params(
+            ChargerBackend::Modbus,
+            "modbus",
+            "[[modbus.connectors]]\nconnector = 1\nunit_id = 1\nrelay_coil = 0\nmodel = \"sdm120\"",
+        );
+        assert!(params.new_charger(None).is_ok());
+        assert!(ChargerParams::default().new_charger(None).is_ok());
+    }
+
+    #[test]
+    fn should_reject_invalid_charger_config() {
+        let mock = params(
+            ChargerBackend::Mock,
+            "mock",
+            "[mock]\nmin_duration = 60\nmax_duration = 20",
+        );
+        assert!(error(mock.new_charger(None)).contains("max_duration"));
+
+        let ocpp = params(ChargerBackend::Ocpp, "ocpp", "[ocpp]\nversion = \"2.0.1\"");
+        assert!(error(ocpp.new_charger(None)).contains("identity and password"));
+        let ocpp = params(
+            ChargerBackend::Ocpp,
+            "ocpp-version",
+            "[ocpp]\nversion = \"1.5\"\nidentity = \"CP001\"\npassword = \"secret\"",
+        );
+        assert!(error(ocpp.new_charger(None)).contains("Unsupported OCPP version"));
+
+        let modbus = params(
+            ChargerBackend::Modbus,
+            "modbus-model",
+            "[[modbus.connectors]]\nconnector = 1\nunit_id = 1\nrelay_coil = 0\nmodel = \"abb\"",
+        );
+        assert!(error(modbus.new_charger(None)).contains("Unknown meter model"));
+
+        let invalid = params(ChargerBackend::Mock, "invalid", "[mock]\nseed = \"42\"");
+        assert!(error(invalid.new_charger(None)).contains("Invalid charger config"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::charger::ChargerParams;
 use sc_cli::RunCmd;
 use structopt::StructOpt;
 
@@ -8,6 +9,9 @@ pub struct Cli {
 
     #[structopt(flatten)]
     pub run: RunCmd,
+
+    #[structopt(flatten)]
+    pub charger: ChargerParams,
 }
 
 #[derive(Debug, StructOpt)]
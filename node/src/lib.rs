@@ -1,4 +1,5 @@
 pub mod chain_spec;
+pub mod charger;
 pub mod rpc;
 pub mod service;
 
@@ -7,7 +7,8 @@
 
 use std::sync::Arc;
 
-use charger_node_runtime::{opaque::Block, AccountId, Balance, BlockNumber, Index};
+use charge_session_rpc::{ChargeSession, ChargeSessionApi};
+use charger_node_runtime::{opaque::Block, AccountId, Balance, BlockNumber, Hash, Index, Moment};
 use pallet_contracts_rpc::{Contracts, ContractsApi};
 use session_payment_rpc::{SessionPayment, SessionPaymentApi};
 pub use sc_rpc_api::DenyUnsafe;
@@ -36,6 +37,7 @@ where
     C::Api: pallet_contracts_rpc::ContractsRuntimeApi<Block, AccountId, Balance, BlockNumber>,
     C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
 	C::Api: session_payment_rpc::SessionPaymentRuntimeApi<Block>,
+    C::Api: charge_session_rpc::ChargeSessionRuntimeApi<Block, AccountId, Moment, Hash>,
     C::Api: BlockBuilder<Block>,
     P: TransactionPool + 'static,
 {
@@ -71,5 +73,10 @@ where
 		SessionPayment::new(client.clone()),
 	));
 
+    // History of the charge sessions
+    io.extend_with(ChargeSessionApi::to_delegate(ChargeSession::new(
+        client.clone(),
+    )));
+
     io
 }
@@ -10,13 +10,14 @@ use sc_keystore::LocalKeystore;
 use sc_service::{error::Error as ServiceError, Configuration, TaskManager};
 use sp_consensus_aura::sr25519::AuthorityPair as AuraPair;
 use sp_inherents::InherentDataProviders;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::charger::{ChargerParams, SharedCharger};
 use charger_service::runtime::externalities::ChargerExt;
 
 struct ExtensionsFactory {
-    api: Arc<Mutex<charger_service::mock::MockCharger>>,
+    api: SharedCharger,
 }
 
 impl sc_client_api::execution_extensions::ExtensionsFactory for ExtensionsFactory {
@@ -85,11 +86,6 @@ pub fn new_partial(
         sc_service::new_full_parts::<Block, RuntimeApi, Executor>(&config)?;
     let client = Arc::new(client);
 
-    let api = Arc::new(Mutex::new(charger_service::mock::MockCharger::new()));
-    client
-        .execution_extensions()
-        .set_extensions_factory(Box::new(ExtensionsFactory { api }));
-
     let select_chain = sc_consensus::LongestChain::new(backend.clone());
 
     let transaction_pool = sc_transaction_pool::BasicPool::new_full(
@@ -143,7 +139,10 @@ fn remote_keystore(_url: &String) -> Result<Arc<LocalKeystore>, &'static str> {
 }
 
 /// Builds a new service for a full client.
-pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError> {
+pub fn new_full(
+    mut config: Configuration,
+    charger: &ChargerParams,
+) -> Result<TaskManager, ServiceError> {
     let sc_service::PartialComponents {
         client,
         backend,
@@ -156,6 +155,17 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
         other: (block_import, grandpa_link),
     } = new_partial(&config)?;
 
+    // Built for the full node only: the other commands must not reach the charger
+    let sessions_file = config.base_path.as_ref().map(|base_path| {
+        base_path
+            .config_dir(config.chain_spec.id())
+            .join("charger_sessions.json")
+    });
+    let api = charger.new_charger(sessions_file)?;
+    client
+        .execution_extensions()
+        .set_extensions_factory(Box::new(ExtensionsFactory { api }));
+
     if let Some(url) = &config.keystore_remote {
         match remote_keystore(url) {
             Ok(k) => keystore_container.set_remote_keystore(k),
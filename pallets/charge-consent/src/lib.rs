@@ -73,6 +73,11 @@ pub mod pallet {
         ) -> Option<SessionConsent<T::AccountId, T::AccountId>> {
             ChargeConsent::<T>::get(&session_id)
         }
+
+        /// Remove the consent of a session that will not take place
+        pub fn remove_consent(session_id: T::Hash) {
+            ChargeConsent::<T>::remove(&session_id);
+        }
     }
 }
 
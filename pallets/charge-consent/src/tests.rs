@@ -129,3 +129,19 @@ fn should_not_find_consent_from_id() {
         assert!(ChargeConsent::get_consent_from_session_id(session_id).is_none());
     });
 }
+
+#[test]
+fn should_remove_consent() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        let charger = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let session_id = <Test as frame_system::Config>::Hashing::hash(&user);
+        assert_ok!(ChargeConsent::new_consent_for_user(Origin::signed(user), charger, session_id));
+        ChargeConsent::remove_consent(session_id);
+        assert!(ChargeConsent::get_consent_from_session_id(session_id).is_none());
+    });
+}
@@ -0,0 +1,100 @@
+//! RPC interface of the charge-session pallet: history of the ended sessions
+
+pub use charge_session_runtime_api::ChargeSessionApi as ChargeSessionRuntimeApi;
+use codec::Codec;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use pallet_charge_session::SessionRecord;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use std::sync::Arc;
+
+/// Error code of the failed calls to the runtime
+const RUNTIME_ERROR: i64 = 1;
+
+#[rpc]
+pub trait ChargeSessionApi<BlockHash, AccountId, Moment, Hash> {
+    /// Ended sessions of `user`, the most recent first, by pages of `page_size` sessions
+    #[rpc(name = "chargeSession_sessionsByUser")]
+    fn sessions_by_user(
+        &self,
+        user: AccountId,
+        page: u32,
+        page_size: u32,
+        at: Option<BlockHash>,
+    ) -> Result<Vec<SessionRecord<AccountId, Moment, Hash>>>;
+
+    /// Ended sessions of `charger`, the most recent first, by pages of `page_size` sessions
+    #[rpc(name = "chargeSession_sessionsByCharger")]
+    fn sessions_by_charger(
+        &self,
+        charger: AccountId,
+        page: u32,
+        page_size: u32,
+        at: Option<BlockHash>,
+    ) -> Result<Vec<SessionRecord<AccountId, Moment, Hash>>>;
+}
+
+/// Implements `ChargeSessionApi` with the runtime API of the client
+pub struct ChargeSession<C, B> {
+    client: Arc<C>,
+    _marker: std::marker::PhantomData<B>,
+}
+
+impl<C, B> ChargeSession<C, B> {
+    pub fn new(client: Arc<C>) -> Self {
+        ChargeSession {
+            client,
+            _marker: Default::default(),
+        }
+    }
+}
+
+impl<C, Block, AccountId, Moment, Hash>
+    ChargeSessionApi<<Block as BlockT>::Hash, AccountId, Moment, Hash> for ChargeSession<C, Block>
+where
+    Block: BlockT,
+    C: Send + Sync + 'static,
+    C: ProvideRuntimeApi<Block>,
+    C: HeaderBackend<Block>,
+    C::Api: ChargeSessionRuntimeApi<Block, AccountId, Moment, Hash>,
+    AccountId: Codec,
+    Moment: Codec,
+    Hash: Codec,
+{
+    fn sessions_by_user(
+        &self,
+        user: AccountId,
+        page: u32,
+        page_size: u32,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<SessionRecord<AccountId, Moment, Hash>>> {
+        let api = self.client.runtime_api();
+        // The best block when the block hash is not supplied
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.sessions_by_user(&at, user, page, page_size)
+            .map_err(runtime_error)
+    }
+
+    fn sessions_by_charger(
+        &self,
+        charger: AccountId,
+        page: u32,
+        page_size: u32,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<SessionRecord<AccountId, Moment, Hash>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.sessions_by_charger(&at, charger, page, page_size)
+            .map_err(runtime_error)
+    }
+}
+
+fn runtime_error(error: impl std::fmt::Debug) -> RpcError {
+    RpcError {
+        code: ErrorCode::ServerError(RUNTIME_ERROR),
+        message: "Unable to query the session history".into(),
+        data: Some(format!("{:?}", error).into()),
+    }
+}
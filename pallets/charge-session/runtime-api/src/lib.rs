@@ -0,0 +1,29 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Codec;
+use pallet_charge_session::SessionRecord;
+use sp_std::vec::Vec;
+
+sp_api::decl_runtime_apis! {
+    /// History of the ended charge sessions
+    pub trait ChargeSessionApi<AccountId, Moment, Hash>
+    where
+        AccountId: Codec,
+        Moment: Codec,
+        Hash: Codec,
+    {
+        /// Ended sessions of `user`, the most recent first, by pages of `page_size` sessions
+        fn sessions_by_user(
+            user: AccountId,
+            page: u32,
+            page_size: u32,
+        ) -> Vec<SessionRecord<AccountId, Moment, Hash>>;
+
+        /// Ended sessions of `charger`, the most recent first, by pages of `page_size` sessions
+        fn sessions_by_charger(
+            charger: AccountId,
+            page: u32,
+            page_size: u32,
+        ) -> Vec<SessionRecord<AccountId, Moment, Hash>>;
+    }
+}
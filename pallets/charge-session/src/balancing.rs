@@ -0,0 +1,42 @@
+//! Split of the power of a site between the chargers sharing its grid connection
+use core::cmp::Ordering;
+use sp_std::vec::Vec;
+
+/// Split `max_power_w` between `chargers`, given as `(charger, active sessions, power limit)`,
+/// in proportion of their active sessions. A charger never gets more than its own limit: the
+/// power it cannot take is split between the others. Chargers without session get nothing
+/// and are left out of the result.
+pub fn split_power<C>(max_power_w: u32, chargers: Vec<(C, u32, Option<u32>)>) -> Vec<(C, u32)> {
+    let mut chargers: Vec<_> = chargers
+        .into_iter()
+        .filter(|(_, sessions, _)| *sessions > 0)
+        .collect();
+    // The chargers with the lowest limit per session are served first, so that the power
+    // they leave is known when the others are served
+    chargers.sort_by(|(_, a_sessions, a_limit), (_, b_sessions, b_limit)| {
+        match (a_limit, b_limit) {
+            (Some(a_limit), Some(b_limit)) => {
+                (*a_limit as u64 * *b_sessions as u64).cmp(&(*b_limit as u64 * *a_sessions as u64))
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    });
+
+    let mut remaining_w = max_power_w as u64;
+    let mut remaining_sessions: u64 = chargers
+        .iter()
+        .map(|(_, sessions, _)| *sessions as u64)
+        .sum();
+    chargers
+        .into_iter()
+        .map(|(charger, sessions, limit)| {
+            let share = remaining_w * sessions as u64 / remaining_sessions;
+            let power = limit.map_or(share, |limit| share.min(limit as u64));
+            remaining_w -= power;
+            remaining_sessions -= sessions as u64;
+            (charger, power as u32)
+        })
+        .collect()
+}
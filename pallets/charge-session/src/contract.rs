@@ -0,0 +1,391 @@
+//! Verification of the ISO 15118 contract certificates presented by the vehicles (Plug & Charge).
+//!
+//! The contract certificate of a vehicle carries its contract id (eMAID) as the common name of
+//! its subject, and is issued by the sub-CAs of a mobility operator up to a trusted root. Only
+//! the certificates of ISO 15118-2, signed with ECDSA-secp256r1-SHA256, are supported.
+//!
+//! The certificates are read with a minimal DER reader, which only goes through the fields
+//! needed to verify the chain.
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use sp_std::vec::Vec;
+
+/// Longest chain of a contract: the contract certificate and up to two sub-CAs, the root
+/// being excluded
+pub const MAX_CHAIN_LENGTH: usize = 3;
+/// Longest certificate allowed by ISO 15118-2, in bytes
+pub const MAX_CERTIFICATE_LENGTH: usize = 800;
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_EXTENSIONS: u8 = 0xa3;
+
+/// 1.2.840.10045.4.3.2
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+/// 1.2.840.10045.2.1
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+/// 1.2.840.10045.3.1.7
+const OID_SECP256R1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+/// 2.5.4.3
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// 2.5.29.19
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+
+/// Contract of a verified certificate chain
+#[derive(Debug, PartialEq, Eq)]
+pub struct Contract {
+    pub emaid: Vec<u8>,
+    /// End of the validity of the chain, as unix time in seconds
+    pub not_after: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ContractError {
+    /// A certificate cannot be read, or the chain is empty or too long
+    Malformed,
+    /// A certificate is not signed with ECDSA-secp256r1-SHA256, or has another kind of key
+    UnsupportedAlgorithm,
+    /// A certificate is not signed by the key of its issuer
+    BadSignature,
+    /// The chain is not issued by any of the trusted roots
+    UntrustedRoot,
+    /// A certificate of the chain is expired, or not valid yet
+    NotValidNow,
+    /// A certificate issues another, but is not a certificate authority
+    NotACertificateAuthority,
+    /// The contract certificate has no common name
+    NoEmaid,
+}
+
+/// Fields of a certificate used to verify a chain
+struct Certificate<'a> {
+    tbs: &'a [u8],
+    signature: Signature,
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    not_before: u64,
+    not_after: u64,
+    public_key: VerifyingKey,
+    is_ca: bool,
+}
+
+impl<'a> Certificate<'a> {
+    fn parse(der: &'a [u8]) -> Result<Certificate<'a>, ContractError> {
+        if der.len() > MAX_CERTIFICATE_LENGTH {
+            return Err(ContractError::Malformed);
+        }
+        let mut certificate = Reader::new(Reader::new(der).single(TAG_SEQUENCE)?);
+        let (tbs, tbs_der) = certificate.read_with_encoding(TAG_SEQUENCE)?;
+        expect_ecdsa_with_sha256(certificate.read(TAG_SEQUENCE)?)?;
+        let signature = Signature::from_der(bit_string(certificate.read(TAG_BIT_STRING)?)?)
+            .map_err(|_| ContractError::Malformed)?;
+
+        let mut tbs = Reader::new(tbs);
+        tbs.skip_optional(TAG_VERSION)?;
+        tbs.read(TAG_INTEGER)?;
+        expect_ecdsa_with_sha256(tbs.read(TAG_SEQUENCE)?)?;
+        let (_, issuer) = tbs.read_with_encoding(TAG_SEQUENCE)?;
+        let mut validity = Reader::new(tbs.read(TAG_SEQUENCE)?);
+        let not_before = validity.read_time()?;
+        let not_after = validity.read_time()?;
+        let (_, subject) = tbs.read_with_encoding(TAG_SEQUENCE)?;
+        let public_key = read_public_key(tbs.read(TAG_SEQUENCE)?)?;
+        let mut is_ca = false;
+        while !tbs.is_empty() {
+            let (tag, content) = tbs.read_any()?;
+            if tag == TAG_EXTENSIONS {
+                is_ca = read_is_ca(Reader::new(content).single(TAG_SEQUENCE)?)?;
+            }
+        }
+
+        Ok(Certificate {
+            tbs: tbs_der,
+            signature,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            public_key,
+            is_ca,
+        })
+    }
+
+    /// Check that this certificate is issued by `issuer`
+    fn verify_issued_by(&self, issuer: &Certificate) -> Result<(), ContractError> {
+        if self.issuer != issuer.subject {
+            return Err(ContractError::BadSignature);
+        }
+        if !issuer.is_ca {
+            return Err(ContractError::NotACertificateAuthority);
+        }
+        issuer
+            .public_key
+            .verify(self.tbs, &self.signature)
+            .map_err(|_| ContractError::BadSignature)
+    }
+
+    fn is_valid_at(&self, now: u64) -> bool {
+        self.not_before <= now && now <= self.not_after
+    }
+
+    /// First common name of the subject
+    fn common_name(&self) -> Result<Option<&'a [u8]>, ContractError> {
+        let mut subject = Reader::new(Reader::new(self.subject).single(TAG_SEQUENCE)?);
+        while !subject.is_empty() {
+            let mut attributes = Reader::new(subject.read(TAG_SET)?);
+            while !attributes.is_empty() {
+                let mut attribute = Reader::new(attributes.read(TAG_SEQUENCE)?);
+                if attribute.read(TAG_OID)? == OID_COMMON_NAME {
+                    // Any string type (UTF8String, PrintableString...)
+                    return Ok(Some(attribute.read_any()?.1));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Check that `certificate` can be a trusted root: a self-signed certificate authority
+pub fn is_valid_root(certificate: &[u8]) -> bool {
+    match Certificate::parse(certificate) {
+        Ok(root) => root.issuer == root.subject && root.verify_issued_by(&root).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Verify `chain` (the contract certificate first, then its sub-CAs) at `now` (unix time in
+/// seconds): each certificate must be issued by the next one, the last one by one of `roots`
+pub fn verify_contract(
+    chain: &[Vec<u8>],
+    roots: &[Vec<u8>],
+    now: u64,
+) -> Result<Contract, ContractError> {
+    if chain.is_empty() || chain.len() > MAX_CHAIN_LENGTH {
+        return Err(ContractError::Malformed);
+    }
+    let chain = chain
+        .iter()
+        .map(|der| Certificate::parse(der))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (certificate, issuer) in chain.iter().zip(chain.iter().skip(1)) {
+        certificate.verify_issued_by(issuer)?;
+    }
+
+    // The roots which cannot be read are ignored
+    let last = chain.last().expect("chain is not empty");
+    let root = roots
+        .iter()
+        .filter_map(|der| Certificate::parse(der).ok())
+        .find(|root| last.verify_issued_by(root).is_ok())
+        .ok_or(ContractError::UntrustedRoot)?;
+
+    if !chain
+        .iter()
+        .chain(Some(&root))
+        .all(|certificate| certificate.is_valid_at(now))
+    {
+        return Err(ContractError::NotValidNow);
+    }
+    let emaid = chain[0].common_name()?.ok_or(ContractError::NoEmaid)?;
+    let not_after = chain
+        .iter()
+        .chain(Some(&root))
+        .map(|certificate| certificate.not_after)
+        .min()
+        .unwrap_or_default();
+    Ok(Contract {
+        emaid: emaid.to_vec(),
+        not_after,
+    })
+}
+
+fn expect_ecdsa_with_sha256(algorithm: &[u8]) -> Result<(), ContractError> {
+    // The parameters of ecdsa-with-SHA256 are absent
+    match Reader::new(algorithm).read(TAG_OID) {
+        Ok(OID_ECDSA_WITH_SHA256) => Ok(()),
+        Ok(_) => Err(ContractError::UnsupportedAlgorithm),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_public_key(info: &[u8]) -> Result<VerifyingKey, ContractError> {
+    let mut info = Reader::new(info);
+    let mut algorithm = Reader::new(info.read(TAG_SEQUENCE)?);
+    if algorithm.read(TAG_OID)? != OID_EC_PUBLIC_KEY || algorithm.read(TAG_OID)? != OID_SECP256R1 {
+        return Err(ContractError::UnsupportedAlgorithm);
+    }
+    VerifyingKey::from_sec1_bytes(bit_string(info.read(TAG_BIT_STRING)?)?)
+        .map_err(|_| ContractError::Malformed)
+}
+
+/// Whether the extensions of a certificate make it a certificate authority (basic constraints)
+fn read_is_ca(extensions: &[u8]) -> Result<bool, ContractError> {
+    let mut extensions = Reader::new(extensions);
+    while !extensions.is_empty() {
+        let mut extension = Reader::new(extensions.read(TAG_SEQUENCE)?);
+        if extension.read(TAG_OID)? != OID_BASIC_CONSTRAINTS {
+            continue;
+        }
+        extension.skip_optional(TAG_BOOLEAN)?;
+        let mut constraints =
+            Reader::new(Reader::new(extension.read(TAG_OCTET_STRING)?).single(TAG_SEQUENCE)?);
+        // cA is omitted when false
+        return Ok(!constraints.is_empty() && constraints.read(TAG_BOOLEAN)? != [0]);
+    }
+    Ok(false)
+}
+
+/// Content of a bit string without unused bits
+fn bit_string(content: &[u8]) -> Result<&[u8], ContractError> {
+    match content.split_first() {
+        Some((0, bits)) => Ok(bits),
+        _ => Err(ContractError::Malformed),
+    }
+}
+
+/// UTCTime (`YYMMDDHHMMSSZ`) or GeneralizedTime (`YYYYMMDDHHMMSSZ`), as unix time in seconds
+fn parse_time(tag: u8, time: &[u8]) -> Result<u64, ContractError> {
+    let digits = match (tag, time.split_last()) {
+        (TAG_UTC_TIME, Some((b'Z', digits))) if digits.len() == 12 => digits,
+        (TAG_GENERALIZED_TIME, Some((b'Z', digits))) if digits.len() == 14 => digits,
+        _ => return Err(ContractError::Malformed),
+    };
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return Err(ContractError::Malformed);
+    }
+    let number = |digits: &[u8]| {
+        digits
+            .iter()
+            .fold(0i64, |number, digit| number * 10 + (digit - b'0') as i64)
+    };
+    let (year, rest) = match tag {
+        // Years 1950 to 2049
+        TAG_UTC_TIME => match number(&digits[..2]) {
+            year if year >= 50 => (1900 + year, &digits[2..]),
+            year => (2000 + year, &digits[2..]),
+        },
+        _ => (number(&digits[..4]), &digits[4..]),
+    };
+    let (month, day) = (number(&rest[..2]), number(&rest[2..4]));
+    let (hour, minute, second) = (
+        number(&rest[4..6]),
+        number(&rest[6..8]),
+        number(&rest[8..10]),
+    );
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(ContractError::Malformed);
+    }
+
+    // Days since the epoch of the civil date (proleptic Gregorian calendar)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let time = days * 86_400 + hour * 3600 + minute * 60 + second;
+    if time < 0 {
+        return Err(ContractError::Malformed);
+    }
+    Ok(time as u64)
+}
+
+/// Reader of consecutive DER encoded values, with single byte tags and definite lengths
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Tag and content of the next value
+    fn read_any(&mut self) -> Result<(u8, &'a [u8]), ContractError> {
+        let (tag, content, _) = self.read_value()?;
+        Ok((tag, content))
+    }
+
+    /// Content of the next value, which must have `tag`
+    fn read(&mut self, tag: u8) -> Result<&'a [u8], ContractError> {
+        Ok(self.read_with_encoding(tag)?.0)
+    }
+
+    /// Content and whole encoding of the next value, which must have `tag`
+    fn read_with_encoding(&mut self, tag: u8) -> Result<(&'a [u8], &'a [u8]), ContractError> {
+        match self.read_value()? {
+            (read, content, encoding) if read == tag => Ok((content, encoding)),
+            _ => Err(ContractError::Malformed),
+        }
+    }
+
+    /// Content of the only value left, which must have `tag`
+    fn single(mut self, tag: u8) -> Result<&'a [u8], ContractError> {
+        let content = self.read(tag)?;
+        if !self.is_empty() {
+            return Err(ContractError::Malformed);
+        }
+        Ok(content)
+    }
+
+    /// Skip the next value if it has `tag`
+    fn skip_optional(&mut self, tag: u8) -> Result<(), ContractError> {
+        if self.data.first() == Some(&tag) {
+            self.read_value()?;
+        }
+        Ok(())
+    }
+
+    fn read_time(&mut self) -> Result<u64, ContractError> {
+        let (tag, time) = self.read_any()?;
+        parse_time(tag, time)
+    }
+
+    fn read_value(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), ContractError> {
+        let (&tag, rest) = self.data.split_first().ok_or(ContractError::Malformed)?;
+        // High tag numbers are not used in certificates
+        if tag & 0x1f == 0x1f {
+            return Err(ContractError::Malformed);
+        }
+        let (&first, rest) = rest.split_first().ok_or(ContractError::Malformed)?;
+        let (length, rest) = match first {
+            0..=0x7f => (first as usize, rest),
+            0x81 | 0x82 => {
+                let bytes = (first & 0x7f) as usize;
+                if rest.len() < bytes {
+                    return Err(ContractError::Malformed);
+                }
+                let length = rest[..bytes]
+                    .iter()
+                    .fold(0usize, |length, byte| length << 8 | *byte as usize);
+                (length, &rest[bytes..])
+            }
+            // Indefinite or longer lengths do not fit a certificate
+            _ => return Err(ContractError::Malformed),
+        };
+        if rest.len() < length {
+            return Err(ContractError::Malformed);
+        }
+        let header = self.data.len() - rest.len();
+        let encoding = &self.data[..header + length];
+        self.data = &rest[length..];
+        Ok((tag, &rest[..length], encoding))
+    }
+}
@@ -3,13 +3,62 @@
 #[cfg(test)]
 mod tests;
 
+pub mod balancing;
+pub mod contract;
+pub mod ocmf;
+
+use charger_service::runtime::offchain::ConnectorId;
 use codec::{Decode, Encode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_std::vec::Vec;
+
+/// Identifies a site, whose chargers share a grid connection
+pub type SiteId = u32;
+
+/// Longest token accepted in the registry, the length of an OCPP 2.0.1 id token
+pub const MAX_TOKEN_LENGTH: usize = 36;
+
+/// Most sessions returned by a page of the session history
+pub const MAX_HISTORY_PAGE_SIZE: u32 = 100;
+
+/// Longest address or operator name in the metadata of a charger, in bytes
+pub const MAX_METADATA_TEXT_LENGTH: usize = 256;
+
+/// Most connectors described in the metadata of a charger
+pub const MAX_METADATA_CONNECTORS: usize = 16;
+
+/// Most opening periods in the metadata of a charger, 4 per day of the week
+pub const MAX_OPENING_PERIODS: usize = 28;
+
+/// Blocks after which the offchain worker sends again a start_session or end_session
+/// transaction which was not included
+pub const SUBMISSION_TIMEOUT: u32 = 10;
+
+/// Most queued requests visited by the expiry of a block, the others being visited in the
+/// next blocks
+pub const MAX_EXPIRY_VISITS: u64 = 32;
+
+/// Time after which the lock of a connector taken by an offchain worker is released, in ms
+const CONNECTOR_LOCK_DEADLINE: u64 = 20_000;
 
 #[derive(Debug, PartialEq, Default, Encode, Decode)]
 pub struct ChargeRequest<UserId, Moment, Hash> {
     user_id: UserId,
     created_at: Moment,
     session_id: Hash,
+    limits: SessionLimits<Moment>,
+}
+
+/// Limits of a session agreed by its user: the charger is stopped once one is reached
+#[derive(Debug, PartialEq, Eq, Default, Clone, Encode, Decode)]
+pub struct SessionLimits<Moment> {
+    /// Energy to deliver, in Wh
+    pub energy_wh: Option<u64>,
+    /// Time from the start of the session
+    pub duration: Option<Moment>,
+    /// Amount to pay at the current price, in cents. The user is never billed more
+    pub amount: Option<u128>,
 }
 
 #[derive(Debug, PartialEq, Default, Encode, Decode)]
@@ -17,6 +66,147 @@ pub struct ChargingSession<UserId, Moment, Hash> {
     user_id: UserId,
     started_at: Moment,
     session_id: Hash,
+    last_checkpoint: Option<MeterCheckpoint<Moment>>,
+    /// Set by stop_session, until the charger reports the end of the session
+    stop_requested: bool,
+    limits: SessionLimits<Moment>,
+}
+
+/// Compact record of the meter of an active session, periodically written by the charger
+#[derive(Debug, PartialEq, Default, Clone, Encode, Decode)]
+pub struct MeterCheckpoint<Moment> {
+    /// Energy delivered since the beginning of the session, in Wh
+    energy_wh: u64,
+    /// Instantaneous active power, in W
+    power_w: u32,
+    /// Time of the reading reported by the charger (unix time in milliseconds)
+    read_at: u64,
+    recorded_at: Moment,
+}
+
+/// Charging power limit of a whole charger (connector 0), or of the session of a connector
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
+pub struct PowerLimit<AccountId, Moment> {
+    watts: u32,
+    /// The limit lapses at this time, if set
+    valid_until: Option<Moment>,
+    set_by: AccountId,
+    /// Set once the charger reports that the limit has taken effect
+    applied: bool,
+}
+
+/// Site whose chargers share a grid connection: its power is split between their sessions
+#[derive(Debug, PartialEq, Default, Clone, Encode, Decode)]
+pub struct Site {
+    /// Maximal total charging power of the site, in W
+    max_power_w: u32,
+}
+
+/// Description of a charger, displayed by the apps which also filter the chargers on it
+#[derive(Debug, PartialEq, Eq, Default, Clone, Encode, Decode)]
+pub struct ChargerMetadata {
+    pub coordinates: GeoCoordinates,
+    /// Postal address (UTF-8)
+    pub address: Vec<u8>,
+    pub connectors: Vec<ConnectorMetadata>,
+    /// Weekly opening hours: the charger is always open without any period
+    pub opening_hours: Vec<OpeningPeriod>,
+    /// Name of the charge point operator (UTF-8)
+    pub operator: Vec<u8>,
+}
+
+/// WGS 84 coordinates, in millionths of a degree
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy, Encode, Decode)]
+pub struct GeoCoordinates {
+    pub latitude: i32,
+    pub longitude: i32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct ConnectorMetadata {
+    pub connector: ConnectorId,
+    pub connector_type: ConnectorType,
+    pub current: CurrentType,
+    /// Maximal charging power, in W
+    pub max_power_w: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Encode, Decode)]
+pub enum ConnectorType {
+    /// IEC 62196 Type 2, AC only
+    Type2,
+    /// Combined Charging System (Combo 2), DC only
+    Ccs,
+    /// CHAdeMO, DC only
+    Chademo,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Encode, Decode)]
+pub enum CurrentType {
+    Ac,
+    Dc,
+}
+
+/// Opening period of a day of the week, in minutes from midnight (local time of the charger)
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct OpeningPeriod {
+    /// Day of the week, from 0 (Monday) to 6 (Sunday)
+    pub weekday: u8,
+    pub opens: u16,
+    /// At most 1440, the end of the day
+    pub closes: u16,
+}
+
+/// Ended session, kept in the session history of its user and of its charger
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct SessionRecord<AccountId, Moment, Hash> {
+    pub session_id: Hash,
+    pub user_id: AccountId,
+    pub charger_id: AccountId,
+    pub connector: ConnectorId,
+    pub started_at: Moment,
+    pub ended_at: Moment,
+    pub kwh: u64,
+    /// Amount billed to the user, in cents, if the payment was requested
+    pub amount: Option<u128>,
+    pub payment_status: PaymentStatus,
+}
+
+/// Payment of an ended session
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum PaymentStatus {
+    /// The payment could not be requested
+    Failed,
+    /// The payment is requested, waiting for its validation
+    Pending,
+    Completed,
+}
+
+/// ISO 15118 contract of a user, with the certificate chain of its vehicle (Plug & Charge)
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
+pub struct RegisteredContract<AccountId> {
+    owner: AccountId,
+    /// DER encoded certificates, the contract certificate first and then its sub-CAs
+    certificate_chain: Vec<Vec<u8>>,
+}
+
+/// Transaction sent by the offchain worker for the session of a connector, kept in the
+/// offchain local storage until it is included
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
+pub enum SentTransaction<BlockNumber> {
+    /// start_session sent at this block
+    StartSession(BlockNumber),
+    /// end_session sent at this block, with the energy (in kWh) and the signed meter data
+    /// reported by the charger, which may not report them twice
+    EndSession(BlockNumber, u64, Vec<Vec<u8>>),
+    /// report_fault sent at this block, with the error code and the energy (in kWh) reported
+    /// by the charger when the connector faulted
+    ReportFault(BlockNumber, Vec<u8>, u64),
+    /// close_session sent at this block, for a session which the charger lost
+    CloseSession(BlockNumber),
 }
 
 pub mod crypto {
@@ -46,8 +236,19 @@ pub mod crypto {
 
 #[frame_support::pallet]
 pub mod pallet {
-    use super::{ChargeRequest, ChargingSession};
-    use charger_service::runtime::offchain::{api as charger_api, ChargeStatus};
+    use super::{
+        balancing,
+        contract::{self, ContractError},
+        ocmf::{self, OcmfError},
+        ChargeRequest, ChargerMetadata, ChargingSession, ConnectorType, CurrentType,
+        MeterCheckpoint, PaymentStatus, PowerLimit, RegisteredContract, SentTransaction,
+        SessionLimits, SessionRecord, Site, SiteId, CONNECTOR_LOCK_DEADLINE,
+        MAX_HISTORY_PAGE_SIZE, MAX_METADATA_CONNECTORS, MAX_METADATA_TEXT_LENGTH,
+        MAX_OPENING_PERIODS, MAX_TOKEN_LENGTH, SUBMISSION_TIMEOUT,
+    };
+    use charger_service::runtime::offchain::{
+        api as charger_api, ChargeStatus, ChargerError, ConnectorId, MeterSample,
+    };
     use frame_support::pallet_prelude::*;
     use frame_system::{
         offchain::{
@@ -60,7 +261,12 @@ pub mod pallet {
     use pallet_timestamp as timestamp;
     use pallet_charge_consent as consent;
     use sp_runtime::{
-        traits::{Hash, IdentifyAccount},
+        offchain::{
+            storage::StorageValueRef,
+            storage_lock::{StorageLock, Time},
+            Duration,
+        },
+        traits::{Hash, IdentifyAccount, Saturating, UniqueSaturatedInto, Zero},
         RuntimeAppPublic,
     };
     use sp_std::vec::Vec;
@@ -104,6 +310,12 @@ pub mod pallet {
             <Self as SigningTypes>::Signature,
         >;
         type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+        /// Minimum time between two meter checkpoints of an active session
+        #[pallet::constant]
+        type CheckpointInterval: Get<Self::Moment>;
+        /// Time after which a request not started by its charger expires, freeing its connector
+        #[pallet::constant]
+        type RequestTimeout: Get<Self::Moment>;
     }
 
     #[pallet::pallet]
@@ -112,36 +324,234 @@ pub mod pallet {
 
     #[pallet::storage]
     #[pallet::getter(fn user_requests)]
-    pub type UserRequests<T: Config> = StorageMap<
+    pub type UserRequests<T: Config> = StorageDoubleMap<
         _,
         Blake2_128Concat,
         T::AccountId,
+        Twox64Concat,
+        ConnectorId,
         ChargeRequest<T::AccountId, T::Moment, T::Hash>,
     >;
 
+    /// The requests in the order of their creation, hence of their expiry, by position in the
+    /// queue: `(charger, connector, session_id)`. The requests started, cancelled or rejected
+    /// stay queued until they reach the head of the queue.
+    #[pallet::storage]
+    pub type RequestQueue<T: Config> =
+        StorageMap<_, Twox64Concat, u64, (T::AccountId, ConnectorId, T::Hash)>;
+
+    /// Positions of the head of `RequestQueue`, and of the next queued request
+    #[pallet::storage]
+    pub type RequestQueueBounds<T: Config> = StorageValue<_, (u64, u64), ValueQuery>;
+
     #[pallet::storage]
     #[pallet::getter(fn active_sessions)]
-    pub type ActiveSessions<T: Config> = StorageMap<
+    pub type ActiveSessions<T: Config> = StorageDoubleMap<
         _,
         Blake2_128Concat,
         T::AccountId,
+        Twox64Concat,
+        ConnectorId,
         ChargingSession<T::AccountId, T::Moment, T::Hash>,
     >;
 
+    /// Connectors reported as faulted by their charger, with the reported error code.
+    /// New requests are rejected on these connectors until the fault is cleared.
+    #[pallet::storage]
+    #[pallet::getter(fn faulted_connectors)]
+    pub type FaultedConnectors<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Twox64Concat,
+        ConnectorId,
+        Vec<u8>,
+    >;
+
+    /// Public key of the meter of each charger (SEC1 encoded secp256r1 key). The energy of
+    /// the sessions of these chargers is proven by the readings signed by the meter (OCMF).
+    #[pallet::storage]
+    #[pallet::getter(fn meter_keys)]
+    pub type MeterKeys<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, Vec<u8>>;
+
+    /// Last signed energy register of each connector, in Wh: the readings of a session
+    /// cannot start below it, so the datasets of a past session cannot be replayed
+    #[pallet::storage]
+    #[pallet::getter(fn last_meter_readings)]
+    pub type LastMeterReadings<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Twox64Concat,
+        ConnectorId,
+        u64,
+    >;
+
+    /// Datasets signed by the meter of each session ended with verified readings, kept
+    /// so that the users can audit them
+    #[pallet::storage]
+    #[pallet::getter(fn signed_meter_data)]
+    pub type SignedMeterData<T: Config> = StorageMap<_, Blake2_128Concat, T::Hash, Vec<Vec<u8>>>;
+
+    /// Ended sessions, by session id
+    #[pallet::storage]
+    #[pallet::getter(fn session_history)]
+    pub type SessionHistory<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::Hash, SessionRecord<T::AccountId, T::Moment, T::Hash>>;
+
+    /// Ended sessions of each user, by chronological index
+    #[pallet::storage]
+    pub type UserSessions<T: Config> =
+        StorageDoubleMap<_, Blake2_128Concat, T::AccountId, Twox64Concat, u32, T::Hash>;
+
+    #[pallet::storage]
+    pub type UserSessionCount<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, u32, ValueQuery>;
+
+    /// Ended sessions of each charger, by chronological index
+    #[pallet::storage]
+    pub type ChargerSessions<T: Config> =
+        StorageDoubleMap<_, Blake2_128Concat, T::AccountId, Twox64Concat, u32, T::Hash>;
+
+    #[pallet::storage]
+    pub type ChargerSessionCount<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, u32, ValueQuery>;
+
+    /// Charging power limits set by the site operators: connector 0 limits the whole charger,
+    /// the other connectors their active session (the limit is dropped with the session)
+    #[pallet::storage]
+    #[pallet::getter(fn power_limits)]
+    pub type PowerLimits<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Twox64Concat,
+        ConnectorId,
+        PowerLimit<T::AccountId, T::Moment>,
+    >;
+
+    #[pallet::storage]
+    #[pallet::getter(fn sites)]
+    pub type Sites<T: Config> = StorageMap<_, Twox64Concat, SiteId, Site>;
+
+    /// Site of each charger assigned to one
+    #[pallet::storage]
+    #[pallet::getter(fn charger_sites)]
+    pub type ChargerSites<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, SiteId>;
+
+    /// Chargers of each site
+    #[pallet::storage]
+    pub type SiteChargers<T: Config> =
+        StorageDoubleMap<_, Twox64Concat, SiteId, Blake2_128Concat, T::AccountId, ()>;
+
+    /// Owner of each token (UID of an RFID card, or ISO 15118 contract id) registered by
+    /// the users: the chargers request sessions on their behalf when the token is presented
+    #[pallet::storage]
+    #[pallet::getter(fn tokens)]
+    pub type Tokens<T: Config> = StorageMap<_, Blake2_128Concat, Vec<u8>, T::AccountId>;
+
+    /// Roots trusted to issue the contract certificates (eg. the V2G root certificates),
+    /// DER encoded and identified by their hash
+    #[pallet::storage]
+    #[pallet::getter(fn contract_roots)]
+    pub type ContractRoots<T: Config> = StorageMap<_, Blake2_128Concat, T::Hash, Vec<u8>>;
+
+    /// ISO 15118 contracts registered by the users, by contract id (eMAID): the chargers
+    /// request sessions on their behalf when their vehicle presents the contract certificate
+    #[pallet::storage]
+    #[pallet::getter(fn contracts)]
+    pub type Contracts<T: Config> =
+        StorageMap<_, Blake2_128Concat, Vec<u8>, RegisteredContract<T::AccountId>>;
+
+    /// Chargers suspended by an admin, with the connectors whose sessions were closed by the
+    /// suspension: the charger stops them, and does not run any other session until resumed
+    #[pallet::storage]
+    #[pallet::getter(fn suspended_chargers)]
+    pub type SuspendedChargers<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, Vec<ConnectorId>>;
+
+    /// Chargers removed by an admin, with the connectors whose sessions were closed by the
+    /// removal or by a former suspension: the charger stops them. Cleared when the charger is
+    /// added again.
+    #[pallet::storage]
+    #[pallet::getter(fn removed_chargers)]
+    pub type RemovedChargers<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, Vec<ConnectorId>>;
+
+    /// Metadata of each registered charger, set by add_new_charger
+    #[pallet::storage]
+    #[pallet::getter(fn charger_metadata)]
+    pub type ChargerMetadatas<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, ChargerMetadata>;
+
     #[pallet::storage]
     pub type ChargerOrganization<T: Config> = StorageValue<_, T::AccountId, ValueQuery>;
 
     #[pallet::event]
     #[pallet::generate_deposit(pub(super) fn deposit_event)]
     pub enum Event<T: Config> {
-        /// SessionRequested(User, Charger, Timestamp, SessionId)
-        SessionRequested(T::AccountId, T::AccountId, T::Moment, T::Hash),
-        /// SessionStarted(User, Charger, Timestamp, SessionId)
-        SessionStarted(T::AccountId, T::AccountId, T::Moment, T::Hash),
-        /// SessionEnded(User, Charger, StartedAt, EndedAt, SessionId, kwh)
-        SessionEnded(T::AccountId, T::AccountId, T::Moment, T::Moment, T::Hash, u64),
+        /// SessionRequested(User, Charger, Connector, Timestamp, SessionId)
+        SessionRequested(T::AccountId, T::AccountId, ConnectorId, T::Moment, T::Hash),
+        /// SessionStarted(User, Charger, Connector, Timestamp, SessionId)
+        SessionStarted(T::AccountId, T::AccountId, ConnectorId, T::Moment, T::Hash),
+        /// RequestExpired(User, Charger, Connector, SessionId)
+        RequestExpired(T::AccountId, T::AccountId, ConnectorId, T::Hash),
+        /// SessionCancelled(User, Charger, Connector, SessionId)
+        SessionCancelled(T::AccountId, T::AccountId, ConnectorId, T::Hash),
+        /// SessionRejected(User, Charger, Connector, SessionId, Reason)
+        SessionRejected(T::AccountId, T::AccountId, ConnectorId, T::Hash, ChargerError),
+        /// SessionEnded(User, Charger, Connector, StartedAt, EndedAt, SessionId, kwh)
+        SessionEnded(
+            T::AccountId,
+            T::AccountId,
+            ConnectorId,
+            T::Moment,
+            T::Moment,
+            T::Hash,
+            u64,
+        ),
+        /// SessionStopRequested(RequestedBy, Charger, Connector, SessionId)
+        SessionStopRequested(T::AccountId, T::AccountId, ConnectorId, T::Hash),
+        /// ChargerFaulted(Charger, Connector, ErrorCode)
+        ChargerFaulted(T::AccountId, ConnectorId, Vec<u8>),
+        /// ChargerFaultCleared(Charger, Connector)
+        ChargerFaultCleared(T::AccountId, ConnectorId),
+        /// SessionCheckpoint(User, Charger, Connector, SessionId, EnergyWh, PowerW)
+        SessionCheckpoint(T::AccountId, T::AccountId, ConnectorId, T::Hash, u64, u32),
         // NewChargerAdded(AddedBy, ChargerId, Location)
         NewChargerAdded(T::AccountId, T::AccountId, Vec<u8>),
+        /// ChargerMetadataUpdated(UpdatedBy, Charger)
+        ChargerMetadataUpdated(T::AccountId, T::AccountId),
+        /// ChargerSuspended(SuspendedBy, Charger)
+        ChargerSuspended(T::AccountId, T::AccountId),
+        /// ChargerResumed(ResumedBy, Charger)
+        ChargerResumed(T::AccountId, T::AccountId),
+        /// ChargerRemoved(RemovedBy, Charger)
+        ChargerRemoved(T::AccountId, T::AccountId),
+        /// MeterKeySet(SetBy, Charger, PublicKey)
+        MeterKeySet(T::AccountId, T::AccountId, Vec<u8>),
+        /// PowerLimitSet(SetBy, Charger, Connector, Watts, ValidUntil)
+        PowerLimitSet(T::AccountId, T::AccountId, ConnectorId, u32, Option<T::Moment>),
+        /// PowerLimitApplied(Charger, Connector, Watts)
+        PowerLimitApplied(T::AccountId, ConnectorId, u32),
+        /// PowerLimitRejected(Charger, Connector, Watts, Reason)
+        PowerLimitRejected(T::AccountId, ConnectorId, u32, ChargerError),
+        /// SiteSet(SiteId, MaxPowerW)
+        SiteSet(SiteId, u32),
+        /// ChargerSiteAssigned(Charger, SiteId), no site when the charger is removed from its site
+        ChargerSiteAssigned(T::AccountId, Option<SiteId>),
+        /// TokenRegistered(User, Token)
+        TokenRegistered(T::AccountId, Vec<u8>),
+        /// TokenUnregistered(User, Token)
+        TokenUnregistered(T::AccountId, Vec<u8>),
+        /// ContractRootAdded(AddedBy, RootHash)
+        ContractRootAdded(T::AccountId, T::Hash),
+        /// ContractRootRemoved(RemovedBy, RootHash)
+        ContractRootRemoved(T::AccountId, T::Hash),
+        /// ContractRegistered(User, Emaid)
+        ContractRegistered(T::AccountId, Vec<u8>),
+        /// ContractUnregistered(User, Emaid)
+        ContractUnregistered(T::AccountId, Vec<u8>),
     }
 
     #[pallet::error]
@@ -154,298 +564,2148 @@ pub mod pallet {
         ChargerIsBusy,
         NoPaymentConsent,
         AlreadyRegisteredCharger,
+        StaleCheckpoint,
+        NotAllowedToStop,
+        StopAlreadyRequested,
+        ConnectorFaulted,
+        NotFaulted,
+        InvalidMeterKey,
+        /// The signed meter data cannot be read, or uses an unsupported algorithm
+        InvalidSignedMeterData,
+        BadMeterSignature,
+        MissingMeterReading,
+        /// The reported energy is not the energy of the signed readings
+        MeterReadingMismatch,
+        /// The signed readings start before the end of the previous session
+        StaleMeterReading,
+        /// The power limit would already have lapsed
+        PowerLimitExpired,
+        /// The connector has no such power limit (it may have been replaced meanwhile)
+        NoPowerLimit,
+        UnknownSite,
+        /// The token is empty or longer than `MAX_TOKEN_LENGTH`
+        InvalidToken,
+        TokenAlreadyRegistered,
+        UnknownToken,
+        NotTokenOwner,
+        /// The certificate is not a self-signed certificate authority
+        InvalidContractRoot,
+        UnknownContractRoot,
+        /// A contract certificate cannot be read, or uses an unsupported algorithm
+        InvalidContractCertificate,
+        BadContractSignature,
+        UntrustedContractRoot,
+        /// A certificate of the contract chain is expired, or not valid yet
+        ContractCertificateExpired,
+        /// The contract id or certificate is not the one of the registered contract
+        ContractMismatch,
+        ContractAlreadyRegistered,
+        UnknownContract,
+        NotContractOwner,
+        /// A limit of the session is zero
+        InvalidSessionLimits,
+        ChargerSuspended,
+        ChargerNotSuspended,
+        /// The latitude or longitude of the charger is out of range
+        InvalidCoordinates,
+        /// The address or operator of the charger is empty, or longer than
+        /// `MAX_METADATA_TEXT_LENGTH`
+        InvalidChargerText,
+        /// The charger describes no connector or more than `MAX_METADATA_CONNECTORS`, a connector
+        /// twice, connector 0, a connector without power, or a current its type does not support
+        InvalidConnectorMetadata,
+        /// An opening period is not within a day of the week, or there are more than
+        /// `MAX_OPENING_PERIODS`
+        InvalidOpeningHours,
     }
 
     #[pallet::hooks]
     impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
-        fn offchain_worker(_block: T::BlockNumber) {
+        fn on_initialize(_block: T::BlockNumber) -> Weight {
+            Self::expire_requests()
+        }
+
+        fn offchain_worker(block: T::BlockNumber) {
             // Offchain processing of charge requests & active charge sessions
-            Self::process_charge_sessions();
+            Self::process_charge_sessions(block);
         }
     }
 
     #[pallet::call]
     impl<T: Config> Pallet<T>
 	{
+        /// Request a new session on `connector` of `charger`, which is stopped once one of
+        /// `limits` is reached
         #[pallet::weight(1_000)]
         pub fn new_request(
             origin: OriginFor<T>,
             charger: T::AccountId,
+            connector: ConnectorId,
+            limits: SessionLimits<T::Moment>,
         ) -> DispatchResultWithPostInfo {
-            let sender = ensure_signed(origin.clone())?;
-            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
-
-            let now = <timestamp::Module<T>>::get();
-
-            // Check that sender consent exists in pallet_session_payment
-            if <pallet_session_payment::Module<T>>::has_consent(&sender) == false {
-                debug::native::warn!("No consent for user {}", &sender,);
-                return Err(Error::<T>::NoPaymentConsent.into());
-            }
-
-            // Check that this charger does not have another pending request
-            // TODO: expiration period for request?
-            match UserRequests::<T>::get(&charger) {
-                Some(request) => {
-                    debug::native::warn!(
-                        "Charger {} has pending request {:?}: cannot store a new request",
-                        &charger,
-                        &request
-                    );
-                    return Err(Error::<T>::ChargerIsBusy.into());
-                }
-                _ => {}
-            }
-
-            // Check that this charger does not have an active charging session
-            match ActiveSessions::<T>::get(&charger) {
-                Some(session) => {
-                    debug::native::warn!(
-                        "Charger {} has already active session {:?}: cannot store a new request",
-                        &charger,
-                        &session
-                    );
-                    return Err(Error::<T>::ChargerIsBusy.into());
-                }
-                _ => {}
-            }
-
-            // Generate a new session_id
-            let session_id = Self::generate_charge_id(&sender, &charger);
-
-            // Store the charge consent
-            <consent::Module<T>>::new_consent_for_user(
-                origin,
-                charger.clone(),
-                session_id.clone(),
-            )?;
-
-            // Add the request to the storage with current timestamp
-            UserRequests::<T>::insert(
-                &charger,
-                ChargeRequest {
-                    user_id: sender.clone(),
-                    created_at: now,
-                    session_id,
-                },
+            let sender = ensure_signed(origin)?;
+            ensure!(
+                limits.energy_wh != Some(0)
+                    && limits.duration != Some(Zero::zero())
+                    && limits.amount != Some(0),
+                Error::<T>::InvalidSessionLimits
             );
-
-            Self::deposit_event(Event::SessionRequested(sender, charger, now, session_id));
-
-            Ok(().into())
+            Self::store_request(sender, charger, connector, limits)
         }
 
         #[pallet::weight(1_000)]
         pub fn start_session(
             origin: OriginFor<T>,
             user: T::AccountId,
+            connector: ConnectorId,
         ) -> DispatchResultWithPostInfo {
             let sender = ensure_signed(origin)?;
             ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
 
             let now = <timestamp::Module<T>>::get();
 
-            // Validate that a request exists for this user & charger
-            match UserRequests::<T>::get(&sender) {
+            // Validate that a request exists for this user & connector
+            match UserRequests::<T>::get(&sender, connector) {
                 None => return Err(Error::<T>::NoChargingRequest.into()),
                 Some(request) if request.user_id != user => {
                     return Err(Error::<T>::NoChargingRequest.into())
                 }
                 _ => {}
             }
-            // TODO: check timestamp for maximal period of time between new_request & start_session ?
 
             // Remove the request from storage
-            let request = UserRequests::<T>::take(&sender).expect("cannot be None");
+            let request = UserRequests::<T>::take(&sender, connector).expect("cannot be None");
+
+            // The payment of the session is capped to the agreed amount
+            if let Some(amount) = request.limits.amount {
+                <pallet_session_payment::Module<T>>::set_payment_cap(request.session_id, amount);
+            }
 
             // Add the pending charging session
             ActiveSessions::<T>::insert(
                 &sender,
+                connector,
                 ChargingSession {
                     user_id: user.clone(),
                     started_at: now,
                     session_id: request.session_id,
+                    last_checkpoint: None,
+                    stop_requested: false,
+                    limits: request.limits,
                 },
             );
 
             // Emit an event
-            Self::deposit_event(Event::SessionStarted(user, sender, now, request.session_id));
+            Self::deposit_event(Event::SessionStarted(
+                user,
+                sender,
+                connector,
+                now,
+                request.session_id,
+            ));
 
             Ok(().into())
         }
 
+        /// Drop the request of a connector that the charger could not start, with the reason
+        /// reported by the charger
         #[pallet::weight(1_000)]
-        pub fn end_session(
+        pub fn reject_request(
             origin: OriginFor<T>,
             user: T::AccountId,
-            kwh: u64,
+            connector: ConnectorId,
+            reason: ChargerError,
         ) -> DispatchResultWithPostInfo {
-            let sender = ensure_signed(origin.clone())?;
+            let sender = ensure_signed(origin)?;
             ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
 
-            let now = <timestamp::Module<T>>::get();
-
-            // Validate that a session exists for this user & charger
-            match ActiveSessions::<T>::get(&sender) {
-                None => return Err(Error::<T>::NoChargingSession.into()),
-                Some(session) if session.user_id != user => {
-                    return Err(Error::<T>::NoChargingSession.into())
-                }
-                _ => {}
-            }
+            // Validate that a request exists for this user & connector
+            let request = match UserRequests::<T>::get(&sender, connector) {
+                Some(request) if request.user_id == user => request,
+                _ => return Err(Error::<T>::NoChargingRequest.into()),
+            };
 
-            // Remove the request from storage
-            let session = ActiveSessions::<T>::take(&sender).expect("Cannot be None");
-            
-            // Execute the payment
-			// TODO Uncomment the following code to execute the payment
-            match <pallet_session_payment::Module<T>>::process_payment(
-                origin,
-                session.session_id,
-                kwh.into(),
-            ) {
-                Err(error) => {
-                    // The error is just logged here, because we want to end the session even if payment has failed
-                    // pallet_session_payment deposits an error event which is handled manually in this case
-                    debug::native::error!(
-                        "An error occured in pallet_session_payment::process_payment for session id {}: {:?}",
-                        &session.session_id,
-                        error
-                    );
-                }
-                _ => {}
-            }
+            // Remove the request and its charge consent from storage
+            UserRequests::<T>::remove(&sender, connector);
+            <consent::Module<T>>::remove_consent(request.session_id);
 
             // Emit an event
-            Self::deposit_event(Event::SessionEnded(
+            Self::deposit_event(Event::SessionRejected(
                 user,
                 sender,
-                session.started_at,
-                now,
-                session.session_id,
-                kwh,
+                connector,
+                request.session_id,
+                reason,
             ));
 
             Ok(().into())
         }
 
+        /// Cancel the pending request of the sender on `connector` of `charger`, which has not
+        /// been started yet
         #[pallet::weight(1_000)]
-        pub fn add_new_charger(
+        pub fn cancel_request(
             origin: OriginFor<T>,
-            charger_id: T::AccountId,
+            charger: T::AccountId,
+            connector: ConnectorId,
         ) -> DispatchResultWithPostInfo {
-            let sender = ensure_signed(origin.clone())?;
-            // Check that signer is admin (= owner of chargers organizaton)
-            ensure!(<pallet_did::Module<T>>::is_owner(&<ChargerOrganization<T>>::get(), &sender).is_ok(), Error::<T>::NotAnAdmin);
-            // Check that this charger is not already registered
-            ensure!(Self::is_charger(&charger_id) == false, Error::<T>::AlreadyRegisteredCharger);
-             match <pallet_did::Module<T>>::attribute_and_id(&charger_id, b"location") {
-                 // Check that charger has a location attribute
-                None =>  return Err(Error::<T>::NoLocation.into()),
-                Some(location) => {
-                    // Add charger to organization
-                    <pallet_registrar::Module<T>>::add_to_organization(origin.clone(), charger_id.clone())?;
-                    
-                    // Emit an event
-                    Self::deposit_event(Event::NewChargerAdded(sender, charger_id, location.0.value));
-                    Ok(().into())
-                }
-             }
+            let sender = ensure_signed(origin)?;
+
+            // Validate that the sender has a request on this connector
+            let request = match UserRequests::<T>::get(&charger, connector) {
+                Some(request) if request.user_id == sender => request,
+                _ => return Err(Error::<T>::NoChargingRequest.into()),
+            };
+
+            // Remove the request and its charge consent from storage
+            UserRequests::<T>::remove(&charger, connector);
+            <consent::Module<T>>::remove_consent(request.session_id);
+
+            // Emit an event
+            Self::deposit_event(Event::SessionCancelled(
+                sender,
+                charger,
+                connector,
+                request.session_id,
+            ));
+
+            Ok(().into())
         }
-    }
 
-    impl<T: Config> Pallet<T> {
-        fn process_charge_sessions() {
-            // Get the list of charger accounts
-            let accounts = <<T as Config>::AuthorityId as AppCrypto<
-                <T as SigningTypes>::Public,
-                <T as SigningTypes>::Signature,
-            >>::RuntimeAppPublic::all()
-            .into_iter()
-            .map(|key| {
-                let generic_public = <<T as Config>::AuthorityId as AppCrypto<
-                    <T as SigningTypes>::Public,
-                    <T as SigningTypes>::Signature,
-                >>::GenericPublic::from(key);
-                let public: <T as SigningTypes>::Public = generic_public.into();
-                let signer = Signer::<T, <T as Config>::AuthorityId>::all_accounts()
-                    .with_filter(sp_std::vec!(public.clone()));
-                (public.clone().into_account(), signer)
-            });
+        /// End an active session with the energy delivered. If the charger has a meter key,
+        /// `signed_data` must hold the datasets signed by the meter for the start and end
+        /// readings of the session, which are verified and stored.
+        #[pallet::weight(1_000)]
+        pub fn end_session(
+            origin: OriginFor<T>,
+            user: T::AccountId,
+            connector: ConnectorId,
+            kwh: u64,
+            signed_data: Vec<Vec<u8>>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin.clone())?;
+            ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
 
-            // For each charger account registered in the keystore:
-            for (account_id, signer) in accounts {
-                debug::native::debug!("Use charger account {}", account_id);
+            // Validate that a session exists for this user & connector
+            let session = match ActiveSessions::<T>::get(&sender, connector) {
+                Some(session) if session.user_id == user => session,
+                _ => return Err(Error::<T>::NoChargingSession.into()),
+            };
 
-                // 1) Check if pending user request exists for this charger
-                match Self::user_requests(&account_id) {
-                    Some(request) => {
-                        debug::native::debug!(
-                            "User {} requests a new charge session",
-                            &request.user_id
-                        );
-                        if charger_api::start_charge() {
-                            debug::native::info!(
-                                "Charge session started for user {}",
-                                &request.user_id
-                            );
-                            if Self::send_signed_transaction(
-                                &signer,
-                                Call::start_session(request.user_id.clone()),
-                            )
-                            .is_err()
-                            {
-                                debug::native::error!(
-                                    "Error occured while sending start_session transaction"
-                                );
-                            }
+            // The energy of a charger with a signing meter is proven by the meter readings
+            if let Some(public_key) = MeterKeys::<T>::get(&sender) {
+                let readings =
+                    ocmf::verify_session(&public_key, &signed_data).map_err(|error| match error {
+                        OcmfError::Malformed | OcmfError::UnsupportedAlgorithm => {
+                            Error::<T>::InvalidSignedMeterData
                         }
-                    }
-                    _ => {}
+                        OcmfError::BadSignature => Error::<T>::BadMeterSignature,
+                        OcmfError::MissingReading => Error::<T>::MissingMeterReading,
+                    })?;
+                ensure!(
+                    readings.energy_wh() / 1000 == kwh,
+                    Error::<T>::MeterReadingMismatch
+                );
+                if let Some(last_wh) = LastMeterReadings::<T>::get(&sender, connector) {
+                    ensure!(readings.start_wh >= last_wh, Error::<T>::StaleMeterReading);
                 }
+                LastMeterReadings::<T>::insert(&sender, connector, readings.end_wh);
+                SignedMeterData::<T>::insert(session.session_id, signed_data);
+            }
 
-                // 2) Check if there is an active charge session for this charger
-                match Self::active_sessions(&account_id) {
-                    Some(session) => {
-                        // We have an active session, check the current status
-                        match charger_api::get_current_charge_status() {
-                            ChargeStatus::NoCharge => {
-                                debug::native::error!(
-                                    "Charge session is active in-chain, but not found off-chain"
-                                );
-                            }
-                            ChargeStatus::Active => {
-                                debug::native::debug!("Charge session is still active, waiting...");
-                            }
-                            ChargeStatus::Ended { kwh } => {
-                                debug::native::info!(
-                                    "Charge session is ended for user {}, consumed: {} kwh",
-                                    &session.user_id,
-                                    &kwh
+            Self::settle_session(origin, sender, connector, kwh);
+
+            Ok(().into())
+        }
+
+        /// Report a fault of a connector: the connector is flagged, and its active session
+        /// (if any) is ended with the energy delivered so far. The energy of a charger with a
+        /// meter key is only proven by signed readings: its session is ended with the energy
+        /// of the last checkpoint instead.
+        #[pallet::weight(1_000)]
+        pub fn report_fault(
+            origin: OriginFor<T>,
+            connector: ConnectorId,
+            error_code: Vec<u8>,
+            kwh: u64,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin.clone())?;
+            ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
+            // A fault already reported is only reported again to end a session
+            ensure!(
+                !FaultedConnectors::<T>::contains_key(&sender, connector)
+                    || ActiveSessions::<T>::contains_key(&sender, connector),
+                Error::<T>::ConnectorFaulted
+            );
+
+            FaultedConnectors::<T>::insert(&sender, connector, &error_code);
+            Self::deposit_event(Event::ChargerFaulted(sender.clone(), connector, error_code));
+
+            if let Some(session) = ActiveSessions::<T>::get(&sender, connector) {
+                let kwh = if MeterKeys::<T>::contains_key(&sender) {
+                    Self::last_checkpoint_kwh(&session)
+                } else {
+                    kwh
+                };
+                Self::settle_session(origin, sender, connector, kwh);
+            }
+
+            Ok(().into())
+        }
+
+        /// Clear the fault of a connector: callable by the charger itself, or by an admin
+        /// of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn clear_fault(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            connector: ConnectorId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(
+                sender == charger || Self::is_admin(&sender),
+                Error::<T>::NotAnAdmin
+            );
+            ensure!(
+                FaultedConnectors::<T>::contains_key(&charger, connector),
+                Error::<T>::NotFaulted
+            );
+
+            FaultedConnectors::<T>::remove(&charger, connector);
+            Self::deposit_event(Event::ChargerFaultCleared(charger, connector));
+
+            Ok(().into())
+        }
+
+        /// Ask the charger to stop an active session: callable by the user of the session
+        /// or by an admin of the charger organization. The session is settled by end_session
+        /// once the charger has stopped.
+        #[pallet::weight(1_000)]
+        pub fn stop_session(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            connector: ConnectorId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+
+            let mut session = match ActiveSessions::<T>::get(&charger, connector) {
+                None => return Err(Error::<T>::NoChargingSession.into()),
+                Some(session) => session,
+            };
+            ensure!(
+                session.user_id == sender || Self::is_admin(&sender),
+                Error::<T>::NotAllowedToStop
+            );
+            ensure!(!session.stop_requested, Error::<T>::StopAlreadyRequested);
+
+            session.stop_requested = true;
+            let session_id = session.session_id;
+            ActiveSessions::<T>::insert(&charger, connector, session);
+
+            // Emit an event
+            Self::deposit_event(Event::SessionStopRequested(
+                sender, charger, connector, session_id,
+            ));
+
+            Ok(().into())
+        }
+
+        /// Close an active session that the charger lost, and cannot end with its final
+        /// reading (eg. the node restarted without its sessions), with the energy of its last
+        /// checkpoint: callable by the charger itself, or by an admin of the charger
+        /// organization
+        #[pallet::weight(1_000)]
+        pub fn close_session(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            connector: ConnectorId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(
+                sender == charger || Self::is_admin(&sender),
+                Error::<T>::NotAnAdmin
+            );
+            let session = match ActiveSessions::<T>::get(&charger, connector) {
+                None => return Err(Error::<T>::NoChargingSession.into()),
+                Some(session) => session,
+            };
+
+            // The payment is requested on behalf of the charger
+            Self::settle_session(
+                frame_system::RawOrigin::Signed(charger.clone()).into(),
+                charger,
+                connector,
+                Self::last_checkpoint_kwh(&session),
+            );
+
+            Ok(().into())
+        }
+
+        #[pallet::weight(1_000)]
+        pub fn record_checkpoint(
+            origin: OriginFor<T>,
+            user: T::AccountId,
+            connector: ConnectorId,
+            meter: MeterSample,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
+
+            let now = <timestamp::Module<T>>::get();
+
+            // Validate that a session exists for this user & connector
+            let mut session = match ActiveSessions::<T>::get(&sender, connector) {
+                Some(session) if session.user_id == user => session,
+                _ => return Err(Error::<T>::NoChargingSession.into()),
+            };
+
+            // Meter readings only move forward: reject replayed or older readings
+            if let Some(last) = &session.last_checkpoint {
+                ensure!(
+                    meter.timestamp > last.read_at && meter.energy_wh >= last.energy_wh,
+                    Error::<T>::StaleCheckpoint
+                );
+            }
+
+            session.last_checkpoint = Some(MeterCheckpoint {
+                energy_wh: meter.energy_wh,
+                power_w: meter.power_w,
+                read_at: meter.timestamp,
+                recorded_at: now,
+            });
+            let session_id = session.session_id;
+            ActiveSessions::<T>::insert(&sender, connector, session);
+
+            // Emit an event
+            Self::deposit_event(Event::SessionCheckpoint(
+                user,
+                sender,
+                connector,
+                session_id,
+                meter.energy_wh,
+                meter.power_w,
+            ));
+
+            Ok(().into())
+        }
+
+        /// Set the public key of the meter of a charger, which signs the readings of the
+        /// sessions: callable by an admin of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn set_meter_key(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            public_key: Vec<u8>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
+            ensure!(
+                ocmf::is_valid_public_key(&public_key),
+                Error::<T>::InvalidMeterKey
+            );
+
+            MeterKeys::<T>::insert(&charger, &public_key);
+            Self::deposit_event(Event::MeterKeySet(sender, charger, public_key));
+
+            Ok(().into())
+        }
+
+        /// Limit the charging power of a charger (connector 0) or of the active session of one
+        /// of its connectors, until `valid_until` if set: callable by an admin of the charger
+        /// organization. The offchain worker of the charger pushes the limit to the hardware.
+        #[pallet::weight(1_000)]
+        pub fn set_power_limit(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            connector: ConnectorId,
+            watts: u32,
+            valid_until: Option<T::Moment>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
+            ensure!(
+                connector == 0 || ActiveSessions::<T>::contains_key(&charger, connector),
+                Error::<T>::NoChargingSession
+            );
+            let now = <timestamp::Module<T>>::get();
+            ensure!(
+                valid_until.map_or(true, |valid_until| valid_until > now),
+                Error::<T>::PowerLimitExpired
+            );
+
+            PowerLimits::<T>::insert(
+                &charger,
+                connector,
+                PowerLimit {
+                    watts,
+                    valid_until,
+                    set_by: sender.clone(),
+                    applied: false,
+                },
+            );
+            Self::deposit_event(Event::PowerLimitSet(
+                sender,
+                charger,
+                connector,
+                watts,
+                valid_until,
+            ));
+
+            Ok(().into())
+        }
+
+        /// Report that the power limit of a connector has taken effect on the charger
+        #[pallet::weight(1_000)]
+        pub fn power_limit_applied(
+            origin: OriginFor<T>,
+            connector: ConnectorId,
+            watts: u32,
+            valid_until: Option<T::Moment>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
+
+            let mut limit = match PowerLimits::<T>::get(&sender, connector) {
+                Some(limit) if limit.watts == watts && limit.valid_until == valid_until => limit,
+                _ => return Err(Error::<T>::NoPowerLimit.into()),
+            };
+            limit.applied = true;
+            PowerLimits::<T>::insert(&sender, connector, limit);
+            Self::deposit_event(Event::PowerLimitApplied(sender, connector, watts));
+
+            Ok(().into())
+        }
+
+        /// Drop the power limit of a connector that the charger could not apply, with the
+        /// reason reported by the charger
+        #[pallet::weight(1_000)]
+        pub fn reject_power_limit(
+            origin: OriginFor<T>,
+            connector: ConnectorId,
+            watts: u32,
+            valid_until: Option<T::Moment>,
+            reason: ChargerError,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
+
+            match PowerLimits::<T>::get(&sender, connector) {
+                Some(limit) if limit.watts == watts && limit.valid_until == valid_until => {}
+                _ => return Err(Error::<T>::NoPowerLimit.into()),
+            }
+            PowerLimits::<T>::remove(&sender, connector);
+            Self::deposit_event(Event::PowerLimitRejected(sender, connector, watts, reason));
+
+            Ok(().into())
+        }
+
+        /// Create or update a site, whose chargers share a grid connection of `max_power_w`:
+        /// callable by an admin of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn set_site(
+            origin: OriginFor<T>,
+            site_id: SiteId,
+            max_power_w: u32,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+
+            Sites::<T>::insert(site_id, Site { max_power_w });
+            Self::deposit_event(Event::SiteSet(site_id, max_power_w));
+
+            Ok(().into())
+        }
+
+        /// Assign a charger to a site, or remove it from its site with `None`: callable by an
+        /// admin of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn assign_to_site(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            site_id: Option<SiteId>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
+            if let Some(site_id) = site_id {
+                ensure!(Sites::<T>::contains_key(site_id), Error::<T>::UnknownSite);
+            }
+
+            if let Some(previous) = ChargerSites::<T>::take(&charger) {
+                SiteChargers::<T>::remove(previous, &charger);
+            }
+            if let Some(site_id) = site_id {
+                ChargerSites::<T>::insert(&charger, site_id);
+                SiteChargers::<T>::insert(site_id, &charger, ());
+            }
+            Self::deposit_event(Event::ChargerSiteAssigned(charger, site_id));
+
+            Ok(().into())
+        }
+
+        /// Register a token of the sender (UID of an RFID card, or ISO 15118 contract id), as
+        /// reported by the chargers: presenting it to a charger requests a session on behalf
+        /// of the sender, who must have given its payment consent
+        #[pallet::weight(1_000)]
+        pub fn register_token(origin: OriginFor<T>, token: Vec<u8>) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(
+                !token.is_empty() && token.len() <= MAX_TOKEN_LENGTH,
+                Error::<T>::InvalidToken
+            );
+            ensure!(
+                <pallet_session_payment::Module<T>>::has_consent(&sender),
+                Error::<T>::NoPaymentConsent
+            );
+            ensure!(
+                !Tokens::<T>::contains_key(&token),
+                Error::<T>::TokenAlreadyRegistered
+            );
+
+            Tokens::<T>::insert(&token, &sender);
+            Self::deposit_event(Event::TokenRegistered(sender, token));
+
+            Ok(().into())
+        }
+
+        /// Remove a token from the registry: callable by its owner, or by an admin of the
+        /// charger organization (eg. for a lost card)
+        #[pallet::weight(1_000)]
+        pub fn unregister_token(
+            origin: OriginFor<T>,
+            token: Vec<u8>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            let owner = Tokens::<T>::get(&token).ok_or(Error::<T>::UnknownToken)?;
+            ensure!(
+                owner == sender || Self::is_admin(&sender),
+                Error::<T>::NotTokenOwner
+            );
+
+            Tokens::<T>::remove(&token);
+            Self::deposit_event(Event::TokenUnregistered(owner, token));
+
+            Ok(().into())
+        }
+
+        /// Request a new session on `connector` on behalf of the owner of the token presented
+        /// to the charger
+        #[pallet::weight(1_000)]
+        pub fn new_token_request(
+            origin: OriginFor<T>,
+            token: Vec<u8>,
+            connector: ConnectorId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
+            let user = Tokens::<T>::get(&token).ok_or(Error::<T>::UnknownToken)?;
+            Self::store_request(user, sender, connector, SessionLimits::default())
+        }
+
+        /// Trust `certificate`, a self-signed root, to issue contract certificates: callable
+        /// by an admin of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn add_contract_root(
+            origin: OriginFor<T>,
+            certificate: Vec<u8>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(
+                contract::is_valid_root(&certificate),
+                Error::<T>::InvalidContractRoot
+            );
+
+            let root_hash = T::Hashing::hash(&certificate);
+            ContractRoots::<T>::insert(root_hash, certificate);
+            Self::deposit_event(Event::ContractRootAdded(sender, root_hash));
+
+            Ok(().into())
+        }
+
+        /// Stop trusting a root: its contracts cannot request sessions anymore. Callable by
+        /// an admin of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn remove_contract_root(
+            origin: OriginFor<T>,
+            root_hash: T::Hash,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(
+                ContractRoots::<T>::contains_key(root_hash),
+                Error::<T>::UnknownContractRoot
+            );
+
+            ContractRoots::<T>::remove(root_hash);
+            Self::deposit_event(Event::ContractRootRemoved(sender, root_hash));
+
+            Ok(().into())
+        }
+
+        /// Register the ISO 15118 contract `emaid` of the sender, with the certificate chain
+        /// installed in its vehicle (the contract certificate first): once the chain is
+        /// verified, the vehicle starts charging as soon as it is plugged in. The sender must
+        /// have given its payment consent. Registering the contract again renews its chain
+        #[pallet::weight(1_000)]
+        pub fn register_contract(
+            origin: OriginFor<T>,
+            emaid: Vec<u8>,
+            certificate_chain: Vec<Vec<u8>>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(
+                <pallet_session_payment::Module<T>>::has_consent(&sender),
+                Error::<T>::NoPaymentConsent
+            );
+            let contract = Self::verify_contract(&certificate_chain)?;
+            ensure!(contract.emaid == emaid, Error::<T>::ContractMismatch);
+            if let Some(registered) = Contracts::<T>::get(&emaid) {
+                ensure!(
+                    registered.owner == sender,
+                    Error::<T>::ContractAlreadyRegistered
+                );
+            }
+
+            Contracts::<T>::insert(
+                &emaid,
+                RegisteredContract {
+                    owner: sender.clone(),
+                    certificate_chain,
+                },
+            );
+            Self::deposit_event(Event::ContractRegistered(sender, emaid));
+
+            Ok(().into())
+        }
+
+        /// Remove a contract: callable by its owner, or by an admin of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn unregister_contract(
+            origin: OriginFor<T>,
+            emaid: Vec<u8>,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            let registered = Contracts::<T>::get(&emaid).ok_or(Error::<T>::UnknownContract)?;
+            ensure!(
+                registered.owner == sender || Self::is_admin(&sender),
+                Error::<T>::NotContractOwner
+            );
+
+            Contracts::<T>::remove(&emaid);
+            Self::deposit_event(Event::ContractUnregistered(registered.owner, emaid));
+
+            Ok(().into())
+        }
+
+        /// Request a new session on `connector` on behalf of the owner of the contract presented
+        /// by a vehicle with `certificate`, its contract certificate (DER encoded). The chain
+        /// of the contract is verified again, its certificates or root may have lapsed
+        #[pallet::weight(1_000)]
+        pub fn new_contract_request(
+            origin: OriginFor<T>,
+            emaid: Vec<u8>,
+            certificate: Vec<u8>,
+            connector: ConnectorId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_charger(&sender), Error::<T>::NotRegisteredCharger);
+            let registered = Contracts::<T>::get(&emaid).ok_or(Error::<T>::UnknownContract)?;
+            ensure!(
+                registered.certificate_chain.first() == Some(&certificate),
+                Error::<T>::ContractMismatch
+            );
+            Self::verify_contract(&registered.certificate_chain)?;
+            Self::store_request(registered.owner, sender, connector, SessionLimits::default())
+        }
+
+        #[pallet::weight(1_000)]
+        pub fn add_new_charger(
+            origin: OriginFor<T>,
+            charger_id: T::AccountId,
+            metadata: ChargerMetadata,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin.clone())?;
+            // Check that signer is admin (= owner of chargers organizaton)
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            // Check that this charger is not already registered
+            ensure!(Self::is_charger(&charger_id) == false, Error::<T>::AlreadyRegisteredCharger);
+            Self::check_metadata(&metadata)?;
+             match <pallet_did::Module<T>>::attribute_and_id(&charger_id, b"location") {
+                 // Check that charger has a location attribute
+                None =>  return Err(Error::<T>::NoLocation.into()),
+                Some(location) => {
+                    // Add charger to organization
+                    <pallet_registrar::Module<T>>::add_to_organization(origin.clone(), charger_id.clone())?;
+                    ChargerMetadatas::<T>::insert(&charger_id, metadata);
+                    RemovedChargers::<T>::remove(&charger_id);
+
+                    // Emit an event
+                    Self::deposit_event(Event::NewChargerAdded(sender, charger_id, location.0.value));
+                    Ok(().into())
+                }
+             }
+        }
+
+        /// Replace the metadata of a registered charger: callable by an admin of the charger
+        /// organization, or by the charger itself
+        #[pallet::weight(1_000)]
+        pub fn update_charger_metadata(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            metadata: ChargerMetadata,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(sender == charger || Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
+            Self::check_metadata(&metadata)?;
+
+            ChargerMetadatas::<T>::insert(&charger, metadata);
+            Self::deposit_event(Event::ChargerMetadataUpdated(sender, charger));
+
+            Ok(().into())
+        }
+
+        /// Suspend a charger, which cannot receive new requests until resumed: its pending
+        /// requests are cancelled and its active sessions closed with the energy of their
+        /// last checkpoint. Callable by an admin of the charger organization.
+        #[pallet::weight(1_000)]
+        pub fn suspend_charger(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
+            ensure!(
+                !SuspendedChargers::<T>::contains_key(&charger),
+                Error::<T>::ChargerSuspended
+            );
+
+            let closed = Self::close_charger_sessions(&charger);
+            SuspendedChargers::<T>::insert(&charger, closed);
+            Self::deposit_event(Event::ChargerSuspended(sender, charger));
+
+            Ok(().into())
+        }
+
+        /// Resume a suspended charger: callable by an admin of the charger organization
+        #[pallet::weight(1_000)]
+        pub fn resume_charger(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin)?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(
+                SuspendedChargers::<T>::contains_key(&charger),
+                Error::<T>::ChargerNotSuspended
+            );
+
+            SuspendedChargers::<T>::remove(&charger);
+            Self::deposit_event(Event::ChargerResumed(sender, charger));
+
+            Ok(().into())
+        }
+
+        /// Remove a charger from the charger organization, suspended or not: its pending
+        /// requests are cancelled and its active sessions closed as by suspend_charger, and
+        /// it leaves its site. Its session history is kept; its metadata and meter key are
+        /// removed. Callable by an admin of the charger organization.
+        #[pallet::weight(1_000)]
+        pub fn remove_charger(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+        ) -> DispatchResultWithPostInfo {
+            let sender = ensure_signed(origin.clone())?;
+            ensure!(Self::is_admin(&sender), Error::<T>::NotAnAdmin);
+            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
+
+            <pallet_registrar::Module<T>>::remove_from_organization(origin, charger.clone())?;
+            let mut closed = SuspendedChargers::<T>::take(&charger).unwrap_or_default();
+            closed.extend(Self::close_charger_sessions(&charger));
+            if !closed.is_empty() {
+                RemovedChargers::<T>::insert(&charger, closed);
+            }
+            ChargerMetadatas::<T>::remove(&charger);
+            MeterKeys::<T>::remove(&charger);
+            LastMeterReadings::<T>::remove_prefix(&charger);
+            if let Some(site_id) = ChargerSites::<T>::take(&charger) {
+                SiteChargers::<T>::remove(site_id, &charger);
+            }
+            PowerLimits::<T>::remove_prefix(&charger);
+            FaultedConnectors::<T>::remove_prefix(&charger);
+            Self::deposit_event(Event::ChargerRemoved(sender, charger));
+
+            Ok(().into())
+        }
+    }
+
+    impl<T: Config> Pallet<T> {
+        /// Remove the requests older than `RequestTimeout`, with their charge consent: their
+        /// charger did not start them, and would otherwise stay busy. Only the head of
+        /// `RequestQueue` is visited, up to `MAX_EXPIRY_VISITS` requests.
+        fn expire_requests() -> Weight {
+            let now = <timestamp::Module<T>>::get();
+            let timeout = T::RequestTimeout::get();
+
+            let (first, tail) = RequestQueueBounds::<T>::get();
+            let mut head = first;
+            let mut reads: u64 = 1;
+            let mut writes: u64 = 0;
+            while head < tail && head - first < MAX_EXPIRY_VISITS {
+                reads += 2;
+                if let Some((charger, connector, session_id)) = RequestQueue::<T>::get(head) {
+                    match UserRequests::<T>::get(&charger, connector) {
+                        // The queue is ordered by creation: the next requests are not expired
+                        Some(request)
+                            if request.session_id == session_id
+                                && now.saturating_sub(request.created_at) <= timeout =>
+                        {
+                            break
+                        }
+                        Some(request) if request.session_id == session_id => {
+                            debug::native::info!(
+                                "Request of user {} on connector {} of charger {} expired",
+                                &request.user_id,
+                                connector,
+                                charger
+                            );
+                            UserRequests::<T>::remove(&charger, connector);
+                            <consent::Module<T>>::remove_consent(request.session_id);
+                            Self::deposit_event(Event::RequestExpired(
+                                request.user_id,
+                                charger,
+                                connector,
+                                request.session_id,
+                            ));
+                            writes += 2;
+                        }
+                        // Started, cancelled or rejected since queued
+                        _ => {}
+                    }
+                }
+                RequestQueue::<T>::remove(head);
+                writes += 1;
+                head += 1;
+            }
+            if head != first {
+                RequestQueueBounds::<T>::put((head, tail));
+                writes += 1;
+            }
+
+            T::DbWeight::get().reads_writes(reads, writes)
+        }
+
+        /// Cancel the pending requests of `charger`, and close its active sessions with the
+        /// energy of their last checkpoint, as the charger can no longer report it. Returns
+        /// the connectors of the closed sessions.
+        fn close_charger_sessions(charger: &T::AccountId) -> Vec<ConnectorId> {
+            for (connector, request) in UserRequests::<T>::drain_prefix(charger) {
+                <consent::Module<T>>::remove_consent(request.session_id);
+                Self::deposit_event(Event::SessionCancelled(
+                    request.user_id,
+                    charger.clone(),
+                    connector,
+                    request.session_id,
+                ));
+            }
+
+            let sessions: Vec<_> = ActiveSessions::<T>::iter_prefix(charger).collect();
+            for (connector, session) in sessions.iter() {
+                // The payment is requested on behalf of the charger
+                Self::settle_session(
+                    frame_system::RawOrigin::Signed(charger.clone()).into(),
+                    charger.clone(),
+                    *connector,
+                    Self::last_checkpoint_kwh(session),
+                );
+            }
+            sessions.into_iter().map(|(connector, _)| connector).collect()
+        }
+
+        /// Energy (in kWh) of the last checkpoint of `session`, or 0 without checkpoint
+        fn last_checkpoint_kwh(session: &ChargingSession<T::AccountId, T::Moment, T::Hash>) -> u64 {
+            session
+                .last_checkpoint
+                .as_ref()
+                .map(|checkpoint| checkpoint.energy_wh / 1000)
+                .unwrap_or_default()
+        }
+
+        /// Check that `metadata` describes a charger which can be displayed by the apps
+        fn check_metadata(metadata: &ChargerMetadata) -> Result<(), Error<T>> {
+            let coordinates = metadata.coordinates;
+            ensure!(
+                coordinates.latitude.abs() <= 90_000_000
+                    && coordinates.longitude.abs() <= 180_000_000,
+                Error::<T>::InvalidCoordinates
+            );
+
+            let valid_text = |text: &Vec<u8>| {
+                !text.is_empty()
+                    && text.len() <= MAX_METADATA_TEXT_LENGTH
+                    && sp_std::str::from_utf8(text).is_ok()
+            };
+            ensure!(
+                valid_text(&metadata.address) && valid_text(&metadata.operator),
+                Error::<T>::InvalidChargerText
+            );
+
+            let connectors = &metadata.connectors;
+            ensure!(
+                !connectors.is_empty() && connectors.len() <= MAX_METADATA_CONNECTORS,
+                Error::<T>::InvalidConnectorMetadata
+            );
+            for (index, connector) in connectors.iter().enumerate() {
+                let current = match connector.connector_type {
+                    ConnectorType::Type2 => CurrentType::Ac,
+                    ConnectorType::Ccs | ConnectorType::Chademo => CurrentType::Dc,
+                };
+                ensure!(
+                    connector.connector != 0
+                        && connector.max_power_w > 0
+                        && connector.current == current
+                        && connectors[..index]
+                            .iter()
+                            .all(|other| other.connector != connector.connector),
+                    Error::<T>::InvalidConnectorMetadata
+                );
+            }
+
+            ensure!(
+                metadata.opening_hours.len() <= MAX_OPENING_PERIODS
+                    && metadata.opening_hours.iter().all(|period| {
+                        period.weekday <= 6 && period.opens < period.closes && period.closes <= 1440
+                    }),
+                Error::<T>::InvalidOpeningHours
+            );
+            Ok(())
+        }
+
+        /// Store the request of `user` for a new session on `connector` of `charger`
+        fn store_request(
+            user: T::AccountId,
+            charger: T::AccountId,
+            connector: ConnectorId,
+            limits: SessionLimits<T::Moment>,
+        ) -> DispatchResultWithPostInfo {
+            ensure!(Self::is_charger(&charger), Error::<T>::NotRegisteredCharger);
+            ensure!(
+                !SuspendedChargers::<T>::contains_key(&charger),
+                Error::<T>::ChargerSuspended
+            );
+
+            let now = <timestamp::Module<T>>::get();
+
+            // Check that user consent exists in pallet_session_payment
+            if <pallet_session_payment::Module<T>>::has_consent(&user) == false {
+                debug::native::warn!("No consent for user {}", &user,);
+                return Err(Error::<T>::NoPaymentConsent.into());
+            }
+
+            // Check that this connector is not faulted
+            ensure!(
+                !FaultedConnectors::<T>::contains_key(&charger, connector),
+                Error::<T>::ConnectorFaulted
+            );
+
+            // Check that this connector does not have another pending request
+            match UserRequests::<T>::get(&charger, connector) {
+                Some(request) => {
+                    debug::native::warn!(
+                        "Charger {} has pending request {:?} on connector {}: cannot store a new request",
+                        &charger,
+                        &request,
+                        connector
+                    );
+                    return Err(Error::<T>::ChargerIsBusy.into());
+                }
+                _ => {}
+            }
+
+            // Check that this connector does not have an active charging session
+            match ActiveSessions::<T>::get(&charger, connector) {
+                Some(session) => {
+                    debug::native::warn!(
+                        "Charger {} has already active session {:?} on connector {}: cannot store a new request",
+                        &charger,
+                        &session,
+                        connector
+                    );
+                    return Err(Error::<T>::ChargerIsBusy.into());
+                }
+                _ => {}
+            }
+
+            // Generate a new session_id
+            let session_id = Self::generate_charge_id(&user, &charger, connector);
+
+            // Store the charge consent, given by the user with the request or the registration
+            // of its token
+            <consent::Module<T>>::new_consent_for_user(
+                frame_system::RawOrigin::Signed(user.clone()).into(),
+                charger.clone(),
+                session_id.clone(),
+            )?;
+
+            // Add the request to the storage with current timestamp
+            UserRequests::<T>::insert(
+                &charger,
+                connector,
+                ChargeRequest {
+                    user_id: user.clone(),
+                    created_at: now,
+                    session_id,
+                    limits,
+                },
+            );
+            RequestQueueBounds::<T>::mutate(|(_, tail)| {
+                RequestQueue::<T>::insert(*tail, (charger.clone(), connector, session_id));
+                *tail += 1;
+            });
+
+            Self::deposit_event(Event::SessionRequested(
+                user, charger, connector, now, session_id,
+            ));
+
+            Ok(().into())
+        }
+
+        /// Verify a contract certificate chain against the trusted roots, at the current time
+        fn verify_contract(
+            certificate_chain: &[Vec<u8>],
+        ) -> Result<contract::Contract, Error<T>> {
+            let roots: Vec<Vec<u8>> = ContractRoots::<T>::iter_values().collect();
+            let now: u64 = <timestamp::Module<T>>::get().unique_saturated_into();
+            contract::verify_contract(certificate_chain, &roots, now / 1000).map_err(|error| {
+                match error {
+                    ContractError::Malformed
+                    | ContractError::UnsupportedAlgorithm
+                    | ContractError::NoEmaid => Error::<T>::InvalidContractCertificate,
+                    ContractError::BadSignature | ContractError::NotACertificateAuthority => {
+                        Error::<T>::BadContractSignature
+                    }
+                    ContractError::UntrustedRoot => Error::<T>::UntrustedContractRoot,
+                    ContractError::NotValidNow => Error::<T>::ContractCertificateExpired,
+                }
+            })
+        }
+
+        /// Remove the active session of `connector`, execute its payment and emit SessionEnded
+        fn settle_session(
+            origin: OriginFor<T>,
+            charger: T::AccountId,
+            connector: ConnectorId,
+            kwh: u64,
+        ) {
+            let now = <timestamp::Module<T>>::get();
+
+            // Remove the session from storage, with its power limit
+            let session = match ActiveSessions::<T>::take(&charger, connector) {
+                Some(session) => session,
+                None => return,
+            };
+            PowerLimits::<T>::remove(&charger, connector);
+
+            // Execute the payment
+            let amount = match <pallet_session_payment::Module<T>>::process_payment(
+                origin,
+                session.session_id,
+                kwh.into(),
+            ) {
+                Err(error) => {
+                    // The error is just logged here, because we want to end the session even if payment has failed
+                    // pallet_session_payment deposits an error event which is handled manually in this case
+                    debug::native::error!(
+                        "An error occured in pallet_session_payment::process_payment for session id {}: {:?}",
+                        &session.session_id,
+                        error
+                    );
+                    None
+                }
+                _ => <pallet_session_payment::Module<T>>::pending_payments()
+                    .iter()
+                    .find(|payment| payment.session_id() == &session.session_id)
+                    .map(|payment| payment.amount()),
+            };
+
+            // Keep the session in the history of its user and its charger
+            SessionHistory::<T>::insert(
+                session.session_id,
+                SessionRecord {
+                    session_id: session.session_id,
+                    user_id: session.user_id.clone(),
+                    charger_id: charger.clone(),
+                    connector,
+                    started_at: session.started_at,
+                    ended_at: now,
+                    kwh,
+                    amount,
+                    payment_status: match amount {
+                        Some(_) => PaymentStatus::Pending,
+                        None => PaymentStatus::Failed,
+                    },
+                },
+            );
+            let index = UserSessionCount::<T>::mutate(&session.user_id, |count| {
+                *count += 1;
+                *count - 1
+            });
+            UserSessions::<T>::insert(&session.user_id, index, session.session_id);
+            let index = ChargerSessionCount::<T>::mutate(&charger, |count| {
+                *count += 1;
+                *count - 1
+            });
+            ChargerSessions::<T>::insert(&charger, index, session.session_id);
+
+            // Emit an event
+            Self::deposit_event(Event::SessionEnded(
+                session.user_id,
+                charger,
+                connector,
+                session.started_at,
+                now,
+                session.session_id,
+                kwh,
+            ));
+        }
+
+        fn process_charge_sessions(block: T::BlockNumber) {
+            // Get the list of charger accounts
+            let accounts = <<T as Config>::AuthorityId as AppCrypto<
+                <T as SigningTypes>::Public,
+                <T as SigningTypes>::Signature,
+            >>::RuntimeAppPublic::all()
+            .into_iter()
+            .map(|key| {
+                let generic_public = <<T as Config>::AuthorityId as AppCrypto<
+                    <T as SigningTypes>::Public,
+                    <T as SigningTypes>::Signature,
+                >>::GenericPublic::from(key);
+                let public: <T as SigningTypes>::Public = generic_public.into();
+                let signer = Signer::<T, <T as Config>::AuthorityId>::all_accounts()
+                    .with_filter(sp_std::vec!(public.clone()));
+                (public.clone().into_account(), signer)
+            });
+
+            // For each charger account registered in the keystore:
+            for (account_id, signer) in accounts {
+                debug::native::debug!("Use charger account {}", account_id);
+
+                // A suspended charger only stops the sessions closed by its suspension
+                if let Some(connectors) = SuspendedChargers::<T>::get(&account_id) {
+                    debug::native::warn!("Charger {} is suspended", account_id);
+                    Self::stop_closed_sessions(&connectors);
+                    Self::discard_presented_authorizations();
+                    continue;
+                }
+
+                // So does a removed charger, with the sessions closed by its removal
+                if let Some(connectors) = RemovedChargers::<T>::get(&account_id) {
+                    debug::native::warn!("Charger {} is removed", account_id);
+                    Self::stop_closed_sessions(&connectors);
+                    Self::discard_presented_authorizations();
+                    continue;
+                }
+
+                // 1) Check if pending user requests exist for the connectors of this charger
+                for (connector, request) in UserRequests::<T>::iter_prefix(&account_id) {
+                    // The workers of consecutive blocks may run at the same time
+                    let lock_key = Self::connector_lock_key(&account_id, connector);
+                    let mut lock = StorageLock::<Time>::with_deadline(
+                        &lock_key,
+                        Duration::from_millis(CONNECTOR_LOCK_DEADLINE),
+                    );
+                    let _guard = match lock.try_lock() {
+                        Ok(guard) => guard,
+                        Err(_) => continue,
+                    };
+                    // The start_session transaction may still be in the pool
+                    if let Some(SentTransaction::StartSession(sent_at)) =
+                        Self::sent_transaction(&account_id, connector, request.session_id)
+                    {
+                        if !Self::is_submission_timed_out(sent_at, block) {
+                            debug::native::debug!(
+                                "Session on connector {} started at block {:?}, waiting for its start_session transaction",
+                                connector,
+                                sent_at
+                            );
+                            continue;
+                        }
+                    }
+                    debug::native::debug!(
+                        "User {} requests a new charge session on connector {}",
+                        &request.user_id,
+                        connector
+                    );
+                    // After a restart of the node, the session may already be running on the
+                    // charger while its start_session transaction was lost
+                    let started = charger_api::start_charge(connector).or_else(|reason| {
+                        match charger_api::get_current_charge_status(connector) {
+                            ChargeStatus::Active { .. }
+                            | ChargeStatus::SuspendedEV { .. }
+                            | ChargeStatus::SuspendedEVSE { .. } => {
+                                debug::native::warn!(
+                                    "Charge session already running on connector {}, resuming it",
+                                    connector
+                                );
+                                Ok(())
+                            }
+                            _ => Err(reason),
+                        }
+                    });
+                    match started {
+                        Ok(()) => {
+                            debug::native::info!(
+                                "Charge session started for user {} on connector {}",
+                                &request.user_id,
+                                connector
+                            );
+                            Self::record_started_request(
+                                &account_id,
+                                connector,
+                                request.session_id,
+                            );
+                            Self::record_sent_transaction(
+                                &account_id,
+                                connector,
+                                request.session_id,
+                                SentTransaction::StartSession(block),
+                            );
+                            if Self::send_signed_transaction(
+                                &signer,
+                                Call::start_session(request.user_id.clone(), connector),
+                            )
+                            .is_err()
+                            {
+                                debug::native::error!(
+                                    "Error occured while sending start_session transaction"
+                                );
+                            }
+                        }
+                        Err(reason) => {
+                            debug::native::warn!(
+                                "Charger cannot start a session for user {} on connector {}: rejecting the request",
+                                &request.user_id,
+                                connector
+                            );
+                            if Self::send_signed_transaction(
+                                &signer,
+                                Call::reject_request(request.user_id.clone(), connector, reason),
+                            )
+                            .is_err()
+                            {
+                                debug::native::error!(
+                                    "Error occured while sending reject_request transaction"
+                                );
+                            }
+                        }
+                    }
+                }
+
+                // 2) Check the active charge sessions on the connectors of this charger
+                for (connector, session) in ActiveSessions::<T>::iter_prefix(&account_id) {
+                    let lock_key = Self::connector_lock_key(&account_id, connector);
+                    let mut lock = StorageLock::<Time>::with_deadline(
+                        &lock_key,
+                        Duration::from_millis(CONNECTOR_LOCK_DEADLINE),
+                    );
+                    let _guard = match lock.try_lock() {
+                        Ok(guard) => guard,
+                        Err(_) => continue,
+                    };
+                    match Self::sent_transaction(&account_id, connector, session.session_id) {
+                        // The start_session transaction is included
+                        Some(SentTransaction::StartSession(_)) => {
+                            Self::clear_sent_transaction(&account_id, connector)
+                        }
+                        // The charger already reported the end of the session, which is only
+                        // sent again once the end_session transaction has timed out
+                        Some(SentTransaction::EndSession(sent_at, kwh, signed_data)) => {
+                            if Self::is_submission_timed_out(sent_at, block) {
+                                debug::native::warn!(
+                                    "end_session transaction of connector {} sent at block {:?} was not included, sending it again",
+                                    connector,
+                                    sent_at
+                                );
+                                Self::send_end_session(
+                                    &signer,
+                                    &account_id,
+                                    connector,
+                                    &session,
+                                    kwh,
+                                    signed_data,
+                                    block,
+                                );
+                            }
+                            continue;
+                        }
+                        // The connector already faulted, the report_fault transaction is only
+                        // sent again once it has timed out
+                        Some(SentTransaction::ReportFault(sent_at, error_code, kwh)) => {
+                            if Self::is_submission_timed_out(sent_at, block) {
+                                debug::native::warn!(
+                                    "report_fault transaction of connector {} sent at block {:?} was not included, sending it again",
+                                    connector,
+                                    sent_at
+                                );
+                                Self::send_report_fault(
+                                    &signer,
+                                    &account_id,
+                                    connector,
+                                    &session,
+                                    error_code,
+                                    kwh,
+                                    block,
+                                );
+                            }
+                            continue;
+                        }
+                        // The charger lost the session, which is only closed again once the
+                        // close_session transaction has timed out
+                        Some(SentTransaction::CloseSession(sent_at)) => {
+                            if Self::is_submission_timed_out(sent_at, block) {
+                                debug::native::warn!(
+                                    "close_session transaction of connector {} sent at block {:?} was not included, sending it again",
+                                    connector,
+                                    sent_at
                                 );
-                                if Self::send_signed_transaction(
+                                Self::send_close_session(
                                     &signer,
-                                    Call::end_session(session.user_id.clone(), kwh),
-                                )
-                                .is_err()
-                                {
-                                    debug::native::error!(
-                                        "Error occured while sending end_session transaction"
-                                    );
+                                    &account_id,
+                                    connector,
+                                    &session,
+                                    block,
+                                );
+                            }
+                            continue;
+                        }
+                        None => {}
+                    }
+
+                    // We have an active session, check the current status
+                    match charger_api::get_current_charge_status(connector) {
+                        ChargeStatus::NoCharge => {
+                            // The charger lost the session (eg. the node restarted without its
+                            // persisted sessions): close it with the energy of the last checkpoint,
+                            // as there is no final reading to end it with
+                            debug::native::error!(
+                                "Charge session is active in-chain, but not found off-chain (connector {}): closing it",
+                                connector
+                            );
+                            Self::send_close_session(
+                                &signer,
+                                &account_id,
+                                connector,
+                                &session,
+                                block,
+                            );
+                        }
+                        ChargeStatus::Unavailable => {
+                            debug::native::warn!(
+                                "Connector {} is unavailable, waiting...",
+                                connector
+                            );
+                        }
+                        ChargeStatus::Faulted { error_code, meter } => {
+                            // End the session with the energy delivered so far
+                            let energy_wh = meter
+                                .map(|meter| meter.energy_wh)
+                                .or(session
+                                    .last_checkpoint
+                                    .as_ref()
+                                    .map(|checkpoint| checkpoint.energy_wh))
+                                .unwrap_or_default();
+                            debug::native::warn!(
+                                "Connector {} is faulted, ending session of user {} with {} Wh",
+                                connector,
+                                &session.user_id,
+                                energy_wh
+                            );
+                            Self::send_report_fault(
+                                &signer,
+                                &account_id,
+                                connector,
+                                &session,
+                                error_code,
+                                energy_wh / 1000,
+                                block,
+                            );
+                        }
+                        ChargeStatus::Active { .. }
+                        | ChargeStatus::SuspendedEV { .. }
+                        | ChargeStatus::SuspendedEVSE { .. }
+                            if session.stop_requested =>
+                        {
+                            debug::native::info!(
+                                "Stop of the charge session on connector {} is requested",
+                                connector
+                            );
+                            if charger_api::stop_charge(connector).is_err() {
+                                debug::native::error!(
+                                    "Cannot stop the charge session on connector {}",
+                                    connector
+                                );
+                            }
+                        }
+                        ChargeStatus::Active { meter }
+                        | ChargeStatus::SuspendedEV { meter }
+                        | ChargeStatus::SuspendedEVSE { meter }
+                            if Self::is_limit_reached(&session, meter.as_ref()) =>
+                        {
+                            debug::native::info!(
+                                "A limit of the charge session on connector {} is reached, stopping it",
+                                connector
+                            );
+                            if charger_api::stop_charge(connector).is_err() {
+                                debug::native::error!(
+                                    "Cannot stop the charge session on connector {}",
+                                    connector
+                                );
+                            }
+                        }
+                        ChargeStatus::Active { meter }
+                        | ChargeStatus::SuspendedEV { meter }
+                        | ChargeStatus::SuspendedEVSE { meter } => {
+                            debug::native::debug!(
+                                "Charge session is still active on connector {}, waiting...",
+                                connector
+                            );
+                            match meter {
+                                Some(meter) if Self::is_checkpoint_due(&session) => {
+                                    if Self::send_signed_transaction(
+                                        &signer,
+                                        Call::record_checkpoint(
+                                            session.user_id.clone(),
+                                            connector,
+                                            meter,
+                                        ),
+                                    )
+                                    .is_err()
+                                    {
+                                        debug::native::error!(
+                                            "Error occured while sending record_checkpoint transaction"
+                                        );
+                                    }
                                 }
+                                _ => {}
                             }
                         }
+                        ChargeStatus::Ended { kwh, signed_data } => {
+                            debug::native::info!(
+                                "Charge session is ended for user {} on connector {}, consumed: {} kwh",
+                                &session.user_id,
+                                connector,
+                                &kwh
+                            );
+                            Self::send_end_session(
+                                &signer,
+                                &account_id,
+                                connector,
+                                &session,
+                                kwh,
+                                signed_data,
+                                block,
+                            );
+                        }
+                    }
+                }
+
+                // 3) Push the power limits which have not taken effect yet to the charger.
+                // The limit of a charger on a site is enforced by its share of the site (4)
+                let now = <timestamp::Module<T>>::get();
+                let site_id = ChargerSites::<T>::get(&account_id);
+                for (connector, limit) in PowerLimits::<T>::iter_prefix(&account_id) {
+                    let lapsed = limit
+                        .valid_until
+                        .map_or(false, |valid_until| valid_until <= now);
+                    if limit.applied || lapsed || (connector == 0 && site_id.is_some()) {
+                        continue;
+                    }
+                    let valid_until = limit
+                        .valid_until
+                        .map(|valid_until| valid_until.unique_saturated_into());
+                    let applied = charger_api::set_power_limit(connector, limit.watts, valid_until);
+                    let call = match applied {
+                        Ok(()) => {
+                            debug::native::info!(
+                                "Power of connector {} limited to {} W",
+                                connector,
+                                limit.watts
+                            );
+                            Call::power_limit_applied(connector, limit.watts, limit.valid_until)
+                        }
+                        // The limit is pushed again once the charger is reachable
+                        Err(ChargerError::Unreachable) => {
+                            debug::native::warn!(
+                                "Charger unreachable, cannot limit power of connector {}",
+                                connector
+                            );
+                            continue;
+                        }
+                        Err(reason) => {
+                            debug::native::warn!(
+                                "Charger cannot limit power of connector {} to {} W: rejecting the limit",
+                                connector,
+                                limit.watts
+                            );
+                            Call::reject_power_limit(
+                                connector,
+                                limit.watts,
+                                limit.valid_until,
+                                reason,
+                            )
+                        }
+                    };
+                    if Self::send_signed_transaction(&signer, call).is_err() {
+                        debug::native::error!(
+                            "Error occured while sending power limit transaction"
+                        );
+                    }
+                }
+
+                // 4) Limit the charger to its share of the power of its site
+                match site_id {
+                    Some(site_id) => Self::balance_site_power(&signer, &account_id, site_id, now),
+                    // The share is pushed again if the charger is assigned to a site again
+                    None => StorageValueRef::persistent(&Self::site_share_key(&account_id)).clear(),
+                }
+
+                // 5) Request sessions on behalf of the owners of the tokens presented to the
+                // charger, the tokens of unknown users or without payment consent being dropped
+                for presented in charger_api::take_presented_tokens() {
+                    let user = match Tokens::<T>::get(&presented.token) {
+                        Some(user) => user,
+                        None => {
+                            debug::native::warn!(
+                                "Unknown token presented on connector {}",
+                                presented.connector
+                            );
+                            continue;
+                        }
+                    };
+                    if !<pallet_session_payment::Module<T>>::has_consent(&user) {
+                        debug::native::warn!(
+                            "Token of user {} presented on connector {}, but the user has no payment consent",
+                            &user,
+                            presented.connector
+                        );
+                        continue;
+                    }
+                    debug::native::info!(
+                        "Token of user {} presented on connector {}, requesting a session",
+                        &user,
+                        presented.connector
+                    );
+                    if Self::send_signed_transaction(
+                        &signer,
+                        Call::new_token_request(presented.token, presented.connector),
+                    )
+                    .is_err()
+                    {
+                        debug::native::error!(
+                            "Error occured while sending new_token_request transaction"
+                        );
+                    }
+                }
+
+                // 6) Request sessions on behalf of the owners of the contracts presented by the
+                // vehicles (Plug & Charge), the chain being verified on-chain
+                for authorization in charger_api::take_contract_authorizations() {
+                    let registered = match Contracts::<T>::get(&authorization.emaid) {
+                        Some(registered) => registered,
+                        None => {
+                            debug::native::warn!(
+                                "Unknown contract presented on connector {}",
+                                authorization.connector
+                            );
+                            continue;
+                        }
+                    };
+                    let certificate = authorization.certificate_chain.into_iter().next();
+                    let certificate = match certificate {
+                        Some(certificate)
+                            if registered.certificate_chain.first() == Some(&certificate) =>
+                        {
+                            certificate
+                        }
+                        _ => {
+                            debug::native::warn!(
+                                "Contract of user {} presented on connector {} with another certificate",
+                                &registered.owner,
+                                authorization.connector
+                            );
+                            continue;
+                        }
+                    };
+                    if !<pallet_session_payment::Module<T>>::has_consent(&registered.owner) {
+                        debug::native::warn!(
+                            "Contract of user {} presented on connector {}, but the user has no payment consent",
+                            &registered.owner,
+                            authorization.connector
+                        );
+                        continue;
+                    }
+                    debug::native::info!(
+                        "Contract of user {} presented on connector {}, requesting a session",
+                        &registered.owner,
+                        authorization.connector
+                    );
+                    if Self::send_signed_transaction(
+                        &signer,
+                        Call::new_contract_request(
+                            authorization.emaid,
+                            certificate,
+                            authorization.connector,
+                        ),
+                    )
+                    .is_err()
+                    {
+                        debug::native::error!(
+                            "Error occured while sending new_contract_request transaction"
+                        );
+                    }
+                }
+
+                // 7) Stop the sessions started for requests which were cancelled or expired
+                // before their start_session transaction
+                Self::stop_sessions_of_dropped_requests(&account_id);
+            }
+        }
+
+        /// Remember that the charger started a session for the request `session_id`, until its
+        /// start_session transaction is included
+        fn record_started_request(
+            charger: &T::AccountId,
+            connector: ConnectorId,
+            session_id: T::Hash,
+        ) {
+            let storage = StorageValueRef::persistent(&Self::started_requests_key(charger));
+            let mut started = storage
+                .get::<Vec<(ConnectorId, T::Hash)>>()
+                .flatten()
+                .unwrap_or_default();
+            started.retain(|(started_connector, _)| *started_connector != connector);
+            started.push((connector, session_id));
+            storage.set(&started);
+        }
+
+        /// Stop the sessions which the charger started for requests that are no longer pending
+        /// and did not become an active session: the request was cancelled by its user, or has
+        /// expired, while its start_session transaction was pending
+        fn stop_sessions_of_dropped_requests(charger: &T::AccountId) {
+            let storage = StorageValueRef::persistent(&Self::started_requests_key(charger));
+            let started = match storage.get::<Vec<(ConnectorId, T::Hash)>>().flatten() {
+                Some(started) if !started.is_empty() => started,
+                _ => return,
+            };
+
+            let mut waiting = Vec::new();
+            for (connector, session_id) in started {
+                if ActiveSessions::<T>::get(charger, connector)
+                    .map_or(false, |session| session.session_id == session_id)
+                {
+                    continue;
+                }
+                if UserRequests::<T>::get(charger, connector)
+                    .map_or(false, |request| request.session_id == session_id)
+                {
+                    waiting.push((connector, session_id));
+                    continue;
+                }
+                // A new request of the same user in the same block gets the same session id
+                if Self::sent_transaction(charger, connector, session_id).is_some() {
+                    Self::clear_sent_transaction(charger, connector);
+                }
+                match charger_api::get_current_charge_status(connector) {
+                    ChargeStatus::Active { .. }
+                    | ChargeStatus::SuspendedEV { .. }
+                    | ChargeStatus::SuspendedEVSE { .. } => {
+                        debug::native::warn!(
+                            "Request of the session running on connector {} was dropped: stopping it",
+                            connector
+                        );
+                        if charger_api::stop_charge(connector).is_err() {
+                            debug::native::error!(
+                                "Cannot stop the charge session on connector {}",
+                                connector
+                            );
+                        }
+                        // Until the charger reports the end of the session
+                        waiting.push((connector, session_id));
+                    }
+                    _ => {}
+                }
+            }
+            storage.set(&waiting);
+        }
+
+        /// Stop the sessions still running on `connectors`, which were closed on-chain
+        fn stop_closed_sessions(connectors: &[ConnectorId]) {
+            for connector in connectors {
+                match charger_api::get_current_charge_status(*connector) {
+                    ChargeStatus::Active { .. }
+                    | ChargeStatus::SuspendedEV { .. }
+                    | ChargeStatus::SuspendedEVSE { .. } => {
+                        debug::native::warn!(
+                            "Session running on connector {} closed on-chain: stopping it",
+                            connector
+                        );
+                        if charger_api::stop_charge(*connector).is_err() {
+                            debug::native::error!(
+                                "Cannot stop the charge session on connector {}",
+                                connector
+                            );
+                        }
                     }
                     _ => {}
                 }
             }
         }
 
+        /// Drop the tokens and contracts presented to a suspended or removed charger, which
+        /// would otherwise request their sessions once the charger is resumed
+        fn discard_presented_authorizations() {
+            for presented in charger_api::take_presented_tokens() {
+                debug::native::warn!(
+                    "Token presented on connector {} of a suspended or removed charger: dropping it",
+                    presented.connector
+                );
+            }
+            for authorization in charger_api::take_contract_authorizations() {
+                debug::native::warn!(
+                    "Contract presented on connector {} of a suspended or removed charger: dropping it",
+                    authorization.connector
+                );
+            }
+        }
+
+        /// Key of the requests started by `charger` and waiting for their start_session
+        /// transaction, in the offchain local storage
+        fn started_requests_key(charger: &T::AccountId) -> Vec<u8> {
+            [b"charge-session::started-requests::".as_ref(), &charger.encode()].concat()
+        }
+
+        /// Send the end_session transaction of the session on `connector`, which is not sent
+        /// again before SUBMISSION_TIMEOUT blocks
+        fn send_end_session(
+            signer: &Signer<T, <T as Config>::AuthorityId, frame_system::offchain::ForAll>,
+            charger: &T::AccountId,
+            connector: ConnectorId,
+            session: &ChargingSession<T::AccountId, T::Moment, T::Hash>,
+            kwh: u64,
+            signed_data: Vec<Vec<u8>>,
+            block: T::BlockNumber,
+        ) {
+            Self::record_sent_transaction(
+                charger,
+                connector,
+                session.session_id,
+                SentTransaction::EndSession(block, kwh, signed_data.clone()),
+            );
+            if Self::send_signed_transaction(
+                signer,
+                Call::end_session(session.user_id.clone(), connector, kwh, signed_data),
+            )
+            .is_err()
+            {
+                debug::native::error!("Error occured while sending end_session transaction");
+            }
+        }
+
+        /// Send the report_fault transaction of the session on `connector`, which is not sent
+        /// again before SUBMISSION_TIMEOUT blocks
+        fn send_report_fault(
+            signer: &Signer<T, <T as Config>::AuthorityId, frame_system::offchain::ForAll>,
+            charger: &T::AccountId,
+            connector: ConnectorId,
+            session: &ChargingSession<T::AccountId, T::Moment, T::Hash>,
+            error_code: Vec<u8>,
+            kwh: u64,
+            block: T::BlockNumber,
+        ) {
+            Self::record_sent_transaction(
+                charger,
+                connector,
+                session.session_id,
+                SentTransaction::ReportFault(block, error_code.clone(), kwh),
+            );
+            if Self::send_signed_transaction(signer, Call::report_fault(connector, error_code, kwh))
+                .is_err()
+            {
+                debug::native::error!("Error occured while sending report_fault transaction");
+            }
+        }
+
+        /// Send the close_session transaction of the session on `connector`, which is not sent
+        /// again before SUBMISSION_TIMEOUT blocks
+        fn send_close_session(
+            signer: &Signer<T, <T as Config>::AuthorityId, frame_system::offchain::ForAll>,
+            charger: &T::AccountId,
+            connector: ConnectorId,
+            session: &ChargingSession<T::AccountId, T::Moment, T::Hash>,
+            block: T::BlockNumber,
+        ) {
+            Self::record_sent_transaction(
+                charger,
+                connector,
+                session.session_id,
+                SentTransaction::CloseSession(block),
+            );
+            if Self::send_signed_transaction(
+                signer,
+                Call::close_session(charger.clone(), connector),
+            )
+            .is_err()
+            {
+                debug::native::error!("Error occured while sending close_session transaction");
+            }
+        }
+
+        /// Transaction sent for the session `session_id` on `connector` of `charger`, unless
+        /// it was included
+        fn sent_transaction(
+            charger: &T::AccountId,
+            connector: ConnectorId,
+            session_id: T::Hash,
+        ) -> Option<SentTransaction<T::BlockNumber>> {
+            StorageValueRef::persistent(&Self::sent_transaction_key(charger, connector))
+                .get::<(T::Hash, SentTransaction<T::BlockNumber>)>()
+                .flatten()
+                .filter(|(sent_for, _)| *sent_for == session_id)
+                .map(|(_, sent)| sent)
+        }
+
+        /// Remember the last transaction sent for the session `session_id` on `connector`
+        fn record_sent_transaction(
+            charger: &T::AccountId,
+            connector: ConnectorId,
+            session_id: T::Hash,
+            sent: SentTransaction<T::BlockNumber>,
+        ) {
+            StorageValueRef::persistent(&Self::sent_transaction_key(charger, connector))
+                .set(&(session_id, sent));
+        }
+
+        fn clear_sent_transaction(charger: &T::AccountId, connector: ConnectorId) {
+            StorageValueRef::persistent(&Self::sent_transaction_key(charger, connector)).clear();
+        }
+
+        /// Whether a transaction sent at block `sent_at` may be sent again at block `block`
+        fn is_submission_timed_out(sent_at: T::BlockNumber, block: T::BlockNumber) -> bool {
+            block.saturating_sub(sent_at) >= T::BlockNumber::from(SUBMISSION_TIMEOUT)
+        }
+
+        /// Key of the last transaction sent for the session on `connector` of `charger`, in
+        /// the offchain local storage
+        fn sent_transaction_key(charger: &T::AccountId, connector: ConnectorId) -> Vec<u8> {
+            [
+                b"charge-session::sent-transaction::".as_ref(),
+                &charger.encode(),
+                &connector.encode(),
+            ]
+            .concat()
+        }
+
+        /// Key of the lock taken by an offchain worker processing `connector` of `charger`
+        fn connector_lock_key(charger: &T::AccountId, connector: ConnectorId) -> Vec<u8> {
+            [
+                b"charge-session::connector-lock::".as_ref(),
+                &charger.encode(),
+                &connector.encode(),
+            ]
+            .concat()
+        }
+
+        /// Limit the power of `charger` to its share of the power of its site, which is split
+        /// between the chargers of the site in proportion of their active sessions. The share
+        /// is only pushed to the charger when it changes.
+        fn balance_site_power(
+            signer: &Signer<T, <T as Config>::AuthorityId, frame_system::offchain::ForAll>,
+            charger: &T::AccountId,
+            site_id: SiteId,
+            now: T::Moment,
+        ) {
+            let site = match Sites::<T>::get(site_id) {
+                Some(site) => site,
+                None => return,
+            };
+            let chargers = SiteChargers::<T>::iter_prefix(site_id)
+                .map(|(member, ())| {
+                    let sessions = ActiveSessions::<T>::iter_prefix(&member).count() as u32;
+                    let limit = Self::charger_power_limit(&member, now).map(|limit| limit.watts);
+                    (member, sessions, limit)
+                })
+                .collect();
+            // A charger without session keeps its last share until its next session
+            let share = match balancing::split_power(site.max_power_w, chargers)
+                .into_iter()
+                .find(|(member, _)| member == charger)
+            {
+                Some((_, share)) => share,
+                None => return,
+            };
+
+            let key = Self::site_share_key(charger);
+            let pushed = StorageValueRef::persistent(&key);
+            if pushed.get::<u32>() != Some(Some(share)) {
+                if charger_api::set_power_limit(0, share, None).is_err() {
+                    // The share is pushed again at the next block
+                    debug::native::warn!(
+                        "Charger cannot limit its power to its share of site {}",
+                        site_id
+                    );
+                    return;
+                }
+                debug::native::info!(
+                    "Power of the charger limited to {} W, its share of site {}",
+                    share,
+                    site_id
+                );
+                pushed.set(&share);
+            }
+
+            // The share is within the limit of the charger, which has then taken effect
+            match Self::charger_power_limit(charger, now) {
+                Some(limit) if !limit.applied => {
+                    if Self::send_signed_transaction(
+                        signer,
+                        Call::power_limit_applied(0, limit.watts, limit.valid_until),
+                    )
+                    .is_err()
+                    {
+                        debug::native::error!(
+                            "Error occured while sending power_limit_applied transaction"
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        /// Key of the last share of its site pushed to `charger`, in the offchain local storage
+        fn site_share_key(charger: &T::AccountId) -> Vec<u8> {
+            [b"charge-session::site-share::".as_ref(), &charger.encode()].concat()
+        }
+
+        /// Power limit of the whole charger, unless it has lapsed
+        fn charger_power_limit(
+            charger: &T::AccountId,
+            now: T::Moment,
+        ) -> Option<PowerLimit<T::AccountId, T::Moment>> {
+            PowerLimits::<T>::get(charger, 0).filter(|limit| {
+                limit
+                    .valid_until
+                    .map_or(true, |valid_until| valid_until > now)
+            })
+        }
+
+        /// A checkpoint is due when `CheckpointInterval` has elapsed since the last one,
+        /// or since the beginning of the session
+        fn is_checkpoint_due(session: &ChargingSession<T::AccountId, T::Moment, T::Hash>) -> bool {
+            let last = session
+                .last_checkpoint
+                .as_ref()
+                .map(|checkpoint| checkpoint.recorded_at)
+                .unwrap_or(session.started_at);
+            <timestamp::Module<T>>::get() >= last + T::CheckpointInterval::get()
+        }
+
+        /// Whether a limit agreed by the user of `session` is reached, `meter` being the last
+        /// reading of the charger
+        fn is_limit_reached(
+            session: &ChargingSession<T::AccountId, T::Moment, T::Hash>,
+            meter: Option<&MeterSample>,
+        ) -> bool {
+            let limits = &session.limits;
+            let elapsed = <timestamp::Module<T>>::get().saturating_sub(session.started_at);
+            if limits.duration.map_or(false, |duration| elapsed >= duration) {
+                return true;
+            }
+
+            let last_checkpoint = session.last_checkpoint.as_ref();
+            let energy_wh = match meter
+                .map(|meter| meter.energy_wh)
+                .or_else(|| last_checkpoint.map(|checkpoint| checkpoint.energy_wh))
+            {
+                Some(energy_wh) => energy_wh,
+                None => return false,
+            };
+            if limits.energy_wh.map_or(false, |limit| energy_wh >= limit) {
+                return true;
+            }
+            // The amount billed by process_payment, for the whole kWh reported by end_session
+            let amount = <pallet_session_payment::Module<T>>::price_of((energy_wh / 1000).into());
+            limits.amount.map_or(false, |limit| amount >= limit)
+        }
+
+        /// Ended sessions of `user`, the most recent first, by pages of `page_size` sessions
+        pub fn sessions_by_user(
+            user: &T::AccountId,
+            page: u32,
+            page_size: u32,
+        ) -> Vec<SessionRecord<T::AccountId, T::Moment, T::Hash>> {
+            Self::history_page(UserSessionCount::<T>::get(user), page, page_size, |index| {
+                UserSessions::<T>::get(user, index)
+            })
+        }
+
+        /// Ended sessions of `charger`, the most recent first, by pages of `page_size` sessions
+        pub fn sessions_by_charger(
+            charger: &T::AccountId,
+            page: u32,
+            page_size: u32,
+        ) -> Vec<SessionRecord<T::AccountId, T::Moment, T::Hash>> {
+            Self::history_page(ChargerSessionCount::<T>::get(charger), page, page_size, |index| {
+                ChargerSessions::<T>::get(charger, index)
+            })
+        }
+
+        /// Page of a history of `count` sessions, `session_at` giving the session id at an index
+        fn history_page(
+            count: u32,
+            page: u32,
+            page_size: u32,
+            session_at: impl Fn(u32) -> Option<T::Hash>,
+        ) -> Vec<SessionRecord<T::AccountId, T::Moment, T::Hash>> {
+            let page_size = page_size.min(MAX_HISTORY_PAGE_SIZE);
+            let skipped = page.saturating_mul(page_size);
+            (0..count.saturating_sub(skipped))
+                .rev()
+                .take(page_size as usize)
+                .filter_map(session_at)
+                .filter_map(|session_id| SessionHistory::<T>::get(session_id))
+                .map(|mut record| {
+                    // The payment is validated after the end of the session
+                    let completed = <pallet_session_payment::Module<T>>::completed_payments(
+                        record.session_id,
+                    )
+                    .is_some();
+                    if record.payment_status == PaymentStatus::Pending && completed {
+                        record.payment_status = PaymentStatus::Completed;
+                    }
+                    record
+                })
+                .collect()
+        }
+
+        /// Admins of the chargers are the owners of the charger organization
+        pub fn is_admin(who: &T::AccountId) -> bool {
+            <pallet_did::Module<T>>::is_owner(&<ChargerOrganization<T>>::get(), who).is_ok()
+        }
+
         pub fn is_charger(who: &T::AccountId) -> bool {
             return <pallet_registrar::Module<T>>::members_of(<ChargerOrganization<T>>::get())
                 .contains(who);
         }
 
+        pub fn is_suspended(charger: &T::AccountId) -> bool {
+            SuspendedChargers::<T>::contains_key(charger)
+        }
+
         fn send_signed_transaction(
             signer: &Signer<T, <T as Config>::AuthorityId, frame_system::offchain::ForAll>,
             call: Call<T>,
@@ -461,12 +2721,22 @@ pub mod pallet {
         ///     - current block number
         ///     - AccountId of the user (charge requester)
         ///     - AccountId of the charger
-        /// since there is only one possible session for a charger and user at any given time, this identifier is guaranteed to be unique
-        fn generate_charge_id(user: &T::AccountId, charger: &T::AccountId) -> T::Hash {
+        ///     - connector of the charger
+        ///     - position of the request in `RequestQueue`
+        /// the position is unique to each request, so a request cancelled or rejected and then
+        /// sent again in the same block gets a new identifier
+        fn generate_charge_id(
+            user: &T::AccountId,
+            charger: &T::AccountId,
+            connector: ConnectorId,
+        ) -> T::Hash {
             let block_number = <frame_system::Pallet<T>>::block_number();
+            let (_, position) = RequestQueueBounds::<T>::get();
             let mut key = T::AccountId::encode(user);
             key.append(&mut T::AccountId::encode(charger));
+            key.append(&mut connector.encode());
             key.append(&mut T::BlockNumber::encode(&block_number));
+            key.append(&mut position.encode());
             let hash = T::Hashing::hash(&key);
             return hash;
         }
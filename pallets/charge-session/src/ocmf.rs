@@ -0,0 +1,163 @@
+//! Verification of the meter readings signed by the meter itself, in the Open Charge Metering
+//! Format (OCMF) required by the German calibration law (Eichrecht).
+//!
+//! A dataset is `OCMF|{payload}|{signature}`: the payload lists the readings of the meter,
+//! and the signature is computed by the meter over the payload with its own key. Only the
+//! default algorithm of OCMF, ECDSA-secp256r1-SHA256 with a hex encoded signature, is supported.
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use sp_std::{str, vec::Vec};
+
+const SIGNATURE_ALGORITHM: &str = "ECDSA-secp256r1-SHA256";
+
+/// Energy registers of the meter at the beginning and at the end of a session, in Wh
+#[derive(Debug, PartialEq, Eq)]
+pub struct SessionReadings {
+    pub start_wh: u64,
+    pub end_wh: u64,
+}
+
+impl SessionReadings {
+    pub fn energy_wh(&self) -> u64 {
+        self.end_wh - self.start_wh
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum OcmfError {
+    /// A dataset is not `OCMF|{payload}|{signature}`, or its payload cannot be read
+    Malformed,
+    /// A dataset is not signed with ECDSA-secp256r1-SHA256
+    UnsupportedAlgorithm,
+    /// A signature does not match its payload and the key of the meter
+    BadSignature,
+    /// The start or the end reading of the session is missing, or the meter reported an error
+    MissingReading,
+}
+
+#[derive(Deserialize)]
+struct Payload<'a> {
+    #[serde(rename = "RD", borrow, default)]
+    readings: Vec<Reading<'a>>,
+}
+
+#[derive(Deserialize)]
+struct Reading<'a> {
+    /// B for the beginning of the transaction, E for its end (C, X, L... otherwise)
+    #[serde(rename = "TX", default)]
+    kind: Option<&'a str>,
+    /// Decimal value, kept as written by the meter
+    #[serde(rename = "RV", borrow)]
+    value: &'a RawValue,
+    #[serde(rename = "RU")]
+    unit: &'a str,
+    /// G when the meter is in a good state
+    #[serde(rename = "ST", default)]
+    status: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct SignatureData<'a> {
+    #[serde(rename = "SA", default)]
+    algorithm: Option<&'a str>,
+    #[serde(rename = "SE", default)]
+    encoding: Option<&'a str>,
+    #[serde(rename = "SD")]
+    data: &'a str,
+}
+
+/// Check that `public_key` is a SEC1 encoded secp256r1 key
+pub fn is_valid_public_key(public_key: &[u8]) -> bool {
+    VerifyingKey::from_sec1_bytes(public_key).is_ok()
+}
+
+/// Verify the signature of each dataset with `public_key`, and return the start and end
+/// readings of the session they carry (in one dataset, or one dataset each)
+pub fn verify_session(
+    public_key: &[u8],
+    datasets: &[Vec<u8>],
+) -> Result<SessionReadings, OcmfError> {
+    let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|_| OcmfError::BadSignature)?;
+    let mut start_wh = None;
+    let mut end_wh = None;
+    for dataset in datasets {
+        let payload = verified_payload(&key, dataset)?;
+        let payload: Payload =
+            serde_json::from_str(payload).map_err(|_| OcmfError::Malformed)?;
+        for reading in payload.readings {
+            if reading.status.unwrap_or("G") != "G" {
+                return Err(OcmfError::MissingReading);
+            }
+            let wh = match reading.kind {
+                Some("B") | Some("E") => {
+                    to_wh(reading.value.get(), reading.unit).ok_or(OcmfError::Malformed)?
+                }
+                _ => continue,
+            };
+            if reading.kind == Some("B") {
+                start_wh = start_wh.or(Some(wh));
+            } else {
+                end_wh = Some(wh);
+            }
+        }
+    }
+    match (start_wh, end_wh) {
+        (Some(start_wh), Some(end_wh)) if end_wh >= start_wh => {
+            Ok(SessionReadings { start_wh, end_wh })
+        }
+        _ => Err(OcmfError::MissingReading),
+    }
+}
+
+/// Payload of `dataset`, once its signature is verified
+fn verified_payload<'a>(key: &VerifyingKey, dataset: &'a [u8]) -> Result<&'a str, OcmfError> {
+    let dataset = str::from_utf8(dataset).map_err(|_| OcmfError::Malformed)?;
+    let sections = dataset
+        .strip_prefix("OCMF|")
+        .ok_or(OcmfError::Malformed)?;
+    // The signature section is the last one: the payload may contain a separator
+    let separator = sections.rfind('|').ok_or(OcmfError::Malformed)?;
+    let (payload, signature) = (&sections[..separator], &sections[separator + 1..]);
+
+    let signature: SignatureData =
+        serde_json::from_str(signature).map_err(|_| OcmfError::Malformed)?;
+    if signature.algorithm.unwrap_or(SIGNATURE_ALGORITHM) != SIGNATURE_ALGORITHM
+        || signature.encoding.unwrap_or("hex") != "hex"
+    {
+        return Err(OcmfError::UnsupportedAlgorithm);
+    }
+    let signature = hex::decode(signature.data)
+        .ok()
+        .and_then(|der| Signature::from_der(&der).ok())
+        .ok_or(OcmfError::Malformed)?;
+    key.verify(payload.as_bytes(), &signature)
+        .map_err(|_| OcmfError::BadSignature)?;
+    Ok(payload)
+}
+
+/// Reading `value` expressed in `unit` (kWh or Wh), in Wh. Decimals are parsed as written
+/// rather than as floats, the decimals below 1 Wh being dropped
+fn to_wh(value: &str, unit: &str) -> Option<u64> {
+    let decimals = match unit {
+        "kWh" => 3,
+        "Wh" => 0,
+        _ => return None,
+    };
+    let (integer, fraction) = match value.find('.') {
+        Some(point) => (&value[..point], &value[point + 1..]),
+        None => (value, ""),
+    };
+    if integer.is_empty()
+        || !integer.bytes().all(|digit| digit.is_ascii_digit())
+        || !fraction.bytes().all(|digit| digit.is_ascii_digit())
+    {
+        return None;
+    }
+    let mut wh: u64 = integer.parse().ok()?;
+    for position in 0..decimals {
+        let digit = fraction.as_bytes().get(position).map_or(0, |digit| digit - b'0');
+        wh = wh.checked_mul(10)?.checked_add(digit as u64)?;
+    }
+    Some(wh)
+}
@@ -1,16 +1,48 @@
 use crate as pallet_charge_session;
+use crate::{
+    balancing::split_power, ChargerMetadata, ConnectorMetadata, ConnectorType, CurrentType,
+    GeoCoordinates, OpeningPeriod, PaymentStatus, PowerLimit, RegisteredContract, RequestQueue,
+    RequestQueueBounds, SessionLimits, Site, MAX_EXPIRY_VISITS, SUBMISSION_TIMEOUT,
+};
 
-use frame_support::{assert_err, assert_ok, traits::GenesisBuild};
+use charger_service::{
+    api::{ChargeStatus, ChargerApi},
+    mock::{MockCharger, MockFault, ScriptedSession},
+    runtime::{
+        externalities::{ChargerExt, ChargerExternalities},
+        offchain::{ChargerError, MeterSample},
+    },
+};
+use codec::Decode;
+use frame_support::{
+    assert_err, assert_ok,
+    traits::{GenesisBuild, OffchainWorker, OnInitialize},
+};
 use hex_literal::hex;
+use p256::ecdsa::{signature::Signer, SigningKey};
 use pallet_did::did::Did;
+use parking_lot::RwLock;
 use sp_core::{
+    offchain::{
+        testing::{PoolState, TestOffchainExt, TestTransactionPoolExt},
+        OffchainExt, TransactionPoolExt,
+    },
     sr25519::{Public, Signature},
     H256,
 };
 use sp_io::TestExternalities;
+use sp_keystore::{testing::KeyStore, KeystoreExt, SyncCryptoStore};
 use sp_runtime::{
     testing::{Header, TestXt},
-    traits::{BlakeTwo256, Extrinsic as ExtrinsicT, IdentifyAccount, IdentityLookup, Verify},
+    traits::{
+        BlakeTwo256, Dispatchable, Extrinsic as ExtrinsicT, Hash, IdentifyAccount, IdentityLookup,
+        Verify,
+    },
+    RuntimeAppPublic,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
@@ -95,9 +127,16 @@ impl pallet_session_payment::Config for Test {
     type AuthorityId = pallet_session_payment::crypto::PaymentValidatorId;
 }
 
+frame_support::parameter_types! {
+  pub const CheckpointInterval: u64 = 60_000;
+  pub const RequestTimeout: u64 = 300_000;
+}
+
 impl pallet_charge_session::Config for Test {
     type Event = Event;
     type AuthorityId = pallet_charge_session::crypto::ChargerId;
+    type CheckpointInterval = CheckpointInterval;
+    type RequestTimeout = RequestTimeout;
 }
 
 impl pallet_tariff_manager::Config for Test {
@@ -167,12 +206,44 @@ pub fn register_charger(charger: Public, location: Vec<u8>) {
             b"chargers".to_vec()
         ));
     }
-    assert_ok!(
-        ChargeSession::add_new_charger(
-            Origin::signed(admin),
-            charger,
-        )
-    );
+    assert_ok!(ChargeSession::add_new_charger(
+        Origin::signed(admin),
+        charger,
+        charger_metadata()
+    ));
+}
+
+/// Metadata of a charger with an AC and a DC connector, open on weekdays
+pub fn charger_metadata() -> ChargerMetadata {
+    ChargerMetadata {
+        coordinates: GeoCoordinates {
+            latitude: 48_856_614,
+            longitude: 2_352_222,
+        },
+        address: b"1 Place de l'Hotel de Ville, 75004 Paris".to_vec(),
+        connectors: vec![
+            ConnectorMetadata {
+                connector: 1,
+                connector_type: ConnectorType::Type2,
+                current: CurrentType::Ac,
+                max_power_w: 22_000,
+            },
+            ConnectorMetadata {
+                connector: 2,
+                connector_type: ConnectorType::Ccs,
+                current: CurrentType::Dc,
+                max_power_w: 50_000,
+            },
+        ],
+        opening_hours: (0..5)
+            .map(|weekday| OpeningPeriod {
+                weekday,
+                opens: 8 * 60,
+                closes: 20 * 60,
+            })
+            .collect(),
+        operator: b"Delmonicos".to_vec(),
+    }
 }
 
 pub fn add_consent(user: Public) {
@@ -186,6 +257,137 @@ pub fn add_consent(user: Public) {
     }
 }
 
+fn meter_signing_key() -> SigningKey {
+    SigningKey::from_bytes(&[7u8; 32]).unwrap()
+}
+
+/// Public key of the meter signing the datasets of `ocmf_dataset`
+pub fn meter_public_key() -> Vec<u8> {
+    meter_signing_key()
+        .verifying_key()
+        .to_encoded_point(true)
+        .as_bytes()
+        .to_vec()
+}
+
+/// OCMF dataset signed by the meter, with the start and end readings of a session in kWh
+pub fn ocmf_dataset(start_kwh: &str, end_kwh: &str) -> Vec<u8> {
+    let payload = format!(
+        r#"{{"FV":"1.0","MS":"METER-1","RD":[{{"TX":"B","RV":{},"RU":"kWh","ST":"G"}},{{"TX":"E","RV":{},"RU":"kWh","ST":"G"}}]}}"#,
+        start_kwh, end_kwh
+    );
+    let signature: p256::ecdsa::Signature = meter_signing_key().sign(payload.as_bytes());
+    format!(
+        r#"OCMF|{}|{{"SA":"ECDSA-secp256r1-SHA256","SD":"{}"}}"#,
+        payload,
+        hex::encode(signature.to_der().as_bytes())
+    )
+    .into_bytes()
+}
+
+/// Time at which the certificates of `ContractPki` are valid (2022-01-01), in milliseconds
+pub const CONTRACT_TIME: u64 = 1_640_995_200_000;
+
+/// PKI issuing the contract certificates: a root and a sub-CA of the mobility operator
+struct ContractPki {
+    root: rcgen::Certificate,
+    sub_ca: rcgen::Certificate,
+}
+
+impl ContractPki {
+    fn new() -> ContractPki {
+        ContractPki {
+            root: certificate("V2G Root", true, 2060),
+            sub_ca: certificate("MO Sub-CA", true, 2030),
+        }
+    }
+
+    /// DER encoded root certificate
+    fn root(&self) -> Vec<u8> {
+        self.root.serialize_der().unwrap()
+    }
+
+    /// Chain of a new contract certificate of `emaid`, expiring at the beginning of `expiry_year`
+    fn contract_chain(&self, emaid: &str, expiry_year: i32) -> Vec<Vec<u8>> {
+        let contract = certificate(emaid, false, expiry_year);
+        vec![
+            contract.serialize_der_with_signer(&self.sub_ca).unwrap(),
+            self.sub_ca.serialize_der_with_signer(&self.root).unwrap(),
+        ]
+    }
+}
+
+fn certificate(common_name: &str, is_ca: bool, expiry_year: i32) -> rcgen::Certificate {
+    let mut params = rcgen::CertificateParams::new(vec![]);
+    params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, common_name);
+    if is_ca {
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    }
+    params.not_before = rcgen::date_time_ymd(2021, 1, 1);
+    params.not_after = rcgen::date_time_ymd(expiry_year, 1, 1);
+    rcgen::Certificate::from_params(params).unwrap()
+}
+
+/// Test externalities running the offchain worker against `charger_api`,
+/// with a charger key (returned) in the keystore
+pub fn new_offchain_test_ext(
+    charger_api: MockCharger,
+) -> (
+    TestExternalities,
+    Public,
+    Arc<Mutex<MockCharger>>,
+    Arc<RwLock<PoolState>>,
+) {
+    new_charger_node("//Charger", charger_api)
+}
+
+/// Test externalities of the node of a charger, whose key is derived from `seed`
+pub fn new_charger_node(
+    seed: &str,
+    charger_api: MockCharger,
+) -> (
+    TestExternalities,
+    Public,
+    Arc<Mutex<MockCharger>>,
+    Arc<RwLock<PoolState>>,
+) {
+    let keystore = KeyStore::new();
+    let charger = SyncCryptoStore::sr25519_generate_new(
+        &keystore,
+        pallet_charge_session::crypto::Public::ID,
+        Some(seed),
+    )
+    .unwrap();
+    let (offchain, _) = TestOffchainExt::new();
+    let (pool, pool_state) = TestTransactionPoolExt::new();
+    let charger_api = Arc::new(Mutex::new(charger_api));
+
+    let mut ext = new_test_ext();
+    ext.register_extension(OffchainExt::new(offchain));
+    ext.register_extension(TransactionPoolExt::new(pool));
+    ext.register_extension(KeystoreExt(Arc::new(keystore)));
+    ext.register_extension(ChargerExt::new(ChargerExternalities::new(
+        charger_api.clone(),
+    )));
+    (ext, charger, charger_api, pool_state)
+}
+
+/// Run the offchain worker of the current block, and return the calls of the transactions it
+/// sent
+pub fn run_offchain_worker(pool_state: &Arc<RwLock<PoolState>>) -> Vec<Call> {
+    ChargeSession::offchain_worker(System::block_number());
+    pool_state
+        .write()
+        .transactions
+        .drain(..)
+        .map(|tx| Extrinsic::decode(&mut &*tx).unwrap().call)
+        .collect()
+}
+
 #[test]
 fn should_create_new_request() {
     new_test_ext().execute_with(|| {
@@ -200,12 +402,17 @@ fn should_create_new_request() {
 
         Timestamp::set_timestamp(999);
 
-        let current_request = ChargeSession::user_requests(user);
+        let current_request = ChargeSession::user_requests(user, 1);
         assert_eq!(current_request, None);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user), charger));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
 
-        let current_request = ChargeSession::user_requests(charger).unwrap();
+        let current_request = ChargeSession::user_requests(charger, 1).unwrap();
         assert_eq!(current_request.user_id, user);
         assert_eq!(current_request.created_at, 999);
     });
@@ -224,13 +431,22 @@ fn should_start_a_new_session() {
         add_consent(user);
 
         Timestamp::set_timestamp(999);
-        assert!(ChargeSession::active_sessions(charger).is_none());
-        assert_ok!(ChargeSession::new_request(Origin::signed(user), charger));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
 
-        assert_ok!(ChargeSession::start_session(Origin::signed(charger), user));
-        assert!(ChargeSession::user_requests(charger).is_none());
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert!(ChargeSession::user_requests(charger, 1).is_none());
 
-        let session = ChargeSession::active_sessions(charger).unwrap();
+        let session = ChargeSession::active_sessions(charger, 1).unwrap();
 
         assert_eq!(session.user_id, user);
         assert_eq!(session.started_at, 999);
@@ -250,10 +466,10 @@ fn should_not_start_unrequested_session() {
         add_consent(user);
 
         assert_err!(
-            ChargeSession::start_session(Origin::signed(charger), user),
+            ChargeSession::start_session(Origin::signed(charger), user, 1),
             pallet_charge_session::Error::<Test>::NoChargingRequest
         );
-        assert!(ChargeSession::active_sessions(charger).is_none());
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
     });
 }
 
@@ -269,10 +485,19 @@ fn should_not_start_twice() {
         register_charger(charger, "[1,2]".as_bytes().to_vec());
         add_consent(user);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user), charger));
-        assert_ok!(ChargeSession::start_session(Origin::signed(charger), user));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
         assert_err!(
-            ChargeSession::start_session(Origin::signed(charger), user),
+            ChargeSession::start_session(Origin::signed(charger), user, 1),
             pallet_charge_session::Error::<Test>::NoChargingRequest
         );
     });
@@ -294,11 +519,75 @@ fn should_not_take_request_from_another_charger() {
         register_charger(charger_2, "[1,2]".as_bytes().to_vec());
         add_consent(user);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user), charger_2));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger_2,
+            1,
+            SessionLimits::default()
+        ));
+        assert_err!(
+            ChargeSession::start_session(Origin::signed(charger_1), user, 1),
+            pallet_charge_session::Error::<Test>::NoChargingRequest
+        );
+    });
+}
+
+#[test]
+fn should_reject_a_request() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "44ce5dedab4604c5df7d46ebd146ff5773bfcd975f7203e4cbac45149593a865"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        System::set_block_number(1);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let session_id = ChargeSession::user_requests(charger, 1).unwrap().session_id;
+        assert_ok!(ChargeSession::reject_request(
+            Origin::signed(charger),
+            user,
+            1,
+            ChargerError::Busy
+        ));
+        assert!(ChargeSession::user_requests(charger, 1).is_none());
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert!(ChargeConsent::user_consent(session_id).is_none());
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_charge_session(
+                pallet_charge_session::Event::SessionRejected(
+                    user,
+                    charger,
+                    1,
+                    session_id,
+                    ChargerError::Busy
+                )
+            ))
+        );
+
         assert_err!(
-            ChargeSession::start_session(Origin::signed(charger_1), user),
+            ChargeSession::reject_request(Origin::signed(charger), user, 1, ChargerError::Busy),
             pallet_charge_session::Error::<Test>::NoChargingRequest
         );
+
+        // The request sent again in the same block is another session
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let request = ChargeSession::user_requests(charger, 1).unwrap();
+        assert_ne!(request.session_id, session_id);
     });
 }
 
@@ -314,15 +603,26 @@ fn should_end_an_active_session() {
         register_charger(charger, "[1,2]".as_bytes().to_vec());
         add_consent(user);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user), charger));
-        assert_ok!(ChargeSession::start_session(Origin::signed(charger), user));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
         assert_ok!(ChargeSession::end_session(
             Origin::signed(charger),
             user,
-            99
+            1,
+            99,
+            vec![]
         ));
 
-        assert!(ChargeSession::active_sessions(charger).is_none());
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
     });
 }
 
@@ -342,17 +642,23 @@ fn should_not_end_a_session_from_another_charger() {
         register_charger(charger_2, "[1,2]".as_bytes().to_vec());
         add_consent(user);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user), charger_1));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger_1,
+            1,
+            SessionLimits::default()
+        ));
         assert_ok!(ChargeSession::start_session(
             Origin::signed(charger_1),
-            user
+            user,
+            1
         ));
         assert_err!(
-            ChargeSession::end_session(Origin::signed(charger_2), user, 99),
+            ChargeSession::end_session(Origin::signed(charger_2), user, 1, 99, vec![]),
             pallet_charge_session::Error::<Test>::NoChargingSession
         );
 
-        assert!(ChargeSession::active_sessions(charger_1).is_some());
+        assert!(ChargeSession::active_sessions(charger_1, 1).is_some());
     });
 }
 
@@ -372,14 +678,141 @@ fn should_reject_new_request_if_request_already_exists() {
         add_consent(user_1);
         add_consent(user_2);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user_1), charger));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_1),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
         assert_err!(
-            ChargeSession::new_request(Origin::signed(user_2), charger),
+            ChargeSession::new_request(
+                Origin::signed(user_2),
+                charger,
+                1,
+                SessionLimits::default()
+            ),
             pallet_charge_session::Error::<Test>::ChargerIsBusy
         );
     });
 }
 
+#[test]
+fn should_expire_requests_not_started() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "44ce5dedab4604c5df7d46ebd146ff5773bfcd975f7203e4cbac45149593a865"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        System::set_block_number(1);
+        Timestamp::set_timestamp(1_000);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let session_id = ChargeSession::user_requests(charger, 1).unwrap().session_id;
+        Timestamp::set_timestamp(2_000);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            2,
+            SessionLimits::default()
+        ));
+
+        // The requests live for RequestTimeout
+        Timestamp::set_timestamp(1_000 + RequestTimeout::get());
+        ChargeSession::on_initialize(2);
+        assert!(ChargeSession::user_requests(charger, 1).is_some());
+
+        Timestamp::set_timestamp(1_001 + RequestTimeout::get());
+        ChargeSession::on_initialize(3);
+        assert!(ChargeSession::user_requests(charger, 1).is_none());
+        assert!(ChargeSession::user_requests(charger, 2).is_some());
+        assert!(ChargeConsent::user_consent(session_id).is_none());
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_charge_session(
+                pallet_charge_session::Event::RequestExpired(user, charger, 1, session_id)
+            ))
+        );
+
+        // The connector takes new requests again
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+    });
+}
+
+#[test]
+fn should_expire_requests_from_head_of_queue() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "44ce5dedab4604c5df7d46ebd146ff5773bfcd975f7203e4cbac45149593a865"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        System::set_block_number(1);
+        Timestamp::set_timestamp(1_000);
+
+        // The cancelled requests stay queued before the pending one
+        let cancelled = MAX_EXPIRY_VISITS + 8;
+        for _ in 0..cancelled {
+            assert_ok!(ChargeSession::new_request(
+                Origin::signed(user),
+                charger,
+                1,
+                SessionLimits::default()
+            ));
+            assert_ok!(ChargeSession::cancel_request(
+                Origin::signed(user),
+                charger,
+                1
+            ));
+        }
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            2,
+            SessionLimits::default()
+        ));
+        assert_eq!(RequestQueueBounds::<Test>::get(), (0, cancelled + 1));
+
+        // Nothing expires before the timeout
+        ChargeSession::on_initialize(2);
+        assert_eq!(RequestQueueBounds::<Test>::get(), (0, cancelled + 1));
+
+        // A block visits at most MAX_EXPIRY_VISITS requests of the queue
+        Timestamp::set_timestamp(1_001 + RequestTimeout::get());
+        ChargeSession::on_initialize(3);
+        assert_eq!(
+            RequestQueueBounds::<Test>::get(),
+            (MAX_EXPIRY_VISITS, cancelled + 1)
+        );
+        assert!(RequestQueue::<Test>::get(0).is_none());
+        assert!(ChargeSession::user_requests(charger, 2).is_some());
+
+        ChargeSession::on_initialize(4);
+        assert_eq!(
+            RequestQueueBounds::<Test>::get(),
+            (cancelled + 1, cancelled + 1)
+        );
+        assert!(RequestQueue::<Test>::get(cancelled).is_none());
+        assert!(ChargeSession::user_requests(charger, 2).is_none());
+    });
+}
+
 #[test]
 fn should_reject_new_request_if_charge_is_active() {
     new_test_ext().execute_with(|| {
@@ -396,13 +829,24 @@ fn should_reject_new_request_if_charge_is_active() {
         add_consent(user_1);
         add_consent(user_2);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user_1), charger));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_1),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
         assert_ok!(ChargeSession::start_session(
             Origin::signed(charger),
-            user_1
+            user_1,
+            1
         ));
         assert_err!(
-            ChargeSession::new_request(Origin::signed(user_2), charger),
+            ChargeSession::new_request(
+                Origin::signed(user_2),
+                charger,
+                1,
+                SessionLimits::default()
+            ),
             pallet_charge_session::Error::<Test>::ChargerIsBusy
         );
     });
@@ -424,156 +868,2961 @@ fn should_chain_two_sessions() {
         add_consent(user_1);
         add_consent(user_2);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user_1), charger));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_1),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
         assert_ok!(ChargeSession::start_session(
             Origin::signed(charger),
-            user_1
+            user_1,
+            1
         ));
         assert_ok!(ChargeSession::end_session(
             Origin::signed(charger),
             user_1,
-            99
+            1,
+            99,
+            vec![]
         ));
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user_2), charger));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_2),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
         assert_ok!(ChargeSession::start_session(
             Origin::signed(charger),
-            user_2
+            user_2,
+            1
         ));
         assert_ok!(ChargeSession::end_session(
             Origin::signed(charger),
             user_2,
-            99
+            1,
+            99,
+            vec![]
         ));
     });
 }
 
 #[test]
-fn should_reject_new_request_for_unregistered_charger() {
+fn should_record_session_history() {
     new_test_ext().execute_with(|| {
-        let user = Public::from_raw(hex!(
+        let user_1 = Public::from_raw(hex!(
             "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
         ));
+        let user_2 = Public::from_raw(hex!(
+            "44ce5dedab4604c5df7d46ebd146ff5773bfcd975f7203e4cbac45149593a865"
+        ));
         let charger = Public::from_raw(hex!(
-            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
         ));
-        add_consent(user);
-        assert_err!(
-            ChargeSession::new_request(Origin::signed(user), charger),
-            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user_1);
+        add_consent(user_2);
+        let run_session = |user: Public, connector: u32, kwh: u64, ended_at: u64| {
+            assert_ok!(ChargeSession::new_request(
+                Origin::signed(user),
+                charger,
+                connector,
+                SessionLimits::default()
+            ));
+            assert_ok!(ChargeSession::start_session(
+                Origin::signed(charger),
+                user,
+                connector
+            ));
+            Timestamp::set_timestamp(ended_at);
+            assert_ok!(ChargeSession::end_session(
+                Origin::signed(charger),
+                user,
+                connector,
+                kwh,
+                vec![]
+            ));
+        };
+        run_session(user_1, 1, 10, 1_000);
+        run_session(user_2, 2, 20, 2_000);
+        run_session(user_1, 2, 30, 3_000);
+        run_session(user_1, 1, 40, 4_000);
+
+        // The sessions of a user, the most recent first
+        let sessions = ChargeSession::sessions_by_user(&user_1, 0, 2);
+        assert_eq!(
+            sessions.iter().map(|record| record.kwh).collect::<Vec<_>>(),
+            vec![40, 30]
+        );
+        let record = &sessions[1];
+        assert_eq!(record.user_id, user_1);
+        assert_eq!(record.charger_id, charger);
+        assert_eq!(record.connector, 2);
+        assert_eq!((record.started_at, record.ended_at), (2_000, 3_000));
+        assert_eq!(record.amount, Some(30 * 15));
+        assert_eq!(record.payment_status, PaymentStatus::Pending);
+        assert_eq!(
+            ChargeSession::sessions_by_user(&user_1, 1, 2)
+                .iter()
+                .map(|record| record.kwh)
+                .collect::<Vec<_>>(),
+            vec![10]
+        );
+        assert!(ChargeSession::sessions_by_user(&user_1, 2, 2).is_empty());
+
+        // The sessions of a charger
+        assert_eq!(
+            ChargeSession::sessions_by_charger(&charger, 0, 10)
+                .iter()
+                .map(|record| record.kwh)
+                .collect::<Vec<_>>(),
+            vec![40, 30, 20, 10]
+        );
+
+        // The payment status follows the validation of the payment
+        let validator = Public::from_raw(hex!(
+            "54ac0c914b2d1552d4749276b0eb547b881486a8c224d3cf8207e9d2f9a91b79"
+        ));
+        assert_ok!(Registrar::create_organization(
+            Origin::signed(Public::default()),
+            b"payment_validators".to_vec()
+        ));
+        assert_ok!(Registrar::add_to_organization(
+            Origin::signed(Public::default()),
+            validator
+        ));
+        assert_ok!(SessionPayment::complete_payment(
+            Origin::signed(validator),
+            record.session_id
+        ));
+        assert_eq!(
+            ChargeSession::sessions_by_user(&user_1, 0, 2)[1].payment_status,
+            PaymentStatus::Completed
+        );
+        assert_eq!(
+            ChargeSession::session_history(record.session_id)
+                .unwrap()
+                .payment_status,
+            PaymentStatus::Pending
         );
     });
 }
 
 #[test]
-fn should_reject_start_session_for_unregistered_charger() {
+fn should_run_sessions_on_several_connectors() {
     new_test_ext().execute_with(|| {
-        let user = Public::from_raw(hex!(
+        let user_1 = Public::from_raw(hex!(
             "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
         ));
+        let user_2 = Public::from_raw(hex!(
+            "44ce5dedab4604c5df7d46ebd146ff5773bfcd975f7203e4cbac45149593a865"
+        ));
         let charger = Public::from_raw(hex!(
-            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
         ));
-        add_consent(user);
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user_1);
+        add_consent(user_2);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_1),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user_1,
+            1
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_2),
+            charger,
+            2,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user_2,
+            2
+        ));
+
+        let session_1 = ChargeSession::active_sessions(charger, 1).unwrap();
+        let session_2 = ChargeSession::active_sessions(charger, 2).unwrap();
+        assert_eq!(session_1.user_id, user_1);
+        assert_eq!(session_2.user_id, user_2);
+        assert_ne!(session_1.session_id, session_2.session_id);
+
         assert_err!(
-            ChargeSession::start_session(Origin::signed(charger), user),
-            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+            ChargeSession::end_session(Origin::signed(charger), user_1, 2, 99, vec![]),
+            pallet_charge_session::Error::<Test>::NoChargingSession
         );
+        assert_ok!(ChargeSession::end_session(
+            Origin::signed(charger),
+            user_1,
+            1,
+            99,
+            vec![]
+        ));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert!(ChargeSession::active_sessions(charger, 2).is_some());
     });
 }
 
 #[test]
-fn should_reject_end_session_for_unregistered_charger() {
+fn should_store_session_limits() {
     new_test_ext().execute_with(|| {
         let user = Public::from_raw(hex!(
             "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
         ));
         let charger = Public::from_raw(hex!(
-            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
         ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
         add_consent(user);
+
         assert_err!(
-            ChargeSession::end_session(Origin::signed(charger), user, 99),
-            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+            ChargeSession::new_request(
+                Origin::signed(user),
+                charger,
+                1,
+                SessionLimits {
+                    energy_wh: Some(0),
+                    ..Default::default()
+                }
+            ),
+            pallet_charge_session::Error::<Test>::InvalidSessionLimits
+        );
+        let limits = SessionLimits {
+            energy_wh: Some(30_000),
+            duration: Some(7_200_000),
+            amount: Some(2_000),
+        };
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            limits.clone()
+        ));
+        let request = ChargeSession::user_requests(charger, 1).unwrap();
+        assert_eq!(request.limits, limits);
+
+        // The limits apply to the session, whose payment is capped
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert_eq!(
+            ChargeSession::active_sessions(charger, 1).unwrap().limits,
+            limits
+        );
+        assert_eq!(
+            SessionPayment::payment_caps(request.session_id),
+            Some(2_000)
         );
     });
 }
 
 #[test]
-fn should_store_charge_consent() {
+fn should_cancel_a_request() {
     new_test_ext().execute_with(|| {
         let user = Public::from_raw(hex!(
             "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
         ));
+        let other_user = Public::from_raw(hex!(
+            "f0a0a685af36aa9f1f20ecb1a4559ba579fd6218c62bcf410f2026d2d66ece14"
+        ));
         let charger = Public::from_raw(hex!(
             "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
         ));
         register_charger(charger, "[1,2]".as_bytes().to_vec());
         add_consent(user);
+        System::set_block_number(1);
 
-        assert_ok!(ChargeSession::new_request(Origin::signed(user), charger));
-
-        let request = ChargeSession::user_requests(charger).expect("no user request");
-        let session_id = request.session_id;
-
-        let consent = ChargeConsent::user_consent(session_id).expect("no user consent");
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let session_id = ChargeSession::user_requests(charger, 1).unwrap().session_id;
 
-        assert_eq!(consent.charger_id, charger);
-        assert_eq!(consent.user_id, user);
+        // Only the user of the request can cancel it
+        assert_err!(
+            ChargeSession::cancel_request(Origin::signed(other_user), charger, 1),
+            pallet_charge_session::Error::<Test>::NoChargingRequest
+        );
+        assert_ok!(ChargeSession::cancel_request(
+            Origin::signed(user),
+            charger,
+            1
+        ));
+        assert!(ChargeSession::user_requests(charger, 1).is_none());
+        assert!(ChargeConsent::user_consent(session_id).is_none());
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_charge_session(
+                pallet_charge_session::Event::SessionCancelled(user, charger, 1, session_id)
+            ))
+        );
+        assert_err!(
+            ChargeSession::cancel_request(Origin::signed(user), charger, 1),
+            pallet_charge_session::Error::<Test>::NoChargingRequest
+        );
+
+        // A started request cannot be cancelled anymore: its session is stopped instead
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert_err!(
+            ChargeSession::cancel_request(Origin::signed(user), charger, 1),
+            pallet_charge_session::Error::<Test>::NoChargingRequest
+        );
     });
 }
 
 #[test]
-fn should_reject_new_request_without_consent() {
+fn should_stop_a_session_by_its_user() {
     new_test_ext().execute_with(|| {
         let user = Public::from_raw(hex!(
             "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
         ));
         let charger = Public::from_raw(hex!(
-            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
         ));
         register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert_ok!(ChargeSession::stop_session(
+            Origin::signed(user),
+            charger,
+            1
+        ));
+        assert!(
+            ChargeSession::active_sessions(charger, 1)
+                .unwrap()
+                .stop_requested
+        );
         assert_err!(
-            ChargeSession::new_request(Origin::signed(user), charger),
-            pallet_charge_session::Error::<Test>::NoPaymentConsent
+            ChargeSession::stop_session(Origin::signed(user), charger, 1),
+            pallet_charge_session::Error::<Test>::StopAlreadyRequested
         );
+
+        // The session is settled by the charger once stopped
+        assert_ok!(ChargeSession::end_session(
+            Origin::signed(charger),
+            user,
+            1,
+            99,
+            vec![]
+        ));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
     });
 }
 
 #[test]
-fn only_admin_can_register_new_charger() {
+fn should_stop_a_session_by_an_admin() {
     new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
         let charger = Public::from_raw(hex!(
-            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
         ));
-        let non_admin = Public::from_raw(hex!(
-            "f0a0a685af36aa9f1f20ecb1a4559ba579fd6218c62bcf410f2026d2d66ece14"
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
         ));
+        assert_ok!(ChargeSession::stop_session(
+            Origin::signed(admin),
+            charger,
+            1
+        ));
+        assert!(
+            ChargeSession::active_sessions(charger, 1)
+                .unwrap()
+                .stop_requested
+        );
+    });
+}
+
+#[test]
+fn should_not_stop_a_session_of_another_user() {
+    new_test_ext().execute_with(|| {
+        let user_1 = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let user_2 = Public::from_raw(hex!(
+            "44ce5dedab4604c5df7d46ebd146ff5773bfcd975f7203e4cbac45149593a865"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user_1);
+
         assert_err!(
-            ChargeSession::add_new_charger(
-                Origin::signed(non_admin),
-                charger,
-            ),
+            ChargeSession::stop_session(Origin::signed(user_1), charger, 1),
+            pallet_charge_session::Error::<Test>::NoChargingSession
+        );
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_1),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user_1,
+            1
+        ));
+        assert_err!(
+            ChargeSession::stop_session(Origin::signed(user_2), charger, 1),
+            pallet_charge_session::Error::<Test>::NotAllowedToStop
+        );
+        assert!(
+            !ChargeSession::active_sessions(charger, 1)
+                .unwrap()
+                .stop_requested
+        );
+    });
+}
+
+#[test]
+fn should_end_session_on_fault() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert_ok!(ChargeSession::report_fault(
+            Origin::signed(charger),
+            1,
+            b"GroundFailure".to_vec(),
+            5
+        ));
+
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert_eq!(
+            ChargeSession::faulted_connectors(charger, 1),
+            Some(b"GroundFailure".to_vec())
+        );
+        // The same fault is not reported twice
+        assert_err!(
+            ChargeSession::report_fault(Origin::signed(charger), 1, b"GroundFailure".to_vec(), 0),
+            pallet_charge_session::Error::<Test>::ConnectorFaulted
+        );
+        // Only the faulted connector rejects new requests
+        assert_err!(
+            ChargeSession::new_request(Origin::signed(user), charger, 1, SessionLimits::default()),
+            pallet_charge_session::Error::<Test>::ConnectorFaulted
+        );
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            2,
+            SessionLimits::default()
+        ));
+    });
+}
+
+#[test]
+fn should_clear_fault() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        assert_err!(
+            ChargeSession::clear_fault(Origin::signed(admin), charger, 1),
+            pallet_charge_session::Error::<Test>::NotFaulted
+        );
+        assert_ok!(ChargeSession::report_fault(
+            Origin::signed(charger),
+            1,
+            b"OverCurrentFailure".to_vec(),
+            0
+        ));
+        assert_err!(
+            ChargeSession::clear_fault(Origin::signed(user), charger, 1),
             pallet_charge_session::Error::<Test>::NotAnAdmin
         );
+        assert_ok!(ChargeSession::clear_fault(
+            Origin::signed(admin),
+            charger,
+            1
+        ));
+
+        assert!(ChargeSession::faulted_connectors(charger, 1).is_none());
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
     });
 }
 
 #[test]
-fn should_reject_chargers_without_location() {
+fn should_end_session_of_metered_charger_on_fault_with_last_checkpoint() {
     new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
         let charger = Public::from_raw(hex!(
-            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::set_meter_key(
+            Origin::signed(admin),
+            charger,
+            meter_public_key()
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        Timestamp::set_timestamp(60_000);
+        assert_ok!(ChargeSession::record_checkpoint(
+            Origin::signed(charger),
+            user,
+            1,
+            MeterSample {
+                energy_wh: 4_200,
+                timestamp: 60_000,
+                ..Default::default()
+            }
         ));
+        let session_id = ChargeSession::active_sessions(charger, 1)
+            .unwrap()
+            .session_id;
+
+        // The energy reported with the fault is not proven by the meter: it is ignored
+        assert_ok!(ChargeSession::report_fault(
+            Origin::signed(charger),
+            1,
+            b"GroundFailure".to_vec(),
+            100
+        ));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert_eq!(ChargeSession::session_history(session_id).unwrap().kwh, 4);
+    });
+}
+
+#[test]
+fn should_close_a_lost_session() {
+    new_test_ext().execute_with(|| {
         let admin = Public::from_raw(hex!(
             "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
         ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::set_meter_key(
+            Origin::signed(admin),
+            charger,
+            meter_public_key()
+        ));
+
         assert_err!(
-            ChargeSession::add_new_charger(
-                Origin::signed(admin),
-                charger,
-            ),
-            pallet_charge_session::Error::<Test>::NoLocation
+            ChargeSession::close_session(Origin::signed(admin), charger, 1),
+            pallet_charge_session::Error::<Test>::NoChargingSession
+        );
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        Timestamp::set_timestamp(60_000);
+        assert_ok!(ChargeSession::record_checkpoint(
+            Origin::signed(charger),
+            user,
+            1,
+            MeterSample {
+                energy_wh: 3_500,
+                timestamp: 60_000,
+                ..Default::default()
+            }
+        ));
+        let session_id = ChargeSession::active_sessions(charger, 1)
+            .unwrap()
+            .session_id;
+
+        // The session has no signed final reading: an admin closes it with the last checkpoint
+        assert_err!(
+            ChargeSession::close_session(Origin::signed(user), charger, 1),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
         );
+        assert_ok!(ChargeSession::close_session(
+            Origin::signed(admin),
+            charger,
+            1
+        ));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        let record = ChargeSession::session_history(session_id).unwrap();
+        assert_eq!(record.kwh, 3);
+        assert_eq!(record.payment_status, PaymentStatus::Pending);
+
+        // The connector takes new requests again
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
     });
 }
+
+#[test]
+fn should_record_meter_checkpoints() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        let meter = |energy_wh, timestamp| MeterSample {
+            energy_wh,
+            power_w: 7400,
+            voltage_mv: 230_000,
+            current_ma: 32_000,
+            timestamp,
+        };
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+
+        Timestamp::set_timestamp(60_000);
+        assert_ok!(ChargeSession::record_checkpoint(
+            Origin::signed(charger),
+            user,
+            1,
+            meter(120, 1_000)
+        ));
+        // A replayed reading is rejected
+        assert_err!(
+            ChargeSession::record_checkpoint(Origin::signed(charger), user, 1, meter(120, 1_000)),
+            pallet_charge_session::Error::<Test>::StaleCheckpoint
+        );
+
+        Timestamp::set_timestamp(120_000);
+        assert_ok!(ChargeSession::record_checkpoint(
+            Origin::signed(charger),
+            user,
+            1,
+            meter(250, 2_000)
+        ));
+
+        let checkpoint = ChargeSession::active_sessions(charger, 1)
+            .unwrap()
+            .last_checkpoint
+            .expect("no checkpoint");
+        assert_eq!(checkpoint.energy_wh, 250);
+        assert_eq!(checkpoint.power_w, 7400);
+        assert_eq!(checkpoint.read_at, 2_000);
+        assert_eq!(checkpoint.recorded_at, 120_000);
+    });
+}
+
+#[test]
+fn should_not_record_checkpoint_without_session() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_err!(
+            ChargeSession::record_checkpoint(
+                Origin::signed(charger),
+                user,
+                1,
+                MeterSample::default()
+            ),
+            pallet_charge_session::Error::<Test>::NoChargingSession
+        );
+    });
+}
+
+#[test]
+fn should_set_meter_key() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+
+        assert_err!(
+            ChargeSession::set_meter_key(Origin::signed(charger), charger, meter_public_key()),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        assert_err!(
+            ChargeSession::set_meter_key(Origin::signed(admin), charger, vec![1; 33]),
+            pallet_charge_session::Error::<Test>::InvalidMeterKey
+        );
+        assert_ok!(ChargeSession::set_meter_key(
+            Origin::signed(admin),
+            charger,
+            meter_public_key()
+        ));
+        assert_eq!(ChargeSession::meter_keys(charger), Some(meter_public_key()));
+    });
+}
+
+#[test]
+fn should_verify_signed_meter_readings() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::set_meter_key(
+            Origin::signed(admin),
+            charger,
+            meter_public_key()
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        let session_id = ChargeSession::active_sessions(charger, 1)
+            .unwrap()
+            .session_id;
+
+        // The energy must be proven by the readings signed by the meter
+        let dataset = ocmf_dataset("100.0", "112.5");
+        let tampered = String::from_utf8(dataset.clone())
+            .unwrap()
+            .replace("112.5", "212.5")
+            .into_bytes();
+        assert_err!(
+            ChargeSession::end_session(Origin::signed(charger), user, 1, 12, vec![]),
+            pallet_charge_session::Error::<Test>::MissingMeterReading
+        );
+        assert_err!(
+            ChargeSession::end_session(Origin::signed(charger), user, 1, 112, vec![tampered]),
+            pallet_charge_session::Error::<Test>::BadMeterSignature
+        );
+        assert_err!(
+            ChargeSession::end_session(Origin::signed(charger), user, 1, 13, vec![dataset.clone()]),
+            pallet_charge_session::Error::<Test>::MeterReadingMismatch
+        );
+        assert_ok!(ChargeSession::end_session(
+            Origin::signed(charger),
+            user,
+            1,
+            12,
+            vec![dataset.clone()]
+        ));
+        assert_eq!(
+            ChargeSession::signed_meter_data(session_id),
+            Some(vec![dataset.clone()])
+        );
+        assert_eq!(
+            ChargeSession::last_meter_readings(charger, 1),
+            Some(112_500)
+        );
+
+        // The readings of a past session cannot be replayed
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert_err!(
+            ChargeSession::end_session(Origin::signed(charger), user, 1, 12, vec![dataset]),
+            pallet_charge_session::Error::<Test>::StaleMeterReading
+        );
+    });
+}
+
+#[test]
+fn should_set_power_limit() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        System::set_block_number(1);
+        Timestamp::set_timestamp(10_000);
+
+        assert_err!(
+            ChargeSession::set_power_limit(Origin::signed(user), charger, 0, 11_000, None),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        assert_err!(
+            ChargeSession::set_power_limit(Origin::signed(admin), user, 0, 11_000, None),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+        assert_err!(
+            ChargeSession::set_power_limit(Origin::signed(admin), charger, 0, 11_000, Some(10_000)),
+            pallet_charge_session::Error::<Test>::PowerLimitExpired
+        );
+
+        // Limit of the whole charger
+        assert_ok!(ChargeSession::set_power_limit(
+            Origin::signed(admin),
+            charger,
+            0,
+            11_000,
+            Some(20_000)
+        ));
+        assert_eq!(
+            ChargeSession::power_limits(charger, 0),
+            Some(PowerLimit {
+                watts: 11_000,
+                valid_until: Some(20_000),
+                set_by: admin,
+                applied: false,
+            })
+        );
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_charge_session(
+                pallet_charge_session::Event::PowerLimitSet(
+                    admin,
+                    charger,
+                    0,
+                    11_000,
+                    Some(20_000)
+                )
+            ))
+        );
+
+        // Limit of a session, dropped with the session
+        assert_err!(
+            ChargeSession::set_power_limit(Origin::signed(admin), charger, 1, 3700, None),
+            pallet_charge_session::Error::<Test>::NoChargingSession
+        );
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert_ok!(ChargeSession::set_power_limit(
+            Origin::signed(admin),
+            charger,
+            1,
+            3700,
+            None
+        ));
+        assert!(ChargeSession::power_limits(charger, 1).is_some());
+        assert_ok!(ChargeSession::end_session(
+            Origin::signed(charger),
+            user,
+            1,
+            2,
+            vec![]
+        ));
+        assert!(ChargeSession::power_limits(charger, 1).is_none());
+        assert!(ChargeSession::power_limits(charger, 0).is_some());
+    });
+}
+
+#[test]
+fn should_report_power_limit_taking_effect() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        System::set_block_number(1);
+        assert_ok!(ChargeSession::set_power_limit(
+            Origin::signed(admin),
+            charger,
+            0,
+            11_000,
+            None
+        ));
+
+        // Only the limit in storage can be applied: it may have been replaced meanwhile
+        assert_err!(
+            ChargeSession::power_limit_applied(Origin::signed(charger), 0, 22_000, None),
+            pallet_charge_session::Error::<Test>::NoPowerLimit
+        );
+        assert_ok!(ChargeSession::power_limit_applied(
+            Origin::signed(charger),
+            0,
+            11_000,
+            None
+        ));
+        assert_eq!(
+            ChargeSession::power_limits(charger, 0).map(|limit| limit.applied),
+            Some(true)
+        );
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_charge_session(
+                pallet_charge_session::Event::PowerLimitApplied(charger, 0, 11_000)
+            ))
+        );
+
+        // A limit refused by the charger is dropped
+        assert_ok!(ChargeSession::reject_power_limit(
+            Origin::signed(charger),
+            0,
+            11_000,
+            None,
+            ChargerError::Rejected
+        ));
+        assert!(ChargeSession::power_limits(charger, 0).is_none());
+        assert_err!(
+            ChargeSession::reject_power_limit(
+                Origin::signed(charger),
+                0,
+                11_000,
+                None,
+                ChargerError::Rejected
+            ),
+            pallet_charge_session::Error::<Test>::NoPowerLimit
+        );
+    });
+}
+
+#[test]
+fn should_assign_chargers_to_sites() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+
+        assert_err!(
+            ChargeSession::set_site(Origin::signed(charger), 1, 22_000),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        assert_err!(
+            ChargeSession::assign_to_site(Origin::signed(admin), charger, Some(1)),
+            pallet_charge_session::Error::<Test>::UnknownSite
+        );
+        assert_ok!(ChargeSession::set_site(Origin::signed(admin), 1, 22_000));
+        assert_ok!(ChargeSession::set_site(Origin::signed(admin), 2, 44_000));
+        assert_eq!(
+            ChargeSession::sites(1),
+            Some(Site {
+                max_power_w: 22_000
+            })
+        );
+
+        assert_ok!(ChargeSession::assign_to_site(
+            Origin::signed(admin),
+            charger,
+            Some(1)
+        ));
+        assert_eq!(ChargeSession::charger_sites(charger), Some(1));
+        assert!(pallet_charge_session::SiteChargers::<Test>::contains_key(
+            1, charger
+        ));
+
+        // A charger belongs to one site at most
+        assert_ok!(ChargeSession::assign_to_site(
+            Origin::signed(admin),
+            charger,
+            Some(2)
+        ));
+        assert!(!pallet_charge_session::SiteChargers::<Test>::contains_key(
+            1, charger
+        ));
+        assert!(pallet_charge_session::SiteChargers::<Test>::contains_key(
+            2, charger
+        ));
+        assert_ok!(ChargeSession::assign_to_site(
+            Origin::signed(admin),
+            charger,
+            None
+        ));
+        assert_eq!(ChargeSession::charger_sites(charger), None);
+        assert!(!pallet_charge_session::SiteChargers::<Test>::contains_key(
+            2, charger
+        ));
+    });
+}
+
+#[test]
+fn should_split_site_power() {
+    let split = |max_power_w, chargers| {
+        let mut shares = split_power(max_power_w, chargers);
+        shares.sort();
+        shares
+    };
+
+    // In proportion of the sessions, chargers without session getting nothing
+    assert_eq!(
+        split(21_000, vec![("a", 2, None), ("b", 1, None), ("c", 0, None)]),
+        vec![("a", 14_000), ("b", 7000)]
+    );
+    // The power a charger cannot take goes to the others
+    assert_eq!(
+        split(21_000, vec![("a", 2, Some(10_000)), ("b", 1, None)]),
+        vec![("a", 10_000), ("b", 11_000)]
+    );
+    assert_eq!(
+        split(
+            21_000,
+            vec![("a", 1, Some(50_000)), ("b", 1, Some(3000)), ("c", 1, None)]
+        ),
+        vec![("a", 9000), ("b", 3000), ("c", 9000)]
+    );
+    // The power of the site is never exceeded
+    let shares = split(10_000, vec![("a", 1, None), ("b", 1, None), ("c", 1, None)]);
+    assert_eq!(shares.iter().map(|(_, power)| power).sum::<u32>(), 10_000);
+}
+
+#[test]
+fn should_register_tokens() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let other_user = Public::from_raw(hex!(
+            "f0a0a685af36aa9f1f20ecb1a4559ba579fd6218c62bcf410f2026d2d66ece14"
+        ));
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        register_charger(
+            Public::from_raw(hex!(
+                "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+            )),
+            b"[1,2]".to_vec(),
+        );
+
+        // Only the users who gave their payment consent can register a token
+        assert_err!(
+            ChargeSession::register_token(Origin::signed(user), b"04A2B3C4".to_vec()),
+            pallet_charge_session::Error::<Test>::NoPaymentConsent
+        );
+        add_consent(user);
+        add_consent(other_user);
+        assert_err!(
+            ChargeSession::register_token(Origin::signed(user), vec![]),
+            pallet_charge_session::Error::<Test>::InvalidToken
+        );
+        assert_err!(
+            ChargeSession::register_token(Origin::signed(user), vec![b'0'; 37]),
+            pallet_charge_session::Error::<Test>::InvalidToken
+        );
+        assert_ok!(ChargeSession::register_token(
+            Origin::signed(user),
+            b"04A2B3C4".to_vec()
+        ));
+        assert_ok!(ChargeSession::register_token(
+            Origin::signed(user),
+            b"FRDELC123456789".to_vec()
+        ));
+        assert_eq!(ChargeSession::tokens(b"04A2B3C4".to_vec()), Some(user));
+        assert_err!(
+            ChargeSession::register_token(Origin::signed(other_user), b"04A2B3C4".to_vec()),
+            pallet_charge_session::Error::<Test>::TokenAlreadyRegistered
+        );
+
+        // A token is unregistered by its owner or an admin
+        assert_err!(
+            ChargeSession::unregister_token(Origin::signed(other_user), b"04A2B3C4".to_vec()),
+            pallet_charge_session::Error::<Test>::NotTokenOwner
+        );
+        assert_ok!(ChargeSession::unregister_token(
+            Origin::signed(user),
+            b"04A2B3C4".to_vec()
+        ));
+        assert_ok!(ChargeSession::unregister_token(
+            Origin::signed(admin),
+            b"FRDELC123456789".to_vec()
+        ));
+        assert_eq!(ChargeSession::tokens(b"FRDELC123456789".to_vec()), None);
+        assert_err!(
+            ChargeSession::unregister_token(Origin::signed(user), b"04A2B3C4".to_vec()),
+            pallet_charge_session::Error::<Test>::UnknownToken
+        );
+    });
+}
+
+#[test]
+fn should_request_a_session_with_a_token() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        register_charger(charger, b"[1,2]".to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::register_token(
+            Origin::signed(user),
+            b"04A2B3C4".to_vec()
+        ));
+        Timestamp::set_timestamp(999);
+
+        // Only the chargers request sessions for the presented tokens
+        assert_err!(
+            ChargeSession::new_token_request(Origin::signed(user), b"04A2B3C4".to_vec(), 1),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+        assert_err!(
+            ChargeSession::new_token_request(Origin::signed(charger), b"04D5E6F7".to_vec(), 1),
+            pallet_charge_session::Error::<Test>::UnknownToken
+        );
+
+        // The request and the charge consent are stored on behalf of the owner of the token
+        assert_ok!(ChargeSession::new_token_request(
+            Origin::signed(charger),
+            b"04A2B3C4".to_vec(),
+            1
+        ));
+        let request = ChargeSession::user_requests(charger, 1).expect("no user request");
+        assert_eq!(request.user_id, user);
+        assert_eq!(request.created_at, 999);
+        let consent = ChargeConsent::user_consent(request.session_id).expect("no user consent");
+        assert_eq!(consent.user_id, user);
+        assert_eq!(consent.charger_id, charger);
+        assert_err!(
+            ChargeSession::new_token_request(Origin::signed(charger), b"04A2B3C4".to_vec(), 1),
+            pallet_charge_session::Error::<Test>::ChargerIsBusy
+        );
+    });
+}
+
+#[test]
+fn should_manage_contract_roots() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let pki = ContractPki::new();
+        let root_hash = BlakeTwo256::hash(&pki.root());
+
+        assert_err!(
+            ChargeSession::add_contract_root(Origin::signed(user), pki.root()),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        // Only self-signed certificate authorities are roots
+        assert_err!(
+            ChargeSession::add_contract_root(
+                Origin::signed(admin),
+                pki.contract_chain("FRDELC123456789", 2025)[1].clone()
+            ),
+            pallet_charge_session::Error::<Test>::InvalidContractRoot
+        );
+        assert_err!(
+            ChargeSession::add_contract_root(Origin::signed(admin), b"root".to_vec()),
+            pallet_charge_session::Error::<Test>::InvalidContractRoot
+        );
+        assert_ok!(ChargeSession::add_contract_root(
+            Origin::signed(admin),
+            pki.root()
+        ));
+        assert_eq!(ChargeSession::contract_roots(root_hash), Some(pki.root()));
+
+        assert_err!(
+            ChargeSession::remove_contract_root(Origin::signed(user), root_hash),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        assert_ok!(ChargeSession::remove_contract_root(
+            Origin::signed(admin),
+            root_hash
+        ));
+        assert_eq!(ChargeSession::contract_roots(root_hash), None);
+        assert_err!(
+            ChargeSession::remove_contract_root(Origin::signed(admin), root_hash),
+            pallet_charge_session::Error::<Test>::UnknownContractRoot
+        );
+    });
+}
+
+#[test]
+fn should_register_contracts() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let other_user = Public::from_raw(hex!(
+            "f0a0a685af36aa9f1f20ecb1a4559ba579fd6218c62bcf410f2026d2d66ece14"
+        ));
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let pki = ContractPki::new();
+        let chain = pki.contract_chain("FRDELC123456789", 2025);
+        Timestamp::set_timestamp(CONTRACT_TIME);
+
+        // Only the users who gave their payment consent can register a contract
+        assert_err!(
+            ChargeSession::register_contract(
+                Origin::signed(user),
+                b"FRDELC123456789".to_vec(),
+                chain.clone()
+            ),
+            pallet_charge_session::Error::<Test>::NoPaymentConsent
+        );
+        add_consent(user);
+        add_consent(other_user);
+
+        // The chain must lead to a trusted root
+        assert_err!(
+            ChargeSession::register_contract(
+                Origin::signed(user),
+                b"FRDELC123456789".to_vec(),
+                chain.clone()
+            ),
+            pallet_charge_session::Error::<Test>::UntrustedContractRoot
+        );
+        assert_ok!(ChargeSession::add_contract_root(
+            Origin::signed(admin),
+            pki.root()
+        ));
+        assert_err!(
+            ChargeSession::register_contract(
+                Origin::signed(user),
+                b"FRDELC123456789".to_vec(),
+                ContractPki::new().contract_chain("FRDELC123456789", 2025)
+            ),
+            pallet_charge_session::Error::<Test>::UntrustedContractRoot
+        );
+        assert_err!(
+            ChargeSession::register_contract(
+                Origin::signed(user),
+                b"FRDELC123456789".to_vec(),
+                vec![b"certificate".to_vec()]
+            ),
+            pallet_charge_session::Error::<Test>::InvalidContractCertificate
+        );
+        assert_err!(
+            ChargeSession::register_contract(
+                Origin::signed(user),
+                b"FRDELC123456789".to_vec(),
+                pki.contract_chain("FRDELC123456789", 2021)
+            ),
+            pallet_charge_session::Error::<Test>::ContractCertificateExpired
+        );
+        // The contract id is the subject of the contract certificate
+        assert_err!(
+            ChargeSession::register_contract(
+                Origin::signed(user),
+                b"FRDELC000000001".to_vec(),
+                chain.clone()
+            ),
+            pallet_charge_session::Error::<Test>::ContractMismatch
+        );
+        assert_ok!(ChargeSession::register_contract(
+            Origin::signed(user),
+            b"FRDELC123456789".to_vec(),
+            chain.clone()
+        ));
+        assert_err!(
+            ChargeSession::register_contract(
+                Origin::signed(other_user),
+                b"FRDELC123456789".to_vec(),
+                chain.clone()
+            ),
+            pallet_charge_session::Error::<Test>::ContractAlreadyRegistered
+        );
+        // Its owner renews the certificate chain of a contract
+        let renewed_chain = pki.contract_chain("FRDELC123456789", 2027);
+        assert_ok!(ChargeSession::register_contract(
+            Origin::signed(user),
+            b"FRDELC123456789".to_vec(),
+            renewed_chain.clone()
+        ));
+        assert_eq!(
+            ChargeSession::contracts(b"FRDELC123456789".to_vec()),
+            Some(RegisteredContract {
+                owner: user,
+                certificate_chain: renewed_chain
+            })
+        );
+
+        // A contract is unregistered by its owner or an admin
+        assert_err!(
+            ChargeSession::unregister_contract(
+                Origin::signed(other_user),
+                b"FRDELC123456789".to_vec()
+            ),
+            pallet_charge_session::Error::<Test>::NotContractOwner
+        );
+        assert_ok!(ChargeSession::unregister_contract(
+            Origin::signed(admin),
+            b"FRDELC123456789".to_vec()
+        ));
+        assert_eq!(ChargeSession::contracts(b"FRDELC123456789".to_vec()), None);
+        assert_err!(
+            ChargeSession::unregister_contract(Origin::signed(user), b"FRDELC123456789".to_vec()),
+            pallet_charge_session::Error::<Test>::UnknownContract
+        );
+    });
+}
+
+#[test]
+fn should_request_a_session_with_a_contract() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        register_charger(charger, b"[1,2]".to_vec());
+        add_consent(user);
+        let pki = ContractPki::new();
+        let chain = pki.contract_chain("FRDELC123456789", 2025);
+        Timestamp::set_timestamp(CONTRACT_TIME);
+        assert_ok!(ChargeSession::add_contract_root(
+            Origin::signed(admin),
+            pki.root()
+        ));
+        assert_ok!(ChargeSession::register_contract(
+            Origin::signed(user),
+            b"FRDELC123456789".to_vec(),
+            chain.clone()
+        ));
+
+        // Only the chargers request sessions for the presented contracts, with their certificate
+        assert_err!(
+            ChargeSession::new_contract_request(
+                Origin::signed(user),
+                b"FRDELC123456789".to_vec(),
+                chain[0].clone(),
+                1
+            ),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+        assert_err!(
+            ChargeSession::new_contract_request(
+                Origin::signed(charger),
+                b"FRDELC000000001".to_vec(),
+                chain[0].clone(),
+                1
+            ),
+            pallet_charge_session::Error::<Test>::UnknownContract
+        );
+        assert_err!(
+            ChargeSession::new_contract_request(
+                Origin::signed(charger),
+                b"FRDELC123456789".to_vec(),
+                pki.contract_chain("FRDELC123456789", 2025)[0].clone(),
+                1
+            ),
+            pallet_charge_session::Error::<Test>::ContractMismatch
+        );
+
+        // The request and the charge consent are stored on behalf of the owner of the contract
+        assert_ok!(ChargeSession::new_contract_request(
+            Origin::signed(charger),
+            b"FRDELC123456789".to_vec(),
+            chain[0].clone(),
+            1
+        ));
+        let request = ChargeSession::user_requests(charger, 1).expect("no user request");
+        assert_eq!(request.user_id, user);
+        let consent = ChargeConsent::user_consent(request.session_id).expect("no user consent");
+        assert_eq!(consent.user_id, user);
+
+        // The chain is verified again: the contract lapses with its root or its certificate
+        assert_err!(
+            ChargeSession::new_contract_request(
+                Origin::signed(charger),
+                b"FRDELC123456789".to_vec(),
+                chain[0].clone(),
+                2
+            ),
+            pallet_charge_session::Error::<Test>::ChargerIsBusy
+        );
+        assert_ok!(ChargeSession::remove_contract_root(
+            Origin::signed(admin),
+            BlakeTwo256::hash(&pki.root())
+        ));
+        assert_err!(
+            ChargeSession::new_contract_request(
+                Origin::signed(charger),
+                b"FRDELC123456789".to_vec(),
+                chain[0].clone(),
+                2
+            ),
+            pallet_charge_session::Error::<Test>::UntrustedContractRoot
+        );
+        assert_ok!(ChargeSession::add_contract_root(
+            Origin::signed(admin),
+            pki.root()
+        ));
+        Timestamp::set_timestamp(1_735_689_601_000);
+        assert_err!(
+            ChargeSession::new_contract_request(
+                Origin::signed(charger),
+                b"FRDELC123456789".to_vec(),
+                chain[0].clone(),
+                2
+            ),
+            pallet_charge_session::Error::<Test>::ContractCertificateExpired
+        );
+    });
+}
+
+#[test]
+fn should_reject_new_request_for_unregistered_charger() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        add_consent(user);
+        assert_err!(
+            ChargeSession::new_request(Origin::signed(user), charger, 1, SessionLimits::default()),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+    });
+}
+
+#[test]
+fn should_reject_start_session_for_unregistered_charger() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        add_consent(user);
+        assert_err!(
+            ChargeSession::start_session(Origin::signed(charger), user, 1),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+    });
+}
+
+#[test]
+fn should_reject_end_session_for_unregistered_charger() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        add_consent(user);
+        assert_err!(
+            ChargeSession::end_session(Origin::signed(charger), user, 1, 99, vec![]),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+    });
+}
+
+#[test]
+fn should_store_charge_consent() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+
+        let request = ChargeSession::user_requests(charger, 1).expect("no user request");
+        let session_id = request.session_id;
+
+        let consent = ChargeConsent::user_consent(session_id).expect("no user consent");
+
+        assert_eq!(consent.charger_id, charger);
+        assert_eq!(consent.user_id, user);
+    });
+}
+
+#[test]
+fn should_reject_new_request_without_consent() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        assert_err!(
+            ChargeSession::new_request(Origin::signed(user), charger, 1, SessionLimits::default()),
+            pallet_charge_session::Error::<Test>::NoPaymentConsent
+        );
+    });
+}
+
+#[test]
+fn only_admin_can_register_new_charger() {
+    new_test_ext().execute_with(|| {
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        let non_admin = Public::from_raw(hex!(
+            "f0a0a685af36aa9f1f20ecb1a4559ba579fd6218c62bcf410f2026d2d66ece14"
+        ));
+        assert_err!(
+            ChargeSession::add_new_charger(Origin::signed(non_admin), charger, charger_metadata()),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+    });
+}
+
+#[test]
+fn should_reject_chargers_without_location() {
+    new_test_ext().execute_with(|| {
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        assert_err!(
+            ChargeSession::add_new_charger(Origin::signed(admin), charger, charger_metadata()),
+            pallet_charge_session::Error::<Test>::NoLocation
+        );
+    });
+}
+
+#[test]
+fn should_reject_invalid_charger_metadata() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        let add_charger =
+            |metadata| ChargeSession::add_new_charger(Origin::signed(admin), charger, metadata);
+
+        let mut metadata = charger_metadata();
+        metadata.coordinates.latitude = 90_000_001;
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidCoordinates
+        );
+
+        let mut metadata = charger_metadata();
+        metadata.operator = vec![];
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidChargerText
+        );
+        let mut metadata = charger_metadata();
+        metadata.address = vec![b'a'; 257];
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidChargerText
+        );
+
+        let mut metadata = charger_metadata();
+        metadata.connectors.clear();
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidConnectorMetadata
+        );
+        // Connector described twice
+        let mut metadata = charger_metadata();
+        metadata.connectors[1].connector = 1;
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidConnectorMetadata
+        );
+        // CHAdeMO is DC only
+        let mut metadata = charger_metadata();
+        metadata.connectors[1].connector_type = ConnectorType::Chademo;
+        metadata.connectors[1].current = CurrentType::Ac;
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidConnectorMetadata
+        );
+        let mut metadata = charger_metadata();
+        metadata.connectors[0].max_power_w = 0;
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidConnectorMetadata
+        );
+
+        let mut metadata = charger_metadata();
+        metadata.opening_hours[0].closes = 24 * 60 + 1;
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidOpeningHours
+        );
+        let mut metadata = charger_metadata();
+        metadata.opening_hours[0].weekday = 7;
+        assert_err!(
+            add_charger(metadata),
+            pallet_charge_session::Error::<Test>::InvalidOpeningHours
+        );
+
+        assert!(!ChargeSession::is_charger(&charger));
+    });
+}
+
+#[test]
+fn should_update_charger_metadata() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "9a75da2249c660ca3c6bc5f7ff925ffbbbf5332fa09ab1e0540d748570c8ce27"
+        ));
+        System::set_block_number(1);
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        assert_eq!(
+            ChargeSession::charger_metadata(charger),
+            Some(charger_metadata())
+        );
+
+        // Always open, and operated by another operator
+        let mut metadata = charger_metadata();
+        metadata.opening_hours.clear();
+        metadata.operator = b"Another operator".to_vec();
+        assert_err!(
+            ChargeSession::update_charger_metadata(Origin::signed(user), charger, metadata.clone()),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        assert_ok!(ChargeSession::update_charger_metadata(
+            Origin::signed(admin),
+            charger,
+            metadata.clone()
+        ));
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_charge_session(
+                pallet_charge_session::Event::ChargerMetadataUpdated(admin, charger)
+            ))
+        );
+        assert_eq!(ChargeSession::charger_metadata(charger), Some(metadata));
+
+        // The charger updates its own metadata, which is validated
+        let mut metadata = charger_metadata();
+        metadata.coordinates.longitude = -180_000_001;
+        assert_err!(
+            ChargeSession::update_charger_metadata(Origin::signed(charger), charger, metadata),
+            pallet_charge_session::Error::<Test>::InvalidCoordinates
+        );
+        assert_ok!(ChargeSession::update_charger_metadata(
+            Origin::signed(charger),
+            charger,
+            charger_metadata()
+        ));
+        assert_eq!(
+            ChargeSession::charger_metadata(charger),
+            Some(charger_metadata())
+        );
+
+        assert_err!(
+            ChargeSession::update_charger_metadata(Origin::signed(admin), user, charger_metadata()),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+    });
+}
+
+#[test]
+fn should_suspend_and_resume_charger() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user_1 = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let user_2 = Public::from_raw(hex!(
+            "44ce5dedab4604c5df7d46ebd146ff5773bfcd975f7203e4cbac45149593a865"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        System::set_block_number(1);
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user_1);
+        add_consent(user_2);
+
+        // A session runs on connector 1, a request waits on connector 2
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_1),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user_1,
+            1
+        ));
+        Timestamp::set_timestamp(60_000);
+        assert_ok!(ChargeSession::record_checkpoint(
+            Origin::signed(charger),
+            user_1,
+            1,
+            MeterSample {
+                energy_wh: 3_500,
+                ..Default::default()
+            }
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_2),
+            charger,
+            2,
+            SessionLimits::default()
+        ));
+        let session_id = ChargeSession::active_sessions(charger, 1)
+            .unwrap()
+            .session_id;
+
+        assert_err!(
+            ChargeSession::suspend_charger(Origin::signed(charger), charger),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        assert_err!(
+            ChargeSession::resume_charger(Origin::signed(admin), charger),
+            pallet_charge_session::Error::<Test>::ChargerNotSuspended
+        );
+        assert_ok!(ChargeSession::suspend_charger(
+            Origin::signed(admin),
+            charger
+        ));
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_charge_session(
+                pallet_charge_session::Event::ChargerSuspended(admin, charger)
+            ))
+        );
+        assert_eq!(ChargeSession::suspended_chargers(charger), Some(vec![1]));
+
+        // The request is cancelled, and the session closed with its last checkpoint
+        assert!(ChargeSession::user_requests(charger, 2).is_none());
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert_eq!(ChargeSession::session_history(session_id).unwrap().kwh, 3);
+
+        // A suspended charger receives no request until resumed
+        assert_err!(
+            ChargeSession::new_request(
+                Origin::signed(user_2),
+                charger,
+                2,
+                SessionLimits::default()
+            ),
+            pallet_charge_session::Error::<Test>::ChargerSuspended
+        );
+        assert_err!(
+            ChargeSession::suspend_charger(Origin::signed(admin), charger),
+            pallet_charge_session::Error::<Test>::ChargerSuspended
+        );
+        assert_ok!(ChargeSession::resume_charger(
+            Origin::signed(admin),
+            charger
+        ));
+        assert!(ChargeSession::suspended_chargers(charger).is_none());
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user_2),
+            charger,
+            2,
+            SessionLimits::default()
+        ));
+    });
+}
+
+#[test]
+fn should_remove_charger() {
+    new_test_ext().execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::set_site(Origin::signed(admin), 1, 22_000));
+        assert_ok!(ChargeSession::assign_to_site(
+            Origin::signed(admin),
+            charger,
+            Some(1)
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::start_session(
+            Origin::signed(charger),
+            user,
+            1
+        ));
+        assert_ok!(ChargeSession::set_meter_key(
+            Origin::signed(admin),
+            charger,
+            meter_public_key()
+        ));
+        pallet_charge_session::LastMeterReadings::<Test>::insert(charger, 1, 12_000);
+        assert_ok!(ChargeSession::suspend_charger(
+            Origin::signed(admin),
+            charger
+        ));
+
+        assert_err!(
+            ChargeSession::remove_charger(Origin::signed(charger), charger),
+            pallet_charge_session::Error::<Test>::NotAnAdmin
+        );
+        assert_ok!(ChargeSession::remove_charger(
+            Origin::signed(admin),
+            charger
+        ));
+        assert!(!ChargeSession::is_charger(&charger));
+        assert!(!Registrar::members_of(admin).contains(&charger));
+        assert!(ChargeSession::suspended_chargers(charger).is_none());
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert_eq!(ChargeSession::charger_sites(charger), None);
+        assert_eq!(ChargeSession::charger_metadata(charger), None);
+        assert_eq!(ChargeSession::meter_keys(charger), None);
+        assert_eq!(ChargeSession::last_meter_readings(charger, 1), None);
+
+        // The session closed by the suspension is still to be stopped by the charger
+        assert_eq!(ChargeSession::removed_chargers(charger), Some(vec![1]));
+        assert!(!pallet_charge_session::SiteChargers::<Test>::contains_key(
+            1, charger
+        ));
+
+        assert_err!(
+            ChargeSession::new_request(Origin::signed(user), charger, 1, SessionLimits::default()),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+        assert_err!(
+            ChargeSession::remove_charger(Origin::signed(admin), charger),
+            pallet_charge_session::Error::<Test>::NotRegisteredCharger
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_run_a_session() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+
+        // The request is started on the charger
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::start_session(user, 1)
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+
+        // A checkpoint is recorded once due
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        Timestamp::set_timestamp(60_000);
+        let calls = run_offchain_worker(&pool_state);
+        match calls.as_slice() {
+            [Call::ChargeSession(pallet_charge_session::Call::record_checkpoint(
+                checkpoint_user,
+                1,
+                meter,
+            ))] => {
+                assert_eq!(checkpoint_user, &user);
+                assert_eq!(meter.energy_wh, 2400);
+            }
+            other => panic!("Unexpected calls {:?}", other),
+        }
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+
+        // The end of the session is reported with its energy
+        charger_api
+            .lock()
+            .unwrap()
+            .advance(Duration::from_secs(240));
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::end_session(user, 1, 12, vec![])
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+    });
+}
+
+#[test]
+fn offchain_worker_should_stop_sessions_at_their_limits() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+
+        // The session is stopped once its energy is delivered
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits {
+                energy_wh: Some(3_000),
+                ..Default::default()
+            }
+        ));
+        let calls = run_offchain_worker(&pool_state);
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        charger_api.lock().unwrap().advance(Duration::from_secs(30));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::end_session(user, 1, 3, vec![])
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+
+        // The session is stopped once its duration has elapsed
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits {
+                duration: Some(120_000),
+                ..Default::default()
+            }
+        ));
+        let calls = run_offchain_worker(&pool_state);
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+        Timestamp::set_timestamp(59_000);
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        Timestamp::set_timestamp(120_000);
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        assert_eq!(
+            run_offchain_worker(&pool_state),
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::end_session(user, 1, 2, vec![])
+            )]
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_stop_sessions_at_the_amount_billed() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits {
+                amount: Some(50),
+                ..Default::default()
+            }
+        ));
+        let calls = run_offchain_worker(&pool_state);
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+
+        // The energy is billed by whole kWh, at 15 cents: 3.6 kWh cost 45 cents
+        charger_api.lock().unwrap().advance(Duration::from_secs(90));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        charger_api.lock().unwrap().advance(Duration::from_secs(30));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::end_session(user, 1, 4, vec![])
+            )]
+        );
+
+        // The bill of the session is its agreed amount
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        assert_eq!(SessionPayment::pending_payments()[0].amount(), 50);
+    });
+}
+
+#[test]
+fn offchain_worker_should_stop_sessions_of_cancelled_requests() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+
+        // The request is cancelled while its start_session transaction is pending
+        assert_eq!(
+            run_offchain_worker(&pool_state),
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::start_session(user, 1)
+            )]
+        );
+        assert_ok!(ChargeSession::cancel_request(
+            Origin::signed(user),
+            charger,
+            1
+        ));
+
+        // The session started on the charger is stopped
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        let status = charger_api.lock().unwrap().get_current_charge_status(1);
+        assert!(matches!(status, Ok(ChargeStatus::Ended { .. })));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+
+        // The connector runs the next requests as usual
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::start_session(user, 1)
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        let status = charger_api.lock().unwrap().get_current_charge_status(1);
+        assert!(matches!(status, Ok(ChargeStatus::Active { .. })));
+    });
+}
+
+#[test]
+fn offchain_worker_should_stop_sessions_of_suspended_charger() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let calls = run_offchain_worker(&pool_state);
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+
+        // The session closed by the suspension is stopped on the charger
+        assert_ok!(ChargeSession::suspend_charger(
+            Origin::signed(admin),
+            charger
+        ));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        let status = charger_api.lock().unwrap().get_current_charge_status(1);
+        assert!(matches!(status, Ok(ChargeStatus::Ended { .. })));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+    });
+}
+
+#[test]
+fn offchain_worker_should_drop_authorizations_presented_to_suspended_charger() {
+    let (mut ext, charger, charger_api, pool_state) =
+        new_offchain_test_ext(MockCharger::builder().manual_clock().build());
+    ext.execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::register_token(
+            Origin::signed(user),
+            b"04A2B3C4".to_vec()
+        ));
+        assert_ok!(ChargeSession::suspend_charger(
+            Origin::signed(admin),
+            charger
+        ));
+
+        // The token presented while suspended does not request a session on resumption
+        charger_api.lock().unwrap().present_token(1, "04A2B3C4");
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        assert_ok!(ChargeSession::resume_charger(
+            Origin::signed(admin),
+            charger
+        ));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+
+        // Tokens presented once resumed are handled again
+        charger_api.lock().unwrap().present_token(1, "04A2B3C4");
+        assert_eq!(
+            run_offchain_worker(&pool_state),
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::new_token_request(b"04A2B3C4".to_vec(), 1)
+            )]
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_stop_sessions_of_removed_charger() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let calls = run_offchain_worker(&pool_state);
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+
+        // The session closed by the removal is stopped on the charger
+        assert_ok!(ChargeSession::remove_charger(
+            Origin::signed(admin),
+            charger
+        ));
+        assert_eq!(ChargeSession::removed_chargers(charger), Some(vec![1]));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        let status = charger_api.lock().unwrap().get_current_charge_status(1);
+        assert!(matches!(status, Ok(ChargeStatus::Ended { .. })));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+    });
+}
+
+#[test]
+fn offchain_worker_should_reject_request_refused_by_charger() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(
+            ScriptedSession::new(Duration::from_secs(300), 12).with_fault(MockFault::NotPlugged),
+        )
+        .build();
+    let (mut ext, charger, _, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+
+        // The reason given by the charger is reported with the rejection
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::reject_request(user, 1, ChargerError::NotPlugged)
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        assert!(ChargeSession::user_requests(charger, 1).is_none());
+
+        // The user can request the session again
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        assert_eq!(
+            run_offchain_worker(&pool_state),
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::start_session(user, 1)
+            )]
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_report_fault() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(
+            ScriptedSession::new(Duration::from_secs(300), 12).with_fault(MockFault::FaultAfter {
+                after: Duration::from_secs(150),
+                error_code: "GroundFailure".to_string(),
+            }),
+        )
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        for call in run_offchain_worker(&pool_state) {
+            assert_ok!(call.dispatch(Origin::signed(charger)));
+        }
+
+        // The session is ended with the energy delivered before the fault
+        charger_api
+            .lock()
+            .unwrap()
+            .advance(Duration::from_secs(200));
+        let report_fault = vec![Call::ChargeSession(
+            pallet_charge_session::Call::report_fault(1, b"GroundFailure".to_vec(), 6),
+        )];
+        assert_eq!(run_offchain_worker(&pool_state), report_fault);
+
+        // report_fault is only sent again once it has timed out, with the energy of the fault
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+        System::set_block_number(1);
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        System::set_block_number(u64::from(SUBMISSION_TIMEOUT));
+        assert_eq!(run_offchain_worker(&pool_state), report_fault);
+        assert_ok!(report_fault[0].clone().dispatch(Origin::signed(charger)));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert_eq!(
+            ChargeSession::faulted_connectors(charger, 1),
+            Some(b"GroundFailure".to_vec())
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_close_session_lost_by_charger() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(
+            ScriptedSession::new(Duration::from_secs(300), 12)
+                .with_fault(MockFault::NoFinalReading),
+        )
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::set_meter_key(
+            Origin::signed(admin),
+            charger,
+            meter_public_key()
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        for call in run_offchain_worker(&pool_state) {
+            assert_ok!(call.dispatch(Origin::signed(charger)));
+        }
+        let session_id = ChargeSession::active_sessions(charger, 1)
+            .unwrap()
+            .session_id;
+        charger_api.lock().unwrap().advance(Duration::from_secs(60));
+        Timestamp::set_timestamp(60_000);
+        for call in run_offchain_worker(&pool_state) {
+            assert_ok!(call.dispatch(Origin::signed(charger)));
+        }
+
+        // The session ends without signed final reading: it is closed with the last checkpoint,
+        // and close_session is only sent again once it has timed out
+        charger_api
+            .lock()
+            .unwrap()
+            .advance(Duration::from_secs(240));
+        let close_session = vec![Call::ChargeSession(
+            pallet_charge_session::Call::close_session(charger, 1),
+        )];
+        assert_eq!(run_offchain_worker(&pool_state), close_session);
+        System::set_block_number(1);
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        System::set_block_number(u64::from(SUBMISSION_TIMEOUT));
+        assert_eq!(run_offchain_worker(&pool_state), close_session);
+        assert_ok!(close_session[0].clone().dispatch(Origin::signed(charger)));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+        assert_eq!(ChargeSession::session_history(session_id).unwrap().kwh, 2);
+    });
+}
+
+#[test]
+fn offchain_worker_should_resume_session_started_before_restart() {
+    let (mut ext, charger, charger_api, pool_state) =
+        new_offchain_test_ext(MockCharger::builder().manual_clock().build());
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        // The session was started, but the node stopped before sending start_session
+        assert!(charger_api.lock().unwrap().start_new_charge(1).is_ok());
+
+        assert_eq!(
+            run_offchain_worker(&pool_state),
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::start_session(user, 1)
+            )]
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_not_send_session_transactions_twice() {
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(ScriptedSession::new(Duration::from_secs(300), 12))
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let start_session = vec![Call::ChargeSession(
+            pallet_charge_session::Call::start_session(user, 1),
+        )];
+
+        // start_session is only sent again once it has timed out
+        assert_eq!(run_offchain_worker(&pool_state), start_session);
+        System::set_block_number(1);
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        let timeout = u64::from(SUBMISSION_TIMEOUT);
+        System::set_block_number(timeout);
+        assert_eq!(run_offchain_worker(&pool_state), start_session);
+        assert_ok!(start_session[0].clone().dispatch(Origin::signed(charger)));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+
+        // end_session is sent again with the energy reported by the charger at the end
+        charger_api
+            .lock()
+            .unwrap()
+            .advance(Duration::from_secs(300));
+        let end_session = vec![Call::ChargeSession(
+            pallet_charge_session::Call::end_session(user, 1, 12, vec![]),
+        )];
+        assert_eq!(run_offchain_worker(&pool_state), end_session);
+        System::set_block_number(2 * timeout - 1);
+        assert!(run_offchain_worker(&pool_state).is_empty());
+        System::set_block_number(2 * timeout);
+        assert_eq!(run_offchain_worker(&pool_state), end_session);
+        assert_ok!(end_session[0].clone().dispatch(Origin::signed(charger)));
+        assert!(ChargeSession::active_sessions(charger, 1).is_none());
+    });
+}
+
+#[test]
+fn offchain_worker_should_end_session_with_signed_meter_data() {
+    let dataset = ocmf_dataset("1500.0", "1512.0");
+    let charger_api = MockCharger::builder()
+        .manual_clock()
+        .session(
+            ScriptedSession::new(Duration::from_secs(300), 12)
+                .with_signed_data(vec![String::from_utf8(dataset.clone()).unwrap()]),
+        )
+        .build();
+    let (mut ext, charger, charger_api, pool_state) = new_offchain_test_ext(charger_api);
+    ext.execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::set_meter_key(
+            Origin::signed(admin),
+            charger,
+            meter_public_key()
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(user),
+            charger,
+            1,
+            SessionLimits::default()
+        ));
+        let calls = run_offchain_worker(&pool_state);
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+
+        // The signed readings of the meter are sent with the end of the session
+        charger_api
+            .lock()
+            .unwrap()
+            .advance(Duration::from_secs(300));
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::end_session(user, 1, 12, vec![dataset.clone()])
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        assert_eq!(
+            ChargeSession::last_meter_readings(charger, 1),
+            Some(1_512_000)
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_push_power_limits() {
+    let (mut ext, charger, charger_api, pool_state) =
+        new_offchain_test_ext(MockCharger::builder().manual_clock().build());
+    ext.execute_with(|| {
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        Timestamp::set_timestamp(10_000);
+        assert_ok!(ChargeSession::set_power_limit(
+            Origin::signed(admin),
+            charger,
+            0,
+            11_000,
+            Some(60_000)
+        ));
+
+        // The limit is pushed to the charger, which reports it has taken effect
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::power_limit_applied(0, 11_000, Some(60_000))
+            )]
+        );
+        assert_eq!(charger_api.lock().unwrap().power_limit(1), Some(11_000));
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+        assert!(run_offchain_worker(&pool_state).is_empty());
+
+        // A lapsed limit is not pushed anymore
+        assert_ok!(ChargeSession::set_power_limit(
+            Origin::signed(admin),
+            charger,
+            0,
+            7400,
+            Some(60_000)
+        ));
+        Timestamp::set_timestamp(60_000);
+        assert!(run_offchain_worker(&pool_state).is_empty());
+    });
+}
+
+#[test]
+fn offchain_worker_should_request_sessions_for_presented_tokens() {
+    let (mut ext, charger, charger_api, pool_state) =
+        new_offchain_test_ext(MockCharger::builder().manual_clock().build());
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        assert_ok!(ChargeSession::register_token(
+            Origin::signed(user),
+            b"04A2B3C4".to_vec()
+        ));
+
+        // Unknown tokens are dropped
+        {
+            let mut charger_api = charger_api.lock().unwrap();
+            charger_api.present_token(2, "04D5E6F7");
+            charger_api.present_token(1, "04A2B3C4");
+        }
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::new_token_request(b"04A2B3C4".to_vec(), 1)
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+
+        // The request is then started as any other
+        assert_eq!(
+            run_offchain_worker(&pool_state),
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::start_session(user, 1)
+            )]
+        );
+    });
+}
+
+#[test]
+fn offchain_worker_should_request_sessions_for_presented_contracts() {
+    let (mut ext, charger, charger_api, pool_state) =
+        new_offchain_test_ext(MockCharger::builder().manual_clock().build());
+    ext.execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let admin = Public::from_raw(hex!(
+            "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+        ));
+        register_charger(charger, "[1,2]".as_bytes().to_vec());
+        add_consent(user);
+        let pki = ContractPki::new();
+        let chain = pki.contract_chain("FRDELC123456789", 2025);
+        Timestamp::set_timestamp(CONTRACT_TIME);
+        assert_ok!(ChargeSession::add_contract_root(
+            Origin::signed(admin),
+            pki.root()
+        ));
+        assert_ok!(ChargeSession::register_contract(
+            Origin::signed(user),
+            b"FRDELC123456789".to_vec(),
+            chain.clone()
+        ));
+
+        // Unknown contracts, and contracts presented with another certificate, are dropped
+        {
+            let mut charger_api = charger_api.lock().unwrap();
+            charger_api.present_contract(
+                2,
+                "FRDELC000000001",
+                pki.contract_chain("FRDELC000000001", 2025),
+            );
+            charger_api.present_contract(
+                2,
+                "FRDELC123456789",
+                pki.contract_chain("FRDELC123456789", 2025),
+            );
+            charger_api.present_contract(1, "FRDELC123456789", chain.clone());
+        }
+        let calls = run_offchain_worker(&pool_state);
+        assert_eq!(
+            calls,
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::new_contract_request(
+                    b"FRDELC123456789".to_vec(),
+                    chain[0].clone(),
+                    1
+                )
+            )]
+        );
+        assert_ok!(calls[0].clone().dispatch(Origin::signed(charger)));
+
+        // The request is then started as any other
+        assert_eq!(
+            run_offchain_worker(&pool_state),
+            vec![Call::ChargeSession(
+                pallet_charge_session::Call::start_session(user, 1)
+            )]
+        );
+    });
+}
+
+/// Nodes of the chargers of a site, sharing one chain: the chain is replicated on every node
+struct SiteSimulation {
+    nodes: Vec<(
+        TestExternalities,
+        Public,
+        Arc<Mutex<MockCharger>>,
+        Arc<RwLock<PoolState>>,
+    )>,
+}
+
+impl SiteSimulation {
+    fn new(charger_apis: Vec<MockCharger>) -> SiteSimulation {
+        SiteSimulation {
+            nodes: charger_apis
+                .into_iter()
+                .enumerate()
+                .map(|(index, charger_api)| {
+                    new_charger_node(&format!("//Charger{}", index), charger_api)
+                })
+                .collect(),
+        }
+    }
+
+    fn charger(&self, index: usize) -> Public {
+        self.nodes[index].1
+    }
+
+    fn charger_api(&self, index: usize) -> Arc<Mutex<MockCharger>> {
+        self.nodes[index].2.clone()
+    }
+
+    /// Execute `f` on the chain of every node
+    fn execute(&mut self, f: impl Fn()) {
+        for (ext, ..) in &mut self.nodes {
+            ext.execute_with(&f);
+        }
+    }
+
+    /// Run the offchain worker of every node, then dispatch the transactions they sent
+    fn run_offchain_workers(&mut self) {
+        let mut calls = Vec::new();
+        for (ext, charger, _, pool_state) in &mut self.nodes {
+            let sent = ext.execute_with(|| run_offchain_worker(pool_state));
+            calls.extend(sent.into_iter().map(|call| (*charger, call)));
+        }
+        self.execute(|| {
+            for (charger, call) in &calls {
+                assert_ok!(call.clone().dispatch(Origin::signed(*charger)));
+            }
+        });
+    }
+
+    /// Power limit of the whole charger `index`
+    fn power_limit(&self, index: usize) -> Option<u32> {
+        self.nodes[index].2.lock().unwrap().power_limit(0)
+    }
+}
+
+#[test]
+fn offchain_workers_should_balance_site_power() {
+    let charger_api = || {
+        MockCharger::builder()
+            .manual_clock()
+            .duration(Duration::from_secs(3600))
+            .kwh(20)
+    };
+    let mut site = SiteSimulation::new(vec![
+        charger_api().build(),
+        charger_api()
+            .session(ScriptedSession::new(Duration::from_secs(600), 2))
+            .build(),
+        charger_api().build(),
+    ]);
+    let (a, b, c) = (site.charger(0), site.charger(1), site.charger(2));
+    let admin = Public::from_raw(hex!(
+        "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+    ));
+    let users: Vec<Public> = (1..=4).map(|user| Public::from_raw([user; 32])).collect();
+    site.execute(|| {
+        for charger in &[a, b, c] {
+            register_charger(*charger, "[1,2]".as_bytes().to_vec());
+        }
+        assert_ok!(ChargeSession::set_site(Origin::signed(admin), 1, 21_000));
+        for charger in &[a, b, c] {
+            assert_ok!(ChargeSession::assign_to_site(
+                Origin::signed(admin),
+                *charger,
+                Some(1)
+            ));
+        }
+        for user in &users {
+            add_consent(*user);
+        }
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(users[0]),
+            a,
+            1,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(users[1]),
+            a,
+            2,
+            SessionLimits::default()
+        ));
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(users[2]),
+            b,
+            1,
+            SessionLimits::default()
+        ));
+    });
+
+    // The sessions are started, then the power of the site is split between them
+    site.run_offchain_workers();
+    site.run_offchain_workers();
+    assert_eq!(site.power_limit(0), Some(14_000));
+    assert_eq!(site.power_limit(1), Some(7000));
+    assert_eq!(site.power_limit(2), None);
+
+    // The power charger A cannot take under its own limit goes to charger B
+    site.execute(|| {
+        assert_ok!(ChargeSession::set_power_limit(
+            Origin::signed(admin),
+            a,
+            0,
+            10_000,
+            None
+        ))
+    });
+    site.run_offchain_workers();
+    assert_eq!(site.power_limit(0), Some(10_000));
+    assert_eq!(site.power_limit(1), Some(11_000));
+    site.execute(|| {
+        assert_eq!(
+            ChargeSession::power_limits(a, 0).map(|limit| limit.applied),
+            Some(true)
+        )
+    });
+
+    // A new session on charger C takes its share from charger B
+    site.execute(|| {
+        assert_ok!(ChargeSession::new_request(
+            Origin::signed(users[3]),
+            c,
+            1,
+            SessionLimits::default()
+        ))
+    });
+    site.run_offchain_workers();
+    site.run_offchain_workers();
+    assert_eq!(site.power_limit(0), Some(10_000));
+    assert_eq!(site.power_limit(1), Some(5500));
+    assert_eq!(site.power_limit(2), Some(5500));
+
+    // Once the session of charger B is over, charger C gets its share
+    site.charger_api(1)
+        .lock()
+        .unwrap()
+        .advance(Duration::from_secs(600));
+    site.run_offchain_workers();
+    site.run_offchain_workers();
+    site.execute(|| assert!(ChargeSession::active_sessions(b, 1).is_none()));
+    assert_eq!(site.power_limit(0), Some(10_000));
+    assert_eq!(site.power_limit(2), Some(11_000));
+}
@@ -0,0 +1,70 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Counts the calls that the runtime lets an account send without fee, so that their number
+//! can be limited over a period of blocks.
+
+#[cfg(test)]
+mod tests;
+
+#[frame_support::pallet]
+pub mod pallet {
+    use frame_support::pallet_prelude::*;
+    use frame_support::sp_runtime::traits::Saturating;
+    use frame_system::pallet_prelude::*;
+
+    #[pallet::config]
+    pub trait Config: frame_system::Config {
+        /// Calls an account may send without fee in a period
+        type MaxCalls: Get<u32>;
+        /// Length of a period, in blocks
+        type Period: Get<Self::BlockNumber>;
+    }
+
+    #[pallet::pallet]
+    #[pallet::generate_store(pub(super) trait Store)]
+    pub struct Pallet<T>(_);
+
+    /// Calls sent without fee by each account: block of the start of the current period of
+    /// the account, and its calls since
+    #[pallet::storage]
+    pub type FeelessCalls<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, (T::BlockNumber, u32)>;
+
+    #[pallet::hooks]
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+    #[pallet::call]
+    impl<T: Config> Pallet<T> {}
+
+    impl<T: Config> Pallet<T> {
+        /// Calls sent without fee by `who` in its current period
+        pub fn calls(who: &T::AccountId) -> u32 {
+            let now = <frame_system::Module<T>>::block_number();
+            match FeelessCalls::<T>::get(who) {
+                Some((since, calls)) if now.saturating_sub(since) < T::Period::get() => calls,
+                _ => 0,
+            }
+        }
+
+        /// Whether `who` has sent all its calls without fee of its current period
+        pub fn is_exhausted(who: &T::AccountId) -> bool {
+            Self::calls(who) >= T::MaxCalls::get()
+        }
+
+        /// Count a call sent without fee by `who`, which starts a new period if its current
+        /// one is over
+        pub fn note_call(who: &T::AccountId) {
+            let now = <frame_system::Module<T>>::block_number();
+            FeelessCalls::<T>::mutate(who, |current| {
+                *current = match *current {
+                    Some((since, calls)) if now.saturating_sub(since) < T::Period::get() => {
+                        Some((since, calls + 1))
+                    }
+                    _ => Some((now, 1)),
+                }
+            });
+        }
+    }
+}
+
+pub use pallet::*;
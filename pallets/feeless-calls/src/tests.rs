@@ -0,0 +1,104 @@
+use crate as pallet_feeless_calls;
+
+use sp_core::H256;
+use sp_io::TestExternalities;
+
+use sp_runtime::{
+    testing::Header,
+    traits::{BlakeTwo256, IdentityLookup},
+};
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+pub fn new_test_ext() -> TestExternalities {
+    frame_system::GenesisConfig::default()
+        .build_storage::<Test>()
+        .unwrap()
+        .into()
+}
+
+frame_support::construct_runtime!(
+  pub enum Test where
+    Block = Block,
+    NodeBlock = Block,
+    UncheckedExtrinsic = UncheckedExtrinsic,
+  {
+    System: frame_system::{Module, Call, Config, Storage, Event<T>},
+    FeelessCalls: pallet_feeless_calls::{Module, Storage},
+  }
+);
+
+frame_support::parameter_types! {
+  pub const BlockHashCount: u64 = 250;
+  pub const MaxCalls: u32 = 2;
+  pub const Period: u64 = 10;
+}
+
+impl frame_system::Config for Test {
+    type BaseCallFilter = ();
+    type BlockWeights = ();
+    type BlockLength = ();
+    type DbWeight = ();
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Call = Call;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type AccountId = u64;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = Event;
+    type BlockHashCount = BlockHashCount;
+    type Version = ();
+    type PalletInfo = PalletInfo;
+    type AccountData = ();
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+    type SS58Prefix = ();
+}
+
+impl pallet_feeless_calls::Config for Test {
+    type MaxCalls = MaxCalls;
+    type Period = Period;
+}
+
+#[test]
+fn should_count_calls_of_each_account() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_eq!(FeelessCalls::calls(&1), 0);
+
+        FeelessCalls::note_call(&1);
+        assert_eq!(FeelessCalls::calls(&1), 1);
+        assert!(!FeelessCalls::is_exhausted(&1));
+        FeelessCalls::note_call(&1);
+        assert_eq!(FeelessCalls::calls(&1), 2);
+        assert!(FeelessCalls::is_exhausted(&1));
+
+        assert_eq!(FeelessCalls::calls(&2), 0);
+        assert!(!FeelessCalls::is_exhausted(&2));
+    });
+}
+
+#[test]
+fn should_start_a_new_period() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        FeelessCalls::note_call(&1);
+        FeelessCalls::note_call(&1);
+
+        // The period of the account starts with its first call
+        System::set_block_number(10);
+        assert!(FeelessCalls::is_exhausted(&1));
+        System::set_block_number(11);
+        assert_eq!(FeelessCalls::calls(&1), 0);
+        FeelessCalls::note_call(&1);
+        System::set_block_number(20);
+        assert_eq!(FeelessCalls::calls(&1), 1);
+        System::set_block_number(21);
+        assert_eq!(FeelessCalls::calls(&1), 0);
+    });
+}
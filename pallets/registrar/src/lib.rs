@@ -1,5 +1,8 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(test)]
+mod tests;
+
 use frame_support::{
     decl_error, decl_event, decl_module, decl_storage, dispatch, ensure, traits::EnsureOrigin,
 };
@@ -20,6 +23,8 @@ decl_error! {
         InvalidOrganization,
         /// Cannot add a user to an organization to which they already belong.
         MemberOfOrganization,
+        /// Cannot remove a user from an organization to which they do not belong.
+        NotMemberOfOrganization,
     }
 }
 
@@ -34,6 +39,8 @@ decl_event!(
         CreatedOrganization(AccountId, Vec<u8>),
         /// An account was added to an organization. [account, organization_id]
         AddedToOrganization(AccountId, Vec<u8>),
+        /// An account was removed from an organization. [account, organization_id]
+        RemovedFromOrganization(AccountId, Vec<u8>),
     }
 );
 
@@ -101,6 +108,18 @@ decl_module! {
             Self::deposit_event(RawEvent::AddedToOrganization(who, b"OrgMember".to_vec()));
             Ok(())
         }
+
+        /// Remove an account from an organization. Will return a NotMemberOfOrganization error if the
+        /// account is not a member. Will emit a RemovedFromOrganization event on success.
+        ///
+        /// The dispatch origin for this call must be Signed.
+        #[weight = 10_000]
+        pub fn remove_from_organization(origin, account: T::AccountId) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::remove_from_org(&who, &account)?;
+            Self::deposit_event(RawEvent::RemovedFromOrganization(who, b"OrgMember".to_vec()));
+            Ok(())
+        }
     }
 }
 
@@ -144,6 +163,29 @@ impl<T: Config> Module<T> {
         Ok(())
     }
 
+    pub fn remove_from_org(org: &T::AccountId, account: &T::AccountId) -> dispatch::DispatchResult {
+        // Organizations list.
+        let orgs = Self::organizations();
+        ensure!(orgs.contains(&org), Error::<T>::InvalidOrganization);
+
+        // Accounts that belong to a certain organization.
+        let mut members = Self::members_of(&org);
+        let index = members
+            .iter()
+            .position(|member| member == account)
+            .ok_or(Error::<T>::NotMemberOfOrganization)?;
+        members.remove(index);
+        MembersOf::<T>::insert(&org, members);
+
+        // Revoke the DID delegate of the account.
+        <pallet_did::Module<T>>::revoke_delegate(
+            RawOrigin::Signed(org.clone()).into(),
+            org.clone(),
+            b"OrgMember".to_vec(),
+            account.clone(),
+        )
+    }
+
     /// Returns true if and only if the account is a member of an organization.
     pub fn part_of_organization(account: &T::AccountId) -> bool {
         let orgs = <Module<T>>::organizations();
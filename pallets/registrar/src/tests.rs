@@ -0,0 +1,159 @@
+use crate as pallet_registrar;
+
+use frame_support::{assert_err, assert_ok};
+use sp_core::{sr25519::Signature, H256};
+use sp_io::TestExternalities;
+
+use sp_runtime::{
+    testing::Header,
+    traits::{BlakeTwo256, IdentityLookup, Verify},
+};
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+pub fn new_test_ext() -> TestExternalities {
+    let mut ext: TestExternalities = frame_system::GenesisConfig::default()
+        .build_storage::<Test>()
+        .unwrap()
+        .into();
+    // Events are only deposited after the genesis block
+    ext.execute_with(|| System::set_block_number(1));
+    ext
+}
+
+frame_support::construct_runtime!(
+  pub enum Test where
+    Block = Block,
+    NodeBlock = Block,
+    UncheckedExtrinsic = UncheckedExtrinsic,
+  {
+    System: frame_system::{Module, Call, Config, Storage, Event<T>},
+    Timestamp: pallet_timestamp::{Module, Call, Storage, Inherent},
+    DID: pallet_did::{Module, Call, Storage, Event<T>},
+    Registrar: pallet_registrar::{Module, Call, Storage, Event<T>},
+  }
+);
+
+frame_support::parameter_types! {
+  pub const BlockHashCount: u64 = 250;
+  pub BlockWeights: frame_system::limits::BlockWeights =
+    frame_system::limits::BlockWeights::simple_max(1024);
+}
+
+frame_support::parameter_types! {
+  pub const MinimumPeriod: u64 = 5;
+}
+
+impl frame_system::Config for Test {
+    type BaseCallFilter = ();
+    type BlockWeights = ();
+    type BlockLength = ();
+    type DbWeight = ();
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Call = Call;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type AccountId = sp_core::sr25519::Public;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = Event;
+    type BlockHashCount = BlockHashCount;
+    type Version = ();
+    type PalletInfo = PalletInfo;
+    type AccountData = ();
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+    type SS58Prefix = ();
+}
+
+impl pallet_timestamp::Config for Test {
+    type Moment = u64;
+    type OnTimestampSet = ();
+    type MinimumPeriod = MinimumPeriod;
+    type WeightInfo = ();
+}
+
+impl pallet_did::Config for Test {
+    type Event = Event;
+    type Public = <Signature as Verify>::Signer;
+    type Signature = Signature;
+    type Time = Timestamp;
+}
+
+impl pallet_registrar::Config for Test {
+    type Event = Event;
+}
+
+use hex_literal::hex;
+use sp_core::sr25519::Public;
+
+fn admin() -> Public {
+    Public::from_raw(hex!(
+        "fc349aca2d746555e2c13e7b48c2f543420eaec94c11b6d3dc80e66508e44148"
+    ))
+}
+
+fn member() -> Public {
+    Public::from_raw(hex!(
+        "e6687af66d6b3a191061c519033b50d86907eaa4c7961ed416a5dc3042346036"
+    ))
+}
+
+#[test]
+fn should_remove_member_from_organization() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(Registrar::create_organization(
+            Origin::signed(admin()),
+            b"chargers".to_vec()
+        ));
+        assert_ok!(Registrar::add_to_organization(
+            Origin::signed(admin()),
+            member()
+        ));
+        assert!(Registrar::part_of_organization(&member()));
+
+        assert_ok!(Registrar::remove_from_organization(
+            Origin::signed(admin()),
+            member()
+        ));
+        assert!(!Registrar::members_of(admin()).contains(&member()));
+        assert!(!Registrar::part_of_organization(&member()));
+        assert_eq!(
+            System::events().last().map(|record| record.event.clone()),
+            Some(Event::pallet_registrar(
+                pallet_registrar::RawEvent::RemovedFromOrganization(admin(), b"OrgMember".to_vec())
+            ))
+        );
+
+        // The account can join the organization again
+        assert_ok!(Registrar::add_to_organization(
+            Origin::signed(admin()),
+            member()
+        ));
+        assert!(Registrar::part_of_organization(&member()));
+    });
+}
+
+#[test]
+fn should_not_remove_account_not_member_of_organization() {
+    new_test_ext().execute_with(|| {
+        assert_err!(
+            Registrar::remove_from_organization(Origin::signed(admin()), member()),
+            pallet_registrar::Error::<Test>::InvalidOrganization
+        );
+
+        assert_ok!(Registrar::create_organization(
+            Origin::signed(admin()),
+            b"chargers".to_vec()
+        ));
+        assert_err!(
+            Registrar::remove_from_organization(Origin::signed(admin()), member()),
+            pallet_registrar::Error::<Test>::NotMemberOfOrganization
+        );
+        assert!(Registrar::members_of(admin()).is_empty());
+    });
+}
@@ -37,6 +37,17 @@ pub struct Payment<Moment, Hash, AccountId> {
     charger_id: AccountId
 }
 
+impl<Moment, Hash, AccountId> Payment<Moment, Hash, AccountId> {
+    /// Amount of the payment, in cents
+    pub fn amount(&self) -> u128 {
+        self.amount
+    }
+
+    pub fn session_id(&self) -> &Hash {
+        &self.session_id
+    }
+}
+
 pub mod crypto {
     use frame_system::offchain::AppCrypto;
     use sp_core::sr25519::Signature as SR25519Signature;
@@ -139,6 +150,11 @@ pub mod pallet {
     #[pallet::getter(fn completed_payments)]
     pub type CompletedPayments<T: Config> = StorageMap<_, Blake2_128Concat, T::Hash, Payment<T::Moment, T::Hash, T::AccountId>>;
 
+    /// Maximal amount agreed by the user for a session, in cents
+    #[pallet::storage]
+    #[pallet::getter(fn payment_caps)]
+    pub type PaymentCaps<T: Config> = StorageMap<_, Blake2_128Concat, T::Hash, u128>;
+
     #[pallet::storage]
     pub type AllowedUsers<T: Config> = StorageValue<_, Vec<(T::AccountId, Vec<u8>)>, ValueQuery>;
 
@@ -244,7 +260,11 @@ pub mod pallet {
 
             // Verify that there is a session_id corresponding
             let debtor = match <consent::Module<T>>::get_consent_from_session_id(session_id) {
-                None => return Err(Error::<T>::NoConsentForPayment.into()),
+                None => {
+                    // A session without consent is never billed
+                    PaymentCaps::<T>::remove(&session_id);
+                    return Err(Error::<T>::NoConsentForPayment.into());
+                }
                 Some(session) => {
                     if session.charger_id != sender {
                         return Err(Error::<T>::NoConsentForPayment.into());
@@ -254,6 +274,9 @@ pub mod pallet {
                 }
             };
 
+            // The charger of the session bills it once: its cap is dropped on every exit
+            let cap = PaymentCaps::<T>::take(&session_id);
+
             // Verify that this session_id has not already been confirmed
             if CompletedPayments::<T>::get(&session_id).is_some() {
                 return Err(Error::<T>::AlreadyConfirmedPayment.into());
@@ -266,11 +289,12 @@ pub mod pallet {
                 Some(consent) => (consent.iban, consent.bic_code),
             };
 
-            // Calculate the price.
-            // Price is in cents
-
-            let current_price = <tariff_manager::Module<T>>::get_current_price();
-            let amount = kwh * current_price;
+            // The user is never billed more than the amount agreed for the session
+            let amount = Self::price_of(kwh);
+            let amount = match cap {
+                Some(cap) => amount.min(cap),
+                None => amount,
+            };
 
             // Add the payment request to the storage, for later processing by the offchain worker
             let mut pending_payments = PendingPayments::<T>::get();
@@ -338,6 +362,16 @@ pub mod pallet {
             PaymentConsents::<T>::get(who).is_some()
         }
 
+        /// Amount billed for `kwh` delivered at the current price, in cents
+        pub fn price_of(kwh: u128) -> u128 {
+            kwh.saturating_mul(<tariff_manager::Module<T>>::get_current_price())
+        }
+
+        /// Bill at most `max_amount` (in cents) for the session `session_id`
+        pub fn set_payment_cap(session_id: T::Hash, max_amount: u128) {
+            PaymentCaps::<T>::insert(&session_id, max_amount);
+        }
+
         pub fn nb_allowed() -> u32 {
             AllowedUsers::<T>::get().len().try_into().unwrap()
         }
@@ -230,6 +230,55 @@ fn should_process_payment_for_user_with_consent() {
     });
 }
 
+#[test]
+fn should_not_bill_more_than_the_payment_cap() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "f42bbe8f90ae3f9a1029a7bfaeca74fb5ca0c759a0d0476610c1eb4c60a40938"
+        ));
+        register_new_usr(user);
+        let session_id = <Test as frame_system::Config>::Hashing::hash(&user);
+        register_new_session(user, charger, session_id);
+
+        SessionPayment::set_payment_cap(session_id, 2000);
+        assert_ok!(SessionPayment::process_payment(Origin::signed(charger), session_id, 1000));
+        assert_eq!(SessionPayment::pending_payments()[0].amount, 2000);
+        assert_eq!(SessionPayment::payment_caps(session_id), None);
+    });
+}
+
+#[test]
+fn should_drop_the_payment_cap_of_a_failed_payment() {
+    new_test_ext().execute_with(|| {
+        let user = Public::from_raw(hex!(
+            "bec4ab0eaff1a0d710274b3648bc5b2253e2bdee293987123962688f08a5c317"
+        ));
+        let charger = Public::from_raw(hex!(
+            "f42bbe8f90ae3f9a1029a7bfaeca74fb5ca0c759a0d0476610c1eb4c60a40938"
+        ));
+        let session_id = <Test as frame_system::Config>::Hashing::hash(&user);
+        register_new_session(user, charger, session_id);
+        SessionPayment::set_payment_cap(session_id, 2000);
+
+        // Only the charger of the session bills it
+        assert_err!(
+            SessionPayment::process_payment(Origin::signed(user), session_id, 1000),
+            pallet_session_payment::Error::<Test>::NoConsentForPayment
+        );
+        assert_eq!(SessionPayment::payment_caps(session_id), Some(2000));
+
+        // The user has no payment consent
+        assert_err!(
+            SessionPayment::process_payment(Origin::signed(charger), session_id, 1000),
+            pallet_session_payment::Error::<Test>::NoConsentForPayment
+        );
+        assert_eq!(SessionPayment::payment_caps(session_id), None);
+    });
+}
+
 #[test]
 fn should_not_process_payment_for_user_without_consent() {
     new_test_ext().execute_with(|| {
@@ -0,0 +1,304 @@
+//! Transaction fees of the calls which the chargers and the payment validators must send for
+//! the charge sessions to proceed
+
+use crate::{AccountId, Balance, Call, ChargeSession, FeelessCalls, Runtime, SessionPayment};
+use codec::{Decode, Encode};
+use pallet_charge_session::Call as ChargeSessionCall;
+use pallet_transaction_payment::ChargeTransactionPayment;
+use sp_runtime::{
+    traits::{DispatchInfoOf, PostDispatchInfoOf, SignedExtension},
+    transaction_validity::{
+        InvalidTransaction, TransactionValidity, TransactionValidityError, ValidTransaction,
+    },
+    DispatchResult, RuntimeDebug,
+};
+
+/// Error of a fee-less call sent by an account which has exhausted its fee-less calls
+pub const FEELESS_CALLS_EXHAUSTED: u8 = 1;
+
+/// Charges the transaction fees (`ChargeTransactionPayment`), except for the calls of the
+/// charge-session pallet sent by the offchain worker of a registered charger which is not
+/// suspended, and complete_payment sent by a payment validator.
+/// An account sends at most `MaxFeelessCalls` of them every `FeelessCallsPeriod` blocks, so
+/// that a compromised charger cannot fill the blocks for free: they are counted by the
+/// feeless-calls pallet.
+#[derive(Encode, Decode, Clone, Eq, PartialEq, RuntimeDebug)]
+pub struct ChargeProtocolFees(ChargeTransactionPayment<Runtime>);
+
+impl From<Balance> for ChargeProtocolFees {
+    fn from(tip: Balance) -> Self {
+        Self(ChargeTransactionPayment::from(tip))
+    }
+}
+
+impl ChargeProtocolFees {
+    /// Whether `call` of `who` pays no fee
+    fn is_feeless(who: &AccountId, call: &Call) -> bool {
+        match call {
+            Call::ChargeSession(
+                ChargeSessionCall::start_session(..)
+                | ChargeSessionCall::end_session(..)
+                | ChargeSessionCall::reject_request(..)
+                | ChargeSessionCall::record_checkpoint(..)
+                | ChargeSessionCall::report_fault(..)
+                | ChargeSessionCall::close_session(..)
+                | ChargeSessionCall::power_limit_applied(..)
+                | ChargeSessionCall::reject_power_limit(..)
+                | ChargeSessionCall::new_token_request(..)
+                | ChargeSessionCall::new_contract_request(..),
+            ) => ChargeSession::is_charger(who) && !ChargeSession::is_suspended(who),
+            Call::SessionPayment(pallet_session_payment::Call::complete_payment(..)) => {
+                SessionPayment::is_payment_validator(who)
+            }
+            _ => false,
+        }
+    }
+
+    fn check_feeless_calls(who: &AccountId) -> Result<(), TransactionValidityError> {
+        if FeelessCalls::is_exhausted(who) {
+            return Err(InvalidTransaction::Custom(FEELESS_CALLS_EXHAUSTED).into());
+        }
+        Ok(())
+    }
+}
+
+impl SignedExtension for ChargeProtocolFees {
+    // Encoded as `ChargeTransactionPayment`, which the wallets know about
+    const IDENTIFIER: &'static str = "ChargeTransactionPayment";
+    type AccountId = AccountId;
+    type Call = Call;
+    type AdditionalSigned = ();
+    /// Set when the fees are charged
+    type Pre = Option<<ChargeTransactionPayment<Runtime> as SignedExtension>::Pre>;
+
+    fn additional_signed(&self) -> Result<(), TransactionValidityError> {
+        Ok(())
+    }
+
+    fn validate(
+        &self,
+        who: &Self::AccountId,
+        call: &Self::Call,
+        info: &DispatchInfoOf<Self::Call>,
+        len: usize,
+    ) -> TransactionValidity {
+        if !Self::is_feeless(who, call) {
+            return self.0.validate(who, call, info, len);
+        }
+        Self::check_feeless_calls(who)?;
+        Ok(ValidTransaction::default())
+    }
+
+    fn pre_dispatch(
+        self,
+        who: &Self::AccountId,
+        call: &Self::Call,
+        info: &DispatchInfoOf<Self::Call>,
+        len: usize,
+    ) -> Result<Self::Pre, TransactionValidityError> {
+        if !Self::is_feeless(who, call) {
+            return Ok(Some(self.0.pre_dispatch(who, call, info, len)?));
+        }
+        Self::check_feeless_calls(who)?;
+        FeelessCalls::note_call(who);
+        Ok(None)
+    }
+
+    fn post_dispatch(
+        pre: Self::Pre,
+        info: &DispatchInfoOf<Self::Call>,
+        post_info: &PostDispatchInfoOf<Self::Call>,
+        len: usize,
+        result: &DispatchResult,
+    ) -> Result<(), TransactionValidityError> {
+        match pre {
+            Some(pre) => ChargeTransactionPayment::<Runtime>::post_dispatch(
+                pre, info, post_info, len, result,
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Balances, BalancesConfig, ChargeSessionConfig, FeelessCallsPeriod, GenesisConfig,
+        MaxFeelessCalls, RegistrarConfig, SessionPaymentConfig, System, SystemConfig,
+    };
+    use frame_support::traits::Get;
+    use frame_support::weights::DispatchInfo;
+    use sp_runtime::BuildStorage;
+
+    fn account(seed: u8) -> AccountId {
+        AccountId::from([seed; 32])
+    }
+
+    fn chargers() -> AccountId {
+        account(1)
+    }
+
+    fn charger() -> AccountId {
+        account(2)
+    }
+
+    fn validators() -> AccountId {
+        account(3)
+    }
+
+    fn validator() -> AccountId {
+        account(4)
+    }
+
+    fn user() -> AccountId {
+        account(5)
+    }
+
+    fn new_test_ext() -> sp_io::TestExternalities {
+        let storage = GenesisConfig {
+            frame_system: Some(SystemConfig {
+                code: vec![],
+                changes_trie_config: Default::default(),
+            }),
+            pallet_balances: Some(BalancesConfig {
+                balances: vec![charger(), validator(), user()]
+                    .into_iter()
+                    .map(|account| (account, 1 << 60))
+                    .collect(),
+            }),
+            pallet_aura: None,
+            pallet_grandpa: None,
+            pallet_sudo: None,
+            pallet_contracts: None,
+            pallet_charge_session: Some(ChargeSessionConfig {
+                charger_organization: chargers(),
+            }),
+            pallet_session_payment: Some(SessionPaymentConfig {
+                payment_validator_organization: validators(),
+            }),
+            pallet_registrar: Some(RegistrarConfig {
+                orgs: vec![
+                    (chargers(), b"chargers".to_vec()),
+                    (validators(), b"payment_validators".to_vec()),
+                ],
+                members: vec![
+                    (chargers(), vec![charger()]),
+                    (validators(), vec![validator()]),
+                ],
+            }),
+        }
+        .build_storage()
+        .unwrap();
+        let mut ext = sp_io::TestExternalities::new(storage);
+        ext.execute_with(|| System::set_block_number(1));
+        ext
+    }
+
+    fn start_session() -> Call {
+        Call::ChargeSession(pallet_charge_session::Call::start_session(user(), 1))
+    }
+
+    fn end_session() -> Call {
+        Call::ChargeSession(pallet_charge_session::Call::end_session(
+            user(),
+            1,
+            12,
+            vec![],
+        ))
+    }
+
+    fn report_fault() -> Call {
+        Call::ChargeSession(pallet_charge_session::Call::report_fault(
+            1,
+            b"GroundFailure".to_vec(),
+            0,
+        ))
+    }
+
+    fn complete_payment() -> Call {
+        Call::SessionPayment(pallet_session_payment::Call::complete_payment(
+            Default::default(),
+        ))
+    }
+
+    /// Fee paid by `who` for `call`, once validated and pre-dispatched
+    fn fee(who: &AccountId, call: &Call) -> Result<Balance, TransactionValidityError> {
+        let info = DispatchInfo {
+            weight: 1_000_000,
+            ..Default::default()
+        };
+        let balance = Balances::free_balance(who);
+        ChargeProtocolFees::from(0).validate(who, call, &info, 100)?;
+        ChargeProtocolFees::from(0).pre_dispatch(who, call, &info, 100)?;
+        Ok(balance - Balances::free_balance(who))
+    }
+
+    #[test]
+    fn should_waive_the_fees_of_the_protocol_calls() {
+        new_test_ext().execute_with(|| {
+            assert_eq!(fee(&charger(), &start_session()), Ok(0));
+            assert_eq!(fee(&charger(), &end_session()), Ok(0));
+            assert_eq!(fee(&validator(), &complete_payment()), Ok(0));
+
+            // The other calls sent by the offchain worker of a charger are waived too
+            let close_session =
+                Call::ChargeSession(pallet_charge_session::Call::close_session(charger(), 1));
+            let power_limit_applied = Call::ChargeSession(
+                pallet_charge_session::Call::power_limit_applied(1, 7_000, None),
+            );
+            let new_token_request = Call::ChargeSession(
+                pallet_charge_session::Call::new_token_request(b"04a2b3c4".to_vec(), 1),
+            );
+            assert_eq!(fee(&charger(), &report_fault()), Ok(0));
+            assert_eq!(fee(&charger(), &close_session), Ok(0));
+            assert_eq!(fee(&charger(), &power_limit_applied), Ok(0));
+            assert_eq!(fee(&charger(), &new_token_request), Ok(0));
+
+            // The fee-less calls are counted for each account
+            assert_eq!(FeelessCalls::calls(&charger()), 6);
+            assert_eq!(FeelessCalls::calls(&validator()), 1);
+            assert_eq!(FeelessCalls::calls(&user()), 0);
+        });
+    }
+
+    #[test]
+    fn should_charge_the_fees_of_the_other_calls() {
+        new_test_ext().execute_with(|| {
+            let remark = Call::System(frame_system::Call::remark(vec![]));
+            assert!(fee(&user(), &start_session()).unwrap() > 0);
+            assert!(fee(&user(), &complete_payment()).unwrap() > 0);
+            assert!(fee(&user(), &report_fault()).unwrap() > 0);
+            assert!(fee(&charger(), &remark).unwrap() > 0);
+            assert!(fee(&charger(), &complete_payment()).unwrap() > 0);
+            assert!(fee(&validator(), &end_session()).unwrap() > 0);
+
+            // A suspended charger pays for its calls
+            pallet_charge_session::SuspendedChargers::<Runtime>::insert(charger(), vec![1]);
+            assert!(fee(&charger(), &start_session()).unwrap() > 0);
+            assert_eq!(FeelessCalls::calls(&charger()), 0);
+        });
+    }
+
+    #[test]
+    fn should_limit_the_feeless_calls_of_an_account() {
+        new_test_ext().execute_with(|| {
+            for _ in 0..MaxFeelessCalls::get() {
+                assert_eq!(fee(&charger(), &end_session()), Ok(0));
+            }
+            let exhausted: TransactionValidityError =
+                InvalidTransaction::Custom(FEELESS_CALLS_EXHAUSTED).into();
+            assert_eq!(fee(&charger(), &start_session()), Err(exhausted));
+
+            // The other accounts have their own calls
+            assert_eq!(fee(&validator(), &complete_payment()), Ok(0));
+
+            // The calls are counted again once the period is over
+            System::set_block_number(FeelessCallsPeriod::get());
+            assert_eq!(fee(&charger(), &start_session()), Err(exhausted));
+            System::set_block_number(1 + FeelessCallsPeriod::get());
+            assert_eq!(fee(&charger(), &start_session()), Ok(0));
+            assert_eq!(FeelessCalls::calls(&charger()), 1);
+        });
+    }
+}
@@ -43,6 +43,8 @@ use pallet_transaction_payment::{CurrencyAdapter, Multiplier};
 pub use sp_runtime::BuildStorage;
 pub use sp_runtime::{Perbill, Permill};
 
+pub mod fees;
+
 /// Import the charge-session pallet.
 pub use pallet_charge_session;
 
@@ -55,6 +57,9 @@ pub use pallet_charge_consent;
 /// The tariff-manager pallet
 pub use pallet_tariff_manager;
 
+/// The feeless-calls pallet
+pub use pallet_feeless_calls;
+
 /// An index to a block.
 pub type BlockNumber = u32;
 
@@ -78,6 +83,9 @@ pub type Index = u32;
 /// A hash of some data used by the chain.
 pub type Hash = sp_core::H256;
 
+/// Time of the chain, in milliseconds since the unix epoch.
+pub type Moment = u64;
+
 /// Digest item type.
 pub type DigestItem = generic::DigestItem<Hash>;
 
@@ -247,7 +255,7 @@ parameter_types! {
 
 impl pallet_timestamp::Config for Runtime {
     /// A timestamp: milliseconds since the unix epoch.
-    type Moment = u64;
+    type Moment = Moment;
     type OnTimestampSet = Aura;
     type MinimumPeriod = MinimumPeriod;
     type WeightInfo = ();
@@ -342,9 +350,30 @@ impl pallet_charge_consent::Config for Runtime {
     type Event = Event;
 }
 
+parameter_types! {
+    /// Meter checkpoints of active charge sessions are recorded every minute
+    pub const CheckpointInterval: u64 = 60 * 1000;
+    /// Requests not started by their charger within 5 minutes expire
+    pub const RequestTimeout: u64 = 5 * 60 * 1000;
+}
+
+parameter_types! {
+    /// The calls of the chargers and payment validators pay no fee (see `fees`), up to 100
+    /// calls of an account every 10 minutes
+    pub const MaxFeelessCalls: u32 = 100;
+    pub const FeelessCallsPeriod: BlockNumber = 10 * MINUTES;
+}
+
+impl pallet_feeless_calls::Config for Runtime {
+    type MaxCalls = MaxFeelessCalls;
+    type Period = FeelessCallsPeriod;
+}
+
 impl pallet_charge_session::Config for Runtime {
     type Event = Event;
     type AuthorityId = pallet_charge_session::crypto::ChargerId;
+    type CheckpointInterval = CheckpointInterval;
+    type RequestTimeout = RequestTimeout;
 }
 
 impl pallet_session_payment::Config for Runtime {
@@ -407,7 +436,7 @@ where
             frame_system::CheckEra::<Runtime>::from(generic::Era::mortal(period, current_block)),
             frame_system::CheckNonce::<Runtime>::from(nonce),
             frame_system::CheckWeight::<Runtime>::new(),
-            pallet_transaction_payment::ChargeTransactionPayment::<Runtime>::from(tip),
+            fees::ChargeProtocolFees::from(tip),
         );
         #[cfg_attr(not(feature = "std"), allow(unused_variables))]
         let raw_payload = SignedPayload::new(call, extra)
@@ -446,6 +475,7 @@ construct_runtime!(
         TariffManager: pallet_tariff_manager::{Module, Call, Storage, Event<T>},
         ChargeConsent: pallet_charge_consent::{Module, Call, Storage, Event<T>},
         ChargeSession: pallet_charge_session::{Module, Call, Config<T>, Storage, Event<T>},
+        FeelessCalls: pallet_feeless_calls::{Module, Storage},
         SessionPayment: pallet_session_payment::{Module, Call, Config<T>, Storage, Event<T>},
         DID: pallet_did::{Module, Call, Storage, Event<T>},
         Registrar: pallet_registrar::{Module, Call, Config<T>, Storage, Event<T>}
@@ -470,7 +500,7 @@ pub type SignedExtra = (
     frame_system::CheckEra<Runtime>,
     frame_system::CheckNonce<Runtime>,
     frame_system::CheckWeight<Runtime>,
-    pallet_transaction_payment::ChargeTransactionPayment<Runtime>,
+    fees::ChargeProtocolFees,
 );
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
@@ -655,6 +685,24 @@ impl_runtime_apis! {
         }
     }
 
+    impl charge_session_runtime_api::ChargeSessionApi<Block, AccountId, Moment, Hash> for Runtime {
+        fn sessions_by_user(
+            user: AccountId,
+            page: u32,
+            page_size: u32,
+        ) -> Vec<pallet_charge_session::SessionRecord<AccountId, Moment, Hash>> {
+            ChargeSession::sessions_by_user(&user, page, page_size)
+        }
+
+        fn sessions_by_charger(
+            charger: AccountId,
+            page: u32,
+            page_size: u32,
+        ) -> Vec<pallet_charge_session::SessionRecord<AccountId, Moment, Hash>> {
+            ChargeSession::sessions_by_charger(&charger, page, page_size)
+        }
+    }
+
     #[cfg(feature = "runtime-benchmarks")]
     impl frame_benchmarking::Benchmark<Block> for Runtime {
         fn dispatch_benchmark(